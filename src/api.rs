@@ -0,0 +1,305 @@
+// JSON REST interface to a `Warehouse`, independent of the HTTP library: the server binary turns
+// each request into a call to `handle` (or `read`, for GET) and sends back the returned status
+// and body.
+//
+//   GET    /items                          every stored record with its locations
+//   POST   /items                          store a new item (`NewItem`), returns its locations
+//   DELETE /locations/{r}/{s}/{l}/{z}      remove the record at a location (`?quantity=n` for part of it)
+//   GET    /locations/{r}/{s}/{l}/{z}      the item at a location
+//   POST   /moves                          move a record (`MoveRequest`)
+//   GET    /stock/id/{id}                  stock summary of a product id
+//   GET    /stock/name/{name}              stock summary of a product name
+//   GET    /products/{id}/locations        records of a product with their locations
+//   GET    /expiring?date=YYYY-MM-DD       expired and soon to expire fragile records (default: today)
+//
+// Requests carry the credentials of a `UserDirectory` account (`Authorization: Basic`, see
+// `authenticate`) and are handled for that user. Mutating routes check the user's permission
+// first and answer 403 if it is missing.
+
+use crate::warehouse::item::{HandlingAttribute, Item};
+use crate::warehouse::storage::StorageBackend;
+use crate::warehouse::users::{Permission, User, UserDirectory};
+use crate::warehouse::warehouse::{Location, Warehouse};
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    fn json<T: Serialize>(status: u16, value: &T) -> Response {
+        match serde_json::to_string(value) {
+            Ok(body) => Response { status, body },
+            Err(e) => Response::error(500, &e.to_string()),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response { status, body: json!({ "error": message }).to_string() }
+    }
+
+    // 2xx status
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+// Body of POST /items; the same fields as the JSON `Item`, without the ones the warehouse assigns
+#[derive(Deserialize)]
+pub struct NewItem {
+    pub product_id: u32,
+    pub name: String,
+    pub quantity: u32,
+    #[serde(default)]
+    pub attributes: Vec<HandlingAttribute>,
+    #[serde(default)]
+    pub expiry_date: Option<NaiveDate>,
+    #[serde(default)]
+    pub lot: Option<String>,
+    #[serde(default)]
+    pub serials: Vec<String>,
+    #[serde(default)]
+    pub unit_cost: f64,
+}
+
+// Body of POST /moves
+#[derive(Deserialize)]
+pub struct MoveRequest {
+    pub record_id: u64,
+    pub to: Vec<Location>,
+}
+
+#[derive(Serialize)]
+struct StoredRecord {
+    item: Item,
+    locations: Vec<Location>,
+}
+
+#[derive(Serialize)]
+struct ExpiringRecord {
+    item: Item,
+    status: String,
+    locations: Vec<Location>,
+}
+
+// The account named by an `Authorization: Basic <base64 of user:password>` header, or a 401
+pub fn authenticate(users: &UserDirectory, authorization: Option<&str>) -> Result<User, Response> {
+    let credentials = authorization
+        .and_then(|value| value.trim().strip_prefix("Basic "))
+        .and_then(|encoded| decode_base64(encoded.trim()))
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .ok_or(Response::error(401, "Indique as credenciais (Authorization: Basic)."))?;
+    let (username, password) = credentials.split_once(':').ok_or(Response::error(401, "Credenciais inválidas."))?;
+    users.authenticate(username, password).map_err(|e| Response::error(401, &e))
+}
+
+// A request by `user`. Changes are made with the warehouse signed in as `user`, only while they
+// run, so they are checked against the user's role and the movements they record carry the name.
+pub fn handle<S: StorageBackend>(warehouse: &mut Warehouse<S>, user: &User, method: &str, url: &str, body: &str) -> Response {
+    if method == "GET" {
+        return read(warehouse, url);
+    }
+    warehouse.sign_in(user);
+    let response = change(warehouse, method, url, body);
+    warehouse.sign_out();
+    response
+}
+
+// A GET request, which every user may make; it only needs a shared borrow of the warehouse
+pub fn read<S: StorageBackend>(warehouse: &Warehouse<S>, url: &str) -> Response {
+    let (path, query, segments) = route(url);
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    let result = match segments.as_slice() {
+        ["items"] => Ok(list_items(warehouse)),
+        ["locations", r, s, l, z] => parse_location(r, s, l, z).and_then(|loc| {
+            warehouse.item_at(&loc).map(|itm| Response::json(200, itm))
+                .ok_or((404, "Nenhum item encontrado nesta localização.".to_string()))
+        }),
+        ["stock", "id", id] => parse_number::<u32>(id, "ID")
+            .map(|id| Response::json(200, &warehouse.search_by_id(id))),
+        ["stock", "name", name] => Ok(Response::json(200, &warehouse.search_by_name(name))),
+        ["products", id, "locations"] => parse_number::<u32>(id, "ID")
+            .map(|id| Response::json(200, &records(warehouse.search_locations_by_id(id)))),
+        ["expiring"] => expiring(warehouse, query),
+        segments => Err(unmatched("GET", path, segments)),
+    };
+    result.unwrap_or_else(|(status, message)| Response::error(status, &message))
+}
+
+fn change<S: StorageBackend>(warehouse: &mut Warehouse<S>, method: &str, url: &str, body: &str) -> Response {
+    let (path, query, segments) = route(url);
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    let result = match (method, segments.as_slice()) {
+        ("POST", ["items"]) => authorize(warehouse, Permission::Store).and_then(|_| add_item(warehouse, body)),
+        ("DELETE", ["locations", r, s, l, z]) => authorize(warehouse, Permission::Remove)
+            .and_then(|_| parse_location(r, s, l, z))
+            .and_then(|loc| remove_item(warehouse, &loc, query)),
+        ("POST", ["moves"]) => authorize(warehouse, Permission::Move).and_then(|_| move_record(warehouse, body)),
+        (_, segments) => Err(unmatched(method, path, segments)),
+    };
+    result.unwrap_or_else(|(status, message)| Response::error(status, &message))
+}
+
+// The path, the query and the decoded path segments of a request URL
+fn route(url: &str) -> (&str, &str, Vec<String>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    (path, query, path.split('/').filter(|s| !s.is_empty()).map(decode).collect())
+}
+
+// A known resource asked for with a method it does not support, or an unknown one
+fn unmatched(method: &str, path: &str, segments: &[&str]) -> (u16, String) {
+    match segments {
+        ["items"] | ["locations", ..] | ["moves"] | ["stock", ..] | ["products", ..] | ["expiring"] => {
+            (405, format!("Método {} não suportado neste recurso.", method))
+        },
+        _ => (404, format!("Recurso não encontrado: {}", path)),
+    }
+}
+
+type ApiResult = Result<Response, (u16, String)>;
+
+fn authorize<S: StorageBackend>(warehouse: &Warehouse<S>, permission: Permission) -> Result<(), (u16, String)> {
+    warehouse.authorize(permission).map_err(|e| (403, e))
+}
+
+fn list_items<S: StorageBackend>(warehouse: &Warehouse<S>) -> Response {
+    let mut grouped = warehouse.grouped_items();
+    grouped.sort_by(|(_, l1), (_, l2)| l1.cmp(l2));
+    Response::json(200, &records(grouped))
+}
+
+fn records(grouped: Vec<(Item, Vec<Location>)>) -> Vec<StoredRecord> {
+    grouped.into_iter().map(|(item, locations)| StoredRecord { item, locations }).collect()
+}
+
+fn add_item<S: StorageBackend>(warehouse: &mut Warehouse<S>, body: &str) -> ApiResult {
+    let new: NewItem = parse_body(body)?;
+    if new.name.trim().is_empty() {
+        return Err((400, "O nome do item não pode estar vazio.".to_string()));
+    }
+    let mut item = Item::new(new.product_id, new.name.trim().to_string(), new.quantity, new.attributes, new.expiry_date);
+    item.lot = new.lot;
+    item.serials = new.serials;
+    item.unit_cost = new.unit_cost;
+    let locations = warehouse.add_item(item).map_err(|e| (422, e))?;
+    Ok(Response::json(201, &json!({ "locations": locations })))
+}
+
+fn remove_item<S: StorageBackend>(warehouse: &mut Warehouse<S>, location: &Location, query: &str) -> ApiResult {
+    let status = if warehouse.item_at(location).is_some() { 422 } else { 404 };
+    match query_param(query, "quantity") {
+        Some(quantity) => {
+            let quantity = parse_number::<u32>(&quantity, "Quantidade")?;
+            let removed = warehouse.remove_quantity(location, quantity).map_err(|e| (status, e))?;
+            Ok(Response::json(200, &removed))
+        },
+        None => {
+            let removed = warehouse.remove_item(location).map_err(|e| (status, e))?;
+            Ok(Response::json(200, &json!({ "removed": removed.first(), "locations": removed.len() })))
+        },
+    }
+}
+
+fn move_record<S: StorageBackend>(warehouse: &mut Warehouse<S>, body: &str) -> ApiResult {
+    let request: MoveRequest = parse_body(body)?;
+    if warehouse.record_locations(request.record_id).is_empty() {
+        return Err((404, "Registo não encontrado no armazém.".to_string()));
+    }
+    warehouse.move_record(request.record_id, request.to.clone()).map_err(|e| (422, e))?;
+    Ok(Response::json(200, &json!({ "record_id": request.record_id, "locations": request.to })))
+}
+
+fn expiring<S: StorageBackend>(warehouse: &Warehouse<S>, query: &str) -> ApiResult {
+    let date = match query_param(query, "date") {
+        Some(date) => NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+            .map_err(|_| (400, format!("Data inválida: {} (use YYYY-MM-DD)", date)))?,
+        None => Local::now().date_naive(),
+    };
+    let records: Vec<ExpiringRecord> = warehouse.find_expiring_items_by_date(date)
+        .into_iter()
+        .map(|(item, status, locations)| ExpiringRecord { item, status, locations })
+        .collect();
+    Ok(Response::json(200, &records))
+}
+
+fn parse_body<T: for<'de> Deserialize<'de>>(body: &str) -> Result<T, (u16, String)> {
+    serde_json::from_str(body).map_err(|e| (400, format!("JSON inválido: {}", e)))
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str, what: &str) -> Result<T, (u16, String)> {
+    text.parse::<T>().map_err(|_| (400, format!("{} inválido: {}", what, text)))
+}
+
+fn parse_location(row: &str, shelf: &str, level: &str, zone: &str) -> Result<Location, (u16, String)> {
+    Ok(Location {
+        row: parse_number(row, "Fileira")?,
+        shelf: parse_number(shelf, "Prateleira")?,
+        level: parse_number(level, "Nível")?,
+        zone: parse_number(zone, "Zona")?,
+    })
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| decode(value))
+}
+
+// Standard base64 with padding, as used by Basic credentials; None if malformed
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let bytes = text.as_bytes();
+    if !bytes.len().is_multiple_of(4) {
+        return None;
+    }
+    let padding = bytes.iter().rev().take_while(|b| **b == b'=').count();
+    if padding > 2 {
+        return None;
+    }
+    let mut decoded = Vec::with_capacity(bytes.len() / 4 * 3);
+    let mut buffer = 0u32;
+    for (i, byte) in bytes[..bytes.len() - padding].iter().enumerate() {
+        buffer = (buffer << 6) | ALPHABET.iter().position(|a| a == byte)? as u32;
+        if i % 4 == 3 {
+            decoded.extend_from_slice(&buffer.to_be_bytes()[1..]);
+            buffer = 0;
+        }
+    }
+    match padding {
+        1 => decoded.extend_from_slice(&(buffer << 6).to_be_bytes()[1..3]),
+        2 => decoded.push((buffer << 12).to_be_bytes()[1]),
+        _ => {},
+    }
+    Some(decoded)
+}
+
+// Percent-decoding for path segments and query values ("Caixa%20A" -> "Caixa A")
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%' && i + 2 < bytes.len())
+            .then(|| std::str::from_utf8(&bytes[i + 1..i + 3]).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,382 @@
+// Serves the REST API in `rust_projeto::api` on localhost.
+//
+//   servidor [endereço] [armazem.json] [células]
+//
+// The address defaults to 127.0.0.1:8080. With a JSON snapshot (as exported by the desktop
+// app) the warehouse is loaded from it and written back after every successful change.
+// With a cell file the stock is kept in it instead of in memory: a `FileStorage`, or for a `.db`
+// file (built with the `sqlite` feature) a `SqliteStorage`. A new cell file starts with the stock
+// of the snapshot. A database keeps the whole warehouse, so the snapshot then only fills a new
+// database and is not written back.
+//
+// Every request, and every events client, authenticates with HTTP Basic credentials of an
+// account in the desktop app's user file and acts with that account's role. The file is
+// utilizadores.json in the working directory unless ARMAZEM_UTILIZADORES names another one; it
+// is read once at startup, so the server has to be restarted for account changes to apply.
+//
+// Requests are served by several worker threads sharing one `SharedWarehouse`: GET requests
+// under its read lock, changes under its write lock. A background thread releases expired
+// reservations every minute.
+//
+// Change events are served on the next port (127.0.0.1:8081 by default): a WebSocket at
+// /events first receives a `snapshot` message with every occupied cell and then one message per
+// change (`added`, `removed`, `moved`, `quantity_changed`), as described by `WarehouseEvent`.
+// Each client has its own thread and queue, so a slow or silent client holds up nobody else.
+
+use rust_projeto::api;
+use rust_projeto::warehouse::events::{EventTracker, WarehouseEvent};
+use rust_projeto::warehouse::shared::SharedWarehouse;
+#[cfg(feature = "sqlite")]
+use rust_projeto::warehouse::sqlite::SqliteStorage;
+use rust_projeto::warehouse::storage::{FileStorage, StorageBackend};
+use rust_projeto::warehouse::users::{User, UserDirectory};
+use rust_projeto::warehouse::warehouse::Warehouse;
+use chrono::Local;
+use serde::Serialize;
+use std::fs;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Request, Response, Server};
+use tungstenite::handshake::server::{ErrorResponse, Request as Handshake, Response as HandshakeReply};
+use tungstenite::http::StatusCode;
+use tungstenite::{Message, WebSocket};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+const USERS_FILE: &str = "utilizadores.json";
+const USERS_FILE_VARIABLE: &str = "ARMAZEM_UTILIZADORES";
+const WAREHOUSE_DIMENSIONS: (u32, u32, u32, u32) = (5, 5, 5, 5);
+const WORKERS: usize = 4;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+// How long a WebSocket client's thread waits for a frame from it before sending what is queued
+const EVENT_POLL: Duration = Duration::from_millis(50);
+
+// Sent with every response
+#[derive(Clone)]
+struct Headers {
+    content_type: Header,
+    challenge: Header, // asks for Basic credentials on a 401
+}
+
+// Queue of serialized events for one WebSocket client, emptied by the client's own thread
+type Subscriber = Sender<String>;
+
+// Writes a committed change wherever the warehouse is kept
+type Persist<S> = Arc<dyn Fn(&Warehouse<S>) + Send + Sync>;
+
+fn load(path: Option<&str>) -> Result<Warehouse, String> {
+    match path {
+        Some(path) if fs::metadata(path).is_ok() => {
+            let json = fs::read_to_string(path).map_err(|e| format!("Não foi possível ler {}: {}", path, e))?;
+            Warehouse::from_json(&json)
+        },
+        _ => Ok(Warehouse::new(WAREHOUSE_DIMENSIONS)),
+    }
+}
+
+// The snapshot's warehouse over `storage`, which is filled with the snapshot's stock if empty
+fn on_storage<S: StorageBackend>(warehouse: Warehouse, mut storage: S) -> Result<Warehouse<S>, String> {
+    if storage.is_empty() {
+        storage.begin();
+        for (loc, itm) in warehouse.items() {
+            storage.put(loc.clone(), itm.clone());
+        }
+        storage.commit()?;
+    }
+    Ok(warehouse.with_cells(storage))
+}
+
+fn header(name: &str, value: &str) -> Result<Header, String> {
+    Header::from_bytes(name, value).map_err(|_| format!("Cabeçalho inválido: {}", name))
+}
+
+fn header_value<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request.headers()
+        .iter()
+        .find(|h| h.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|h| h.value.as_str())
+}
+
+// The accounts, and the credentials already checked against them: Argon2 then runs once per
+// client rather than once per request. Only accepted credentials are remembered.
+struct Accounts {
+    users: UserDirectory,
+    verified: Mutex<HashMap<String, User>>,
+}
+
+impl Accounts {
+    fn authenticate(&self, authorization: Option<&str>) -> Result<User, api::Response> {
+        let authorization = authorization.unwrap_or_default();
+        if let Some(user) = lock(&self.verified).get(authorization) {
+            return Ok(user.clone());
+        }
+        let user = api::authenticate(&self.users, Some(authorization))?;
+        lock(&self.verified).insert(authorization.to_string(), user.clone());
+        Ok(user)
+    }
+}
+
+// Queues the events for every client; clients whose thread has ended are dropped
+fn broadcast(subscribers: &mut Vec<Subscriber>, events: &[WarehouseEvent]) {
+    let messages: Vec<String> = events.iter().filter_map(|event| serde_json::to_string(event).ok()).collect();
+    subscribers.retain(|subscriber| messages.iter().all(|json| subscriber.send(json.clone()).is_ok()));
+}
+
+// Change tracking and the clients' queues, always locked while holding the warehouse lock so
+// that each batch of events takes the warehouse as committed, one batch after the other.
+// Nothing here waits on the network.
+struct Hub {
+    tracker: EventTracker,
+    subscribers: Vec<Subscriber>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// The snapshot is written next to the old one and renamed over it, so a crash mid-write leaves
+// the previous snapshot in place
+fn save<S: StorageBackend + Serialize>(warehouse: &Warehouse<S>, path: Option<&str>) {
+    if let Some(path) = path {
+        let temp = format!("{}.tmp", path);
+        let result = warehouse.to_json()
+            .and_then(|json| fs::write(&temp, json).map_err(|e| format!("Não foi possível escrever {}: {}", temp, e)))
+            .and_then(|_| fs::rename(&temp, path).map_err(|e| format!("Não foi possível substituir {}: {}", path, e)));
+        if let Err(e) = result {
+            eprintln!("Erro ao gravar {}: {}", path, e);
+        }
+    }
+}
+
+fn to_snapshot<S: StorageBackend + Serialize>(path: Option<String>) -> Persist<S> {
+    Arc::new(move |warehouse: &Warehouse<S>| save(warehouse, path.as_deref()))
+}
+
+fn serve<S: StorageBackend>(server: &Server, warehouse: &SharedWarehouse<S>, accounts: &Accounts, hub: &Mutex<Hub>, persist: &Persist<S>, headers: &Headers, events_url: &str) {
+    let Headers { content_type, challenge } = headers;
+    while let Ok(mut request) = server.recv() {
+        if request.url() == "/events" {
+            let body = serde_json::json!({ "error": format!("Os eventos são servidos em {}.", events_url) }).to_string();
+            let _ = request.respond(Response::from_string(body).with_status_code(404).with_header(content_type.clone()));
+            continue;
+        }
+
+        let method = request.method().as_str().to_string();
+        let user = accounts.authenticate(header_value(&request, "Authorization"));
+        let mut body = String::new();
+        let response = match (user, request.as_reader().read_to_string(&mut body)) {
+            (Err(response), _) => response,
+            (Ok(_), Ok(_)) if method == "GET" => warehouse.read(|wh| api::read(wh, request.url())),
+            (Ok(user), Ok(_)) => warehouse.write(|wh| api::handle(wh, &user, &method, request.url(), &body))
+                .unwrap_or_else(|e| api::Response { status: 500, body: serde_json::json!({ "error": e }).to_string() }),
+            (Ok(_), Err(e)) => api::Response { status: 400, body: serde_json::json!({ "error": format!("Pedido inválido: {}", e) }).to_string() },
+        };
+        if response.is_success() && method != "GET" {
+            publish(warehouse, hub, persist);
+        }
+        println!("{} {} -> {}", method, request.url(), response.status);
+
+        let mut reply = Response::from_string(response.body)
+            .with_status_code(response.status)
+            .with_header(content_type.clone());
+        if response.status == 401 {
+            reply.add_header(challenge.clone());
+        }
+        if let Err(e) = request.respond(reply) {
+            eprintln!("Erro ao responder: {}", e);
+        }
+    }
+}
+
+// Persists the warehouse and queues the events of what changed since the last call. Runs once a
+// change is committed; the hub lock keeps two of them from writing at once.
+fn publish<S: StorageBackend>(warehouse: &SharedWarehouse<S>, hub: &Mutex<Hub>, persist: &Persist<S>) {
+    warehouse.read(|wh| {
+        let mut hub = lock(hub);
+        persist(wh);
+        let events = hub.tracker.changes(wh);
+        broadcast(&mut hub.subscribers, &events);
+    });
+}
+
+// Accepts WebSocket clients, each served by a thread of its own
+fn listen_events<S>(listener: TcpListener, warehouse: SharedWarehouse<S>, accounts: Arc<Accounts>, hub: Arc<Mutex<Hub>>)
+where
+    S: StorageBackend + Send + Sync + 'static,
+{
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Erro na ligação WebSocket: {}", e);
+                continue;
+            },
+        };
+        let (warehouse, accounts, hub) = (warehouse.clone(), accounts.clone(), hub.clone());
+        thread::spawn(move || {
+            if let Err(e) = stream_events(stream, &warehouse, &accounts, &hub) {
+                eprintln!("Erro na ligação WebSocket: {}", e);
+            }
+        });
+    }
+}
+
+// Completes the handshake for /events and registers the client's queue, starting with the
+// snapshot. Both happen under the warehouse lock, so no change falls between the two.
+#[allow(clippy::result_large_err)] // the handshake callback's error type is set by tungstenite
+fn stream_events<S: StorageBackend>(stream: TcpStream, warehouse: &SharedWarehouse<S>, accounts: &Accounts, hub: &Mutex<Hub>) -> Result<(), String> {
+    let mut socket = tungstenite::accept_hdr(stream, |request: &Handshake, reply: HandshakeReply| {
+        let refuse = |status: StatusCode, message: String| {
+            let mut refusal = ErrorResponse::new(Some(message));
+            *refusal.status_mut() = status;
+            refusal
+        };
+        if request.uri().path() != "/events" {
+            return Err(refuse(StatusCode::NOT_FOUND, format!("Recurso não encontrado: {}", request.uri().path())));
+        }
+        let authorization = request.headers().get("Authorization").and_then(|value| value.to_str().ok());
+        match accounts.authenticate(authorization) {
+            Ok(_) => Ok(reply),
+            Err(response) => Err(refuse(StatusCode::from_u16(response.status).unwrap_or(StatusCode::UNAUTHORIZED), response.body)),
+        }
+    }).map_err(|e| e.to_string())?;
+    socket.get_ref().set_read_timeout(Some(EVENT_POLL)).map_err(|e| e.to_string())?;
+
+    let (sender, queue) = mpsc::channel();
+    warehouse.read(|wh| {
+        let mut hub = lock(hub);
+        let snapshot = serde_json::to_string(&EventTracker::snapshot(wh)).map_err(|e| e.to_string())?;
+        let _ = sender.send(snapshot);
+        hub.subscribers.push(sender);
+        println!("GET /events -> 101 ({} cliente(s))", hub.subscribers.len());
+        Ok::<(), String>(())
+    })?;
+
+    send_events(&mut socket, &queue)
+}
+
+// A client that closed the connection is done, not an error
+fn closed(e: tungstenite::Error) -> Result<(), String> {
+    match e {
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => Ok(()),
+        e => Err(e.to_string()),
+    }
+}
+
+// Sends whatever is queued, then waits briefly for a frame from the client. Reading is what
+// answers pings and completes a close started by the client; other messages are ignored.
+fn send_events(socket: &mut WebSocket<TcpStream>, queue: &Receiver<String>) -> Result<(), String> {
+    loop {
+        loop {
+            let sent = match queue.try_recv() {
+                Ok(json) => socket.write(Message::text(json)),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return socket.close(None).or_else(closed),
+            };
+            if let Err(e) = sent {
+                return closed(e);
+            }
+        }
+        if let Err(e) = socket.flush() {
+            return closed(e);
+        }
+        match socket.read() {
+            Ok(_) => {},
+            Err(tungstenite::Error::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {},
+            Err(e) => return closed(e),
+        }
+    }
+}
+
+// Events are served on the port after the one of the REST API
+fn events_address(address: &str) -> Result<SocketAddr, String> {
+    let mut events = address.to_socket_addrs().ok()
+        .and_then(|mut addresses| addresses.next())
+        .ok_or(format!("Endereço inválido: {}", address))?;
+    let port = events.port().checked_add(1).ok_or(format!("Não há porta para os eventos depois de {}.", address))?;
+    events.set_port(port);
+    Ok(events)
+}
+
+// Drops reservations past their expiry date so the stock becomes available again
+fn sweep_reservations<S: StorageBackend>(warehouse: &SharedWarehouse<S>, hub: &Mutex<Hub>, persist: &Persist<S>) {
+    loop {
+        thread::sleep(SWEEP_INTERVAL);
+        let today = Local::now().date_naive();
+        match warehouse.write(|wh| wh.purge_expired_reservations(today).len()) {
+            Ok(0) => {},
+            Ok(expired) => {
+                println!("{} reserva(s) expirada(s) libertada(s).", expired);
+                publish(warehouse, hub, persist);
+            },
+            Err(e) => eprintln!("Erro ao libertar reservas expiradas: {}", e),
+        }
+    }
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let address = args.first().cloned().unwrap_or(DEFAULT_ADDRESS.to_string());
+    let snapshot = args.get(1).cloned();
+
+    let users_file = std::env::var(USERS_FILE_VARIABLE).unwrap_or(USERS_FILE.to_string());
+    let users = UserDirectory::load(&users_file)?;
+    if users.is_empty() {
+        return Err(format!("Não há contas em {}; crie um administrador na aplicação antes de iniciar o servidor.", users_file));
+    }
+    let accounts = Accounts { users, verified: Mutex::new(HashMap::new()) };
+    let warehouse = load(snapshot.as_deref())?;
+    match args.get(2) {
+        None => run(warehouse, accounts, address, to_snapshot(snapshot)),
+        #[cfg(feature = "sqlite")]
+        Some(cells) if cells.ends_with(".db") => {
+            let persist: Persist<SqliteStorage> = Arc::new(|wh: &Warehouse<SqliteStorage>| {
+                if let Err(e) = wh.storage.save_state(wh) {
+                    eprintln!("Erro ao gravar a base de dados: {}", e);
+                }
+            });
+            run(SqliteStorage::open_warehouse(cells, &warehouse)?, accounts, address, persist)
+        },
+        Some(cells) => run(on_storage(warehouse, FileStorage::open(cells)?)?, accounts, address, to_snapshot(snapshot)),
+    }
+}
+
+fn run<S>(warehouse: Warehouse<S>, accounts: Accounts, address: String, persist: Persist<S>) -> Result<(), String>
+where
+    S: StorageBackend + Send + Sync + 'static,
+{
+    let warehouse = SharedWarehouse::new(warehouse);
+    let accounts = Arc::new(accounts);
+    let hub = Arc::new(Mutex::new(Hub { tracker: warehouse.read(EventTracker::new), subscribers: Vec::new() }));
+    let server = Arc::new(Server::http(&address).map_err(|e| format!("Não foi possível escutar em {}: {}", address, e))?);
+    let events = events_address(&address)?;
+    let listener = TcpListener::bind(events).map_err(|e| format!("Não foi possível escutar em {}: {}", events, e))?;
+    let events_url = format!("ws://{}/events", events);
+    let headers = Headers {
+        content_type: header("Content-Type", "application/json; charset=utf-8")?,
+        challenge: header("WWW-Authenticate", "Basic realm=\"armazem\", charset=\"UTF-8\"")?,
+    };
+    println!("Servidor do armazém em http://{} (eventos em {})", address, events_url);
+
+    {
+        let (warehouse, hub, persist) = (warehouse.clone(), hub.clone(), persist.clone());
+        thread::spawn(move || sweep_reservations(&warehouse, &hub, &persist));
+    }
+    {
+        let (warehouse, accounts, hub) = (warehouse.clone(), accounts.clone(), hub.clone());
+        thread::spawn(move || listen_events(listener, warehouse, accounts, hub));
+    }
+    let workers: Vec<_> = (0..WORKERS).map(|_| {
+        let (server, warehouse, accounts, hub, persist, headers, events_url) =
+            (server.clone(), warehouse.clone(), accounts.clone(), hub.clone(), persist.clone(), headers.clone(), events_url.clone());
+        thread::spawn(move || serve(&server, &warehouse, &accounts, &hub, &persist, &headers, &events_url))
+    }).collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use rust_projeto::warehouse::item::Item;
+#[cfg(feature = "sqlite")]
+use rust_projeto::warehouse::sqlite::SqliteStorage;
+use rust_projeto::warehouse::storage::{MemoryStorage, StorageBackend};
+use rust_projeto::warehouse::warehouse::Location;
+use serde::{Serialize, Serializer};
+
+// The cells of the warehouse on screen: in memory, or in the SQLite database opened in the
+// import/export panel
+pub enum Cells {
+    Memory(MemoryStorage),
+    #[cfg(feature = "sqlite")]
+    Database(Box<SqliteStorage>),
+}
+
+impl Default for Cells {
+    fn default() -> Self {
+        Cells::Memory(MemoryStorage::default())
+    }
+}
+
+impl Cells {
+    // The cells alone, closing the database if one is open
+    pub fn into_memory(self) -> MemoryStorage {
+        match self {
+            Cells::Memory(cells) => cells,
+            #[cfg(feature = "sqlite")]
+            Cells::Database(storage) => storage.into_cells(),
+        }
+    }
+
+    fn backend(&self) -> &dyn StorageBackend {
+        match self {
+            Cells::Memory(cells) => cells,
+            #[cfg(feature = "sqlite")]
+            Cells::Database(storage) => storage.as_ref(),
+        }
+    }
+
+    fn backend_mut(&mut self) -> &mut dyn StorageBackend {
+        match self {
+            Cells::Memory(cells) => cells,
+            #[cfg(feature = "sqlite")]
+            Cells::Database(storage) => storage.as_mut(),
+        }
+    }
+}
+
+impl Serialize for Cells {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        match self {
+            Cells::Memory(cells) => cells.serialize(serializer),
+            #[cfg(feature = "sqlite")]
+            Cells::Database(storage) => storage.serialize(serializer),
+        }
+    }
+}
+
+impl StorageBackend for Cells {
+    fn get(&self, loc: &Location) -> Option<&Item> {
+        self.backend().get(loc)
+    }
+
+    fn get_mut(&mut self, loc: &Location) -> Option<&mut Item> {
+        self.backend_mut().get_mut(loc)
+    }
+
+    fn put(&mut self, loc: Location, item: Item) -> Option<Item> {
+        self.backend_mut().put(loc, item)
+    }
+
+    fn remove(&mut self, loc: &Location) -> Option<Item> {
+        self.backend_mut().remove(loc)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Location, &Item)> + '_> {
+        self.backend().iter()
+    }
+
+    fn usage(&self, loc: &Location) -> u32 {
+        self.backend().usage(loc)
+    }
+
+    fn record_use(&mut self, loc: &Location) {
+        self.backend_mut().record_use(loc)
+    }
+
+    fn begin(&mut self) {
+        self.backend_mut().begin()
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        self.backend_mut().commit()
+    }
+
+    fn rollback(&mut self) {
+        self.backend_mut().rollback()
+    }
+
+    fn savepoint(&self) -> usize {
+        self.backend().savepoint()
+    }
+
+    fn rollback_to(&mut self, savepoint: usize) {
+        self.backend_mut().rollback_to(savepoint)
+    }
+
+    fn len(&self) -> usize {
+        self.backend().len()
+    }
+}
@@ -1,19 +1,55 @@
-mod warehouse;
+use rust_projeto::warehouse;
+mod cells;
 mod utils;
 
 use eframe::egui;
-use warehouse::item::{Item, ItemQuality};
+use warehouse::climate::{ClimateScope, ClimateZone};
+use warehouse::cyclecount::{CountStatus, CycleCountManager};
+use warehouse::hazard::{HazardClass, SegregationGroup, Separation};
+use warehouse::inbound::InboundManager;
+use warehouse::inventory_csv::{export_inventory, import_inventory};
+use warehouse::item::{HandlingAttribute, Item};
+use warehouse::movement::AdjustmentReason;
+use warehouse::order::{pick_list, OrderLine, OrderManager, OrderStatus};
+use warehouse::recall::recall_report;
+use warehouse::replenishment::{ReplenishmentRules, ReplenishmentScope, ReplenishmentSuggestion};
+use warehouse::uom::PackagingLevel;
+use warehouse::users::{Permission, Role, User, UserDirectory};
+use warehouse::valuation::{export_csv, subtotals, valuation_report, ValuationMethod};
+use warehouse::slotting::{abc_analysis, slotting_advice, SlotMove};
+use warehouse::sites::{Site, SiteRegistry, TransferStatus};
+#[cfg(feature = "sqlite")]
+use warehouse::sqlite::SqliteStorage;
 use warehouse::warehouse::{Location, Warehouse};
-use utils::{parse_date, validate_location_input};
+use cells::Cells;
+use utils::{parse_date, today, validate_location_input};
 
-use std::collections::HashMap;
+use chrono::Duration;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::collections::hash_map::DefaultHasher;
 
 const WAREHOUSE_DIMENSIONS: (u32, u32, u32, u32) = (5, 5, 5, 5);
+const CLIMATE_ROWS: [(u32, ClimateZone); 2] = [(3, ClimateZone::Chilled), (4, ClimateZone::Frozen)];
+const SECURE_ROWS: [u32; 1] = [0];
+const ORDER_RESERVATION_DAYS: i64 = 7;
+const USERS_FILE: &str = "utilizadores.json";
+
+// The other sites and the transfers between them are saved next to the JSON export of the active
+// one: armazem.json -> armazem.sites.json
+fn sites_path(snapshot: &str) -> String {
+    let stem = snapshot.strip_suffix(".json").unwrap_or(snapshot);
+    format!("{}.sites.json", stem)
+}
+const MAIN_SITE: &str = "Armazém Principal";
 
 struct App {
-    warehouse: Warehouse,
+    warehouse: Warehouse<Cells>,
+    orders: OrderManager,
+    inbound: InboundManager,
+    cycle_counts: CycleCountManager,
+    replenishment: ReplenishmentRules,
+    replenishment_alerts: HashSet<(u32, ReplenishmentScope)>,
     current_action: String,
     add_item_form: AddItemForm,
     search_by_id_form: SearchByIdForm,
@@ -24,8 +60,30 @@ struct App {
     show_name_popup: bool,
     search_location_by_id_form: SearchLocationByIdForm,
     check_expiring_form: CheckExpiringForm,
+    segregation_rule_form: SegregationRuleForm,
+    climate_form: ClimateForm,
+    reservation_form: ReservationForm,
+    adjust_quantity_form: AdjustQuantityForm,
+    order_form: OrderForm,
+    inbound_form: InboundForm,
+    cycle_count_form: CycleCountForm,
+    replenishment_form: ReplenishmentForm,
+    traceability_form: TraceabilityForm,
+    packaging_form: PackagingForm,
+    valuation_form: ValuationForm,
+    csv_form: CsvForm,
     next_id: u32,
     grid_scale: f32,
+    route: Vec<Location>,
+    route_form: RouteForm,
+    slotting_moves: Vec<SlotMove>,
+    users: UserDirectory,
+    users_error: Option<String>, // the accounts file could not be read; nobody can sign in
+    login_form: LoginForm,
+    user_form: UserForm,
+    sites: SiteRegistry,
+    site_form: SiteForm,
+    stamped_logs: usize, // log lines already prefixed with the user who caused them
 
     // New mapping from name to ID
     name_to_id: HashMap<String, u32>,
@@ -34,10 +92,45 @@ struct App {
 struct AddItemForm {
     name: String,
     quantity: String,
-    quality: ItemQuality,
+    packaging_level: PackagingLevel,
+    fragile: bool,
+    oversized: bool,
+    hazardous: bool,
+    hazard_class: HazardClass,
+    food: bool,
+    climate: ClimateZone,
+    high_value: bool,
+    stack_limited: bool,
     data_validade: String, // DD-MM-YYYY
+    nivel_minimo: String,
     nivel_maximo: String,
     required_zones: String,
+    required_levels: String,
+    required_shelves: String,
+    stack_limit: String,
+    lot: String,
+    serials: String, // comma separated
+    unit_cost: String,
+}
+
+struct PackagingForm {
+    name: String,
+    base_unit: String,
+    units_per_case: String,
+    cases_per_pallet: String,
+}
+
+struct ValuationForm {
+    method: ValuationMethod,
+    path: String,
+}
+
+struct CsvForm {
+    export_path: String,
+    import_path: String,
+    json_path: String,
+    #[cfg(feature = "sqlite")]
+    db_path: String,
 }
 
 struct SearchByIdForm {
@@ -53,6 +146,8 @@ struct RemoveItemForm {
     shelf: String,
     level: String,
     zone: String,
+    quantity: String, // empty removes the whole record
+    packaging_level: PackagingLevel,
 }
 
 struct SearchLocationByIdForm {
@@ -63,18 +158,147 @@ struct CheckExpiringForm {
     date: String, // DD-MM-YYYY
 }
 
+struct SegregationRuleForm {
+    first: SegregationGroup,
+    second: SegregationGroup,
+    kind: &'static str, // "Zonas", "Prateleiras" or "Fileiras"
+    distance: String,
+}
+
+struct ReservationForm {
+    row: String,
+    shelf: String,
+    level: String,
+    zone: String,
+    quantity: String,
+    order_ref: String,
+    expires: String, // DD-MM-YYYY
+}
+
+struct AdjustQuantityForm {
+    row: String,
+    shelf: String,
+    level: String,
+    zone: String,
+    quantity: String,
+    reason: AdjustmentReason,
+}
+
+struct OrderForm {
+    customer: String,
+    lines: String, // "ID:QTD, ID:QTD"
+    wave_size: String,
+}
+
+struct InboundForm {
+    po_reference: String,
+    supplier: String,
+    asn_reference: String,
+    po_id: String,
+    line_number: String,
+    received: String,
+    damaged: String,
+}
+
+struct CycleCountForm {
+    row: String,
+    shelf: String,
+    num_id: String,
+    task_id: String,
+    counted: String,
+    reason: AdjustmentReason,
+}
+
+struct ReplenishmentForm {
+    num_id: String,
+    pick_zone: String,
+    min: String,
+    max: String,
+}
+
+struct TraceabilityForm {
+    lot: String,
+    serial: String,
+}
+
+struct RouteForm {
+    wave_id: String,
+    pick_lines: String, // "ID:QTD, ID:QTD"
+}
+
+struct LoginForm {
+    username: String,
+    password: String,
+    message: String, // shown on the login screen, where the logs are hidden
+}
+
+struct SiteForm {
+    name: String,
+    rows: String,
+    shelves: String,
+    levels: String,
+    zones: String,
+    transfer_to: String,
+    transfer_id: String,
+    transfer_quantity: String,
+}
+
+struct UserForm {
+    username: String,
+    password: String,
+    role: Role,
+}
+
+struct ClimateForm {
+    scope: &'static str, // "Fileira", "Prateleira" or "Nível"
+    row: String,
+    shelf: String,
+    level: String,
+    zone: ClimateZone,
+}
+
 impl App {
     fn new(max_dimensions: (u32,u32,u32,u32)) -> Self {
+        let mut warehouse = Warehouse::with_storage(Cells::default(), max_dimensions);
+        for (row, zone) in CLIMATE_ROWS {
+            warehouse.climate.assign(ClimateScope::Row(row), zone);
+        }
+        warehouse.secure_rows.extend(SECURE_ROWS);
+        let (users, users_error) = match UserDirectory::load(USERS_FILE) {
+            Ok(users) => (users, None),
+            Err(err) => (UserDirectory::default(), Some(err)),
+        };
+
         Self {
-            warehouse: Warehouse::new(max_dimensions),
+            warehouse,
+            orders: OrderManager::default(),
+            inbound: InboundManager::default(),
+            cycle_counts: CycleCountManager::default(),
+            replenishment: ReplenishmentRules::default(),
+            replenishment_alerts: HashSet::new(),
             current_action: "Bem-vindo ao Gestor de Armazém!".to_string(),
             add_item_form: AddItemForm {
                 name: "".to_string(),
                 quantity: "".to_string(),
-                quality: ItemQuality::Normal,
+                packaging_level: PackagingLevel::Each,
+                fragile: false,
+                oversized: false,
+                hazardous: false,
+                hazard_class: HazardClass::Flammable,
+                food: false,
+                climate: ClimateZone::Ambient,
+                high_value: false,
+                stack_limited: false,
                 data_validade: "".to_string(),
+                nivel_minimo: "".to_string(),
                 nivel_maximo: "".to_string(),
                 required_zones: "".to_string(),
+                required_levels: "".to_string(),
+                required_shelves: "".to_string(),
+                stack_limit: "".to_string(),
+                lot: "".to_string(),
+                serials: "".to_string(),
+                unit_cost: "".to_string(),
             },
             search_by_id_form: SearchByIdForm { id: "".to_string() },
             search_by_name_form: SearchByNameForm { name: "".to_string() },
@@ -83,14 +307,122 @@ impl App {
                 shelf: "".to_string(),
                 level: "".to_string(),
                 zone: "".to_string(),
+                quantity: "".to_string(),
+                packaging_level: PackagingLevel::Each,
             },
             search_results: "".to_string(),
             logs: Vec::new(),
             show_name_popup: false,
             search_location_by_id_form: SearchLocationByIdForm { id: "".to_string() },
             check_expiring_form: CheckExpiringForm { date: "".to_string() },
+            segregation_rule_form: SegregationRuleForm {
+                first: SegregationGroup::Food,
+                second: SegregationGroup::Hazard(HazardClass::Flammable),
+                kind: "Prateleiras",
+                distance: "".to_string(),
+            },
+            climate_form: ClimateForm {
+                scope: "Fileira",
+                row: "".to_string(),
+                shelf: "".to_string(),
+                level: "".to_string(),
+                zone: ClimateZone::Chilled,
+            },
+            reservation_form: ReservationForm {
+                row: "".to_string(),
+                shelf: "".to_string(),
+                level: "".to_string(),
+                zone: "".to_string(),
+                quantity: "".to_string(),
+                order_ref: "".to_string(),
+                expires: "".to_string(),
+            },
+            adjust_quantity_form: AdjustQuantityForm {
+                row: "".to_string(),
+                shelf: "".to_string(),
+                level: "".to_string(),
+                zone: "".to_string(),
+                quantity: "".to_string(),
+                reason: AdjustmentReason::Correction,
+            },
+            order_form: OrderForm {
+                customer: "".to_string(),
+                lines: "".to_string(),
+                wave_size: "".to_string(),
+            },
+            inbound_form: InboundForm {
+                po_reference: "".to_string(),
+                supplier: "".to_string(),
+                asn_reference: "".to_string(),
+                po_id: "".to_string(),
+                line_number: "".to_string(),
+                received: "".to_string(),
+                damaged: "".to_string(),
+            },
+            cycle_count_form: CycleCountForm {
+                row: "".to_string(),
+                shelf: "".to_string(),
+                num_id: "".to_string(),
+                task_id: "".to_string(),
+                counted: "".to_string(),
+                reason: AdjustmentReason::CycleCount,
+            },
+            csv_form: CsvForm {
+                export_path: "inventario.csv".to_string(),
+                import_path: "".to_string(),
+                json_path: "armazem.json".to_string(),
+                #[cfg(feature = "sqlite")]
+                db_path: "armazem.db".to_string(),
+            },
+            valuation_form: ValuationForm {
+                method: ValuationMethod::Fifo,
+                path: "valorizacao.csv".to_string(),
+            },
+            packaging_form: PackagingForm {
+                name: "".to_string(),
+                base_unit: "un".to_string(),
+                units_per_case: "".to_string(),
+                cases_per_pallet: "".to_string(),
+            },
+            traceability_form: TraceabilityForm {
+                lot: "".to_string(),
+                serial: "".to_string(),
+            },
+            replenishment_form: ReplenishmentForm {
+                num_id: "".to_string(),
+                pick_zone: "".to_string(),
+                min: "".to_string(),
+                max: "".to_string(),
+            },
             next_id: 1,
             grid_scale: 1.0,
+            route: Vec::new(),
+            route_form: RouteForm { wave_id: "".to_string(), pick_lines: "".to_string() },
+            slotting_moves: Vec::new(),
+            users,
+            users_error,
+            login_form: LoginForm {
+                username: "".to_string(),
+                password: "".to_string(),
+                message: "".to_string(),
+            },
+            sites: SiteRegistry::new(MAIN_SITE),
+            site_form: SiteForm {
+                name: "".to_string(),
+                rows: "".to_string(),
+                shelves: "".to_string(),
+                levels: "".to_string(),
+                zones: "".to_string(),
+                transfer_to: "".to_string(),
+                transfer_id: "".to_string(),
+                transfer_quantity: "".to_string(),
+            },
+            user_form: UserForm {
+                username: "".to_string(),
+                password: "".to_string(),
+                role: Role::Operator,
+            },
+            stamped_logs: 0,
             name_to_id: HashMap::new(),
         }
     }
@@ -98,9 +430,44 @@ impl App {
 
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if self.warehouse.session().is_none() {
+            egui::CentralPanel::default().show(ctx, |ui| self.render_login(ui));
+            return;
+        }
+
+        let expired = self.warehouse.purge_expired_reservations(today());
+        for reservation in &expired {
+            self.logs.push(format!("Reserva {} ({}) expirou e foi libertada.", reservation.id, reservation.order_ref));
+        }
+        if !expired.is_empty() {
+            self.reopen_lapsed_orders();
+        }
+        self.check_replenishment_alerts();
+        #[cfg(feature = "sqlite")]
+        self.save_database();
 
         // Left Side Panel with logs
         egui::SidePanel::left("side_panel").resizable(true).show(ctx, |ui| {
+            if let Some(session) = self.warehouse.session() {
+                ui.label(format!("Utilizador: {} ({})", session.username, session.role.label()));
+            }
+            let mut selected_site = self.sites.active().to_string();
+            egui::ComboBox::from_label("Armazém")
+                .selected_text(&selected_site)
+                .show_ui(ui, |ui| {
+                    for name in self.sites.names() {
+                        ui.selectable_value(&mut selected_site, name.clone(), name);
+                    }
+                });
+            if selected_site != self.sites.active() {
+                self.switch_site(&selected_site);
+            }
+            if ui.button("Terminar Sessão").clicked() {
+                self.logs.push("Sessão terminada.".to_string());
+                self.stamp_logs();
+                self.warehouse.sign_out();
+                self.current_action = "Bem-vindo ao Gestor de Armazém!".to_string();
+            }
             ui.separator();
             if ui.button("Adicionar Item").clicked() {
                 self.current_action = "Adding Item".to_string();
@@ -122,12 +489,64 @@ impl eframe::App for App {
                 self.logs.push("--------------------------------------".to_string());
                 self.logs.push(self.search_results.clone());
             }
+            if ui.button("Importar/Exportar CSV").clicked() {
+                self.current_action = "Transferring CSV".to_string();
+            }
             if ui.button("Verificar Expirados por Data").clicked() {
                 self.current_action = "Checking Expiring Items by Date".to_string();
             }
             if ui.button("Remover Item").clicked() {
                 self.current_action = "Removing Item".to_string();
             }
+            if ui.button("Unidades e Embalagens").clicked() {
+                self.current_action = "Defining Packaging".to_string();
+            }
+            if ui.button("Ajustar Quantidade").clicked() {
+                self.current_action = "Adjusting Quantity".to_string();
+            }
+            if ui.button("Reservas").clicked() {
+                self.current_action = "Managing Reservations".to_string();
+            }
+            if ui.button("Encomendas").clicked() {
+                self.current_action = "Managing Orders".to_string();
+            }
+            if ui.button("Receção de Mercadoria").clicked() {
+                self.current_action = "Receiving Goods".to_string();
+            }
+            if ui.button("Contagens Cíclicas").clicked() {
+                self.current_action = "Cycle Counting".to_string();
+            }
+            if ui.button("Reposição").clicked() {
+                self.current_action = "Replenishment".to_string();
+            }
+            if ui.button("Rastreabilidade (Lote/Série)").clicked() {
+                self.current_action = "Tracing Lots".to_string();
+            }
+            if ui.button("Valorização de Stock").clicked() {
+                self.current_action = "Valuing Stock".to_string();
+            }
+            if ui.button("Rotas de Picking").clicked() {
+                self.current_action = "Planning Routes".to_string();
+            }
+            if ui.button("Slotting (ABC)").clicked() {
+                self.current_action = "Slotting".to_string();
+            }
+            if ui.button("Validar Segregação").clicked() {
+                self.current_action = "Validating Segregation".to_string();
+                self.validate_segregation();
+            }
+            if ui.button("Regras de Segregação").clicked() {
+                self.current_action = "Editing Segregation Rules".to_string();
+            }
+            if ui.button("Zonas Climáticas").clicked() {
+                self.current_action = "Editing Climate Zones".to_string();
+            }
+            if ui.button("Armazéns e Transferências").clicked() {
+                self.current_action = "Managing Sites".to_string();
+            }
+            if self.warehouse.authorize(Permission::ManageUsers).is_ok() && ui.button("Utilizadores").clicked() {
+                self.current_action = "Managing Users".to_string();
+            }
 
             ui.separator();
             ui.heading("Logs:");
@@ -160,7 +579,26 @@ impl eframe::App for App {
                 }
                 "Checking Expiring Items by Date" => self.render_check_expiring_by_date(ui),
                 "Removing Item" => self.render_remove_item(ui),
+                "Defining Packaging" => self.render_packaging(ui),
+                "Adjusting Quantity" => self.render_adjust_quantity(ui),
+                "Managing Reservations" => self.render_reservations(ui),
+                "Managing Orders" => self.render_orders(ui),
+                "Receiving Goods" => self.render_inbound(ui),
+                "Cycle Counting" => self.render_cycle_counts(ui),
+                "Replenishment" => self.render_replenishment(ui),
+                "Tracing Lots" => self.render_traceability(ui),
+                "Valuing Stock" => self.render_valuation(ui),
+                "Transferring CSV" => self.render_csv(ui),
+                "Planning Routes" => self.render_routes(ui),
+                "Slotting" => self.render_slotting(ui),
                 "Search Location by ID" => self.render_search_location_by_id(ui),
+                "Validating Segregation" => {
+                    ui.label("Verifique o log no painel lateral para o resultado da validação.");
+                }
+                "Editing Segregation Rules" => self.render_segregation_rules(ui),
+                "Editing Climate Zones" => self.render_climate_zones(ui),
+                "Managing Users" => self.render_users(ui),
+                "Managing Sites" => self.render_sites(ui),
                 _ => {
                     ui.label(&self.current_action);
                 }
@@ -193,26 +631,50 @@ impl eframe::App for App {
                 .resizable(false)
                 .show(ctx, |ui| {
                     ui.label("Escolha um item:");
-                    let names: Vec<_> = self.warehouse.items.values().map(|i| i.name.clone()).collect();
+                    let names: Vec<_> = self.warehouse.items().map(|(_, i)| i.name.clone()).collect();
                     let mut unique_names = names.clone();
                     unique_names.sort();
                     unique_names.dedup();
                     for n in unique_names {
                         if ui.button(&n).clicked() {
-                            if let Some(item) = self.warehouse.items.values().find(|it| it.name == n) {
+                            if let Some(item) = self.warehouse.items().map(|(_, i)| i).find(|it| it.name == n).cloned() {
                                 self.add_item_form.name = item.name.clone();
                                 self.add_item_form.quantity = item.item_quantity.to_string();
-                                self.add_item_form.quality = item.item_quality.clone();
-                                if let ItemQuality::Fragile = item.item_quality {
-                                    if let Some(dv) = item.data_validade {
-                                        self.add_item_form.data_validade = dv.format("%d-%m-%Y").to_string();
-                                    }
-                                    if let Some(nm) = item.nivel_maximo {
-                                        self.add_item_form.nivel_maximo = nm.to_string();
-                                    }
-                                } else if let ItemQuality::Oversized = item.item_quality {
-                                    if let Some(rz) = item.required_zones {
-                                        self.add_item_form.required_zones = rz.to_string();
+                                self.add_item_form.packaging_level = PackagingLevel::Each;
+                                self.add_item_form.fragile = false;
+                                self.add_item_form.oversized = false;
+                                self.add_item_form.hazardous = false;
+                                self.add_item_form.food = false;
+                                self.add_item_form.climate = item.climate();
+                                self.add_item_form.high_value = false;
+                                self.add_item_form.stack_limited = false;
+                                if let Some(dv) = item.data_validade {
+                                    self.add_item_form.data_validade = dv.format("%d-%m-%Y").to_string();
+                                }
+                                for attribute in &item.attributes {
+                                    match attribute {
+                                        HandlingAttribute::Fragile => self.add_item_form.fragile = true,
+                                        HandlingAttribute::LevelRange { nivel_minimo, nivel_maximo } => {
+                                            self.add_item_form.nivel_minimo = nivel_minimo.to_string();
+                                            self.add_item_form.nivel_maximo = nivel_maximo.to_string();
+                                        },
+                                        HandlingAttribute::Oversized { required_zones, required_levels, required_shelves } => {
+                                            self.add_item_form.oversized = true;
+                                            self.add_item_form.required_zones = required_zones.to_string();
+                                            self.add_item_form.required_levels = required_levels.to_string();
+                                            self.add_item_form.required_shelves = required_shelves.to_string();
+                                        },
+                                        HandlingAttribute::Hazardous(class) => {
+                                            self.add_item_form.hazardous = true;
+                                            self.add_item_form.hazard_class = *class;
+                                        },
+                                        HandlingAttribute::Food => self.add_item_form.food = true,
+                                        HandlingAttribute::Climate(_) => {},
+                                        HandlingAttribute::HighValue => self.add_item_form.high_value = true,
+                                        HandlingAttribute::StackLimit(limit) => {
+                                            self.add_item_form.stack_limited = true;
+                                            self.add_item_form.stack_limit = limit.to_string();
+                                        },
                                     }
                                 }
                             }
@@ -224,6 +686,8 @@ impl eframe::App for App {
                     }
                 });
         }
+
+        self.stamp_logs();
     }
 }
 
@@ -242,53 +706,91 @@ impl App {
         ui.horizontal(|ui| {
             ui.label("Quantidade:");
             ui.text_edit_singleline(&mut self.add_item_form.quantity);
+            Self::packaging_combo(ui, "add_item_packaging", &mut self.add_item_form.packaging_level);
         });
 
         ui.horizontal(|ui| {
-            ui.label("Qualidade:");
-            egui::ComboBox::from_label("")
-                .selected_text(match self.add_item_form.quality {
-                    ItemQuality::Fragile => "Fragile",
-                    ItemQuality::Oversized => "Oversized",
-                    ItemQuality::Normal => "Normal",
-                })
-                .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut self.add_item_form.quality, ItemQuality::Fragile, "Fragile");
-                    ui.selectable_value(&mut self.add_item_form.quality, ItemQuality::Oversized, "Oversized");
-                    ui.selectable_value(&mut self.add_item_form.quality, ItemQuality::Normal, "Normal");
-                });
+            ui.label("Atributos de Manuseamento:");
+            ui.checkbox(&mut self.add_item_form.fragile, "Fragile");
+            ui.checkbox(&mut self.add_item_form.oversized, "Oversized");
+            ui.checkbox(&mut self.add_item_form.hazardous, "Hazardous");
+            ui.checkbox(&mut self.add_item_form.food, "Food");
+            ui.checkbox(&mut self.add_item_form.high_value, "High-Value");
+            ui.checkbox(&mut self.add_item_form.stack_limited, "Stack Limit");
         });
 
-        if let ItemQuality::Fragile = self.add_item_form.quality {
+        if self.add_item_form.fragile {
             ui.horizontal(|ui| {
                 ui.label("Data de Validade (DD-MM-YYYY):");
                 ui.text_edit_singleline(&mut self.add_item_form.data_validade);
             });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Nível Mínimo de Armazenamento:");
+            ui.text_edit_singleline(&mut self.add_item_form.nivel_minimo);
+            ui.label("Nível Máximo de Armazenamento:");
+            ui.text_edit_singleline(&mut self.add_item_form.nivel_maximo);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Clima de Armazenamento:");
+            egui::ComboBox::from_id_salt("item_climate")
+                .selected_text(self.add_item_form.climate.label())
+                .show_ui(ui, |ui| {
+                    for zone in ClimateZone::ALL {
+                        ui.selectable_value(&mut self.add_item_form.climate, zone, zone.label());
+                    }
+                });
+        });
+
+        if self.add_item_form.hazardous {
             ui.horizontal(|ui| {
-                ui.label("Nível Máximo de Armazenamento:");
-                ui.text_edit_singleline(&mut self.add_item_form.nivel_maximo);
+                ui.label("Classe de Perigo:");
+                egui::ComboBox::from_id_salt("hazard_class")
+                    .selected_text(self.add_item_form.hazard_class.label())
+                    .show_ui(ui, |ui| {
+                        for class in HazardClass::ALL {
+                            ui.selectable_value(&mut self.add_item_form.hazard_class, class, class.label());
+                        }
+                    });
             });
-        } else if let ItemQuality::Oversized = self.add_item_form.quality {
+        }
+        if self.add_item_form.oversized {
             ui.horizontal(|ui| {
                 ui.label("Zonas Contíguas Necessárias:");
                 ui.text_edit_singleline(&mut self.add_item_form.required_zones);
             });
+            ui.horizontal(|ui| {
+                ui.label("Níveis Contíguos Necessários:");
+                ui.text_edit_singleline(&mut self.add_item_form.required_levels);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Prateleiras Contíguas Necessárias:");
+                ui.text_edit_singleline(&mut self.add_item_form.required_shelves);
+            });
+        }
+        if self.add_item_form.stack_limited {
+            ui.horizontal(|ui| {
+                ui.label("Níveis Máximos de Empilhamento:");
+                ui.text_edit_singleline(&mut self.add_item_form.stack_limit);
+            });
         }
+        ui.horizontal(|ui| {
+            ui.label("Custo por Unidade Base (opcional):");
+            ui.text_edit_singleline(&mut self.add_item_form.unit_cost);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Lote (opcional):");
+            ui.text_edit_singleline(&mut self.add_item_form.lot);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Números de Série (separados por vírgula, opcional):");
+            ui.text_edit_singleline(&mut self.add_item_form.serials);
+        });
 
         if ui.button("Adicionar").clicked() {
             match self.create_item_from_form() {
                 Ok(mut item) => {
-                    // If the name doesn't exist in name_to_id, assign next_id and increment.
-                    // If it exists, use that ID.
-                    let item_id = if let Some(&existing_id) = self.name_to_id.get(&item.name) {
-                        existing_id
-                    } else {
-                        let new_id = self.next_id;
-                        self.next_id += 1;
-                        self.name_to_id.insert(item.name.clone(), new_id);
-                        new_id
-                    };
-                    item.num_id = item_id;
+                    item.num_id = self.item_id_for(&item.name);
 
                     match self.warehouse.add_item(item) {
                         Ok(locations) => {
@@ -315,16 +817,42 @@ impl App {
         }
     }
 
+    // Keeps name -> ID lookups in step with a warehouse loaded from elsewhere
+    fn rebuild_name_index(&mut self) {
+        for (itm, _) in self.warehouse.grouped_items() {
+            self.name_to_id.entry(itm.name.clone()).or_insert(itm.num_id);
+            self.next_id = self.next_id.max(itm.num_id.saturating_add(1));
+        }
+    }
+
+    fn item_id_for(&mut self, name: &str) -> u32 {
+        // If the name doesn't exist in name_to_id, assign next_id and increment.
+        // If it exists, use that ID.
+        if let Some(&existing_id) = self.name_to_id.get(name) {
+            existing_id
+        } else {
+            let new_id = self.next_id;
+            self.next_id += 1;
+            self.name_to_id.insert(name.to_string(), new_id);
+            new_id
+        }
+    }
+
     fn render_search_by_id(&mut self, ui: &mut egui::Ui) {
         ui.label("Insira o ID do Item:");
         ui.text_edit_singleline(&mut self.search_by_id_form.id);
         if ui.button("Buscar").clicked() {
             if let Ok(num_id) = self.search_by_id_form.id.trim().parse::<u32>() {
-                let (found, count) = self.warehouse.search_by_id(num_id);
+                let summary = self.warehouse.search_by_id(num_id);
                 self.logs.push("--------------------------------------".to_string());
                 self.logs.push(format!("Resultado da busca por ID {}:", num_id));
-                if found {
-                    self.logs.push(format!("   Encontrado(s) {} item(ns) com ID {}", count, num_id));
+                if summary.on_hand > 0 {
+                    let packaging = self.warehouse.packaging.packaging(num_id);
+                    self.logs.push(format!("   Encontrado(s) {} com ID {}", packaging.describe(summary.on_hand), num_id));
+                    self.logs.push(format!(
+                        "   Em stock: {}, Reservado: {}, Disponível: {}",
+                        packaging.describe(summary.on_hand), packaging.describe(summary.reserved), packaging.describe(summary.available)
+                    ));
                 } else {
                     self.logs.push(format!("   Nenhum item encontrado com ID {}", num_id));
                 }
@@ -344,11 +872,16 @@ impl App {
         if ui.button("Buscar").clicked() {
             let name = self.search_by_name_form.name.trim();
             if !name.is_empty() {
-                let (found, count) = self.warehouse.search_by_name(name);
+                let summary = self.warehouse.search_by_name(name);
                 self.logs.push("--------------------------------------".to_string());
                 self.logs.push(format!("Resultado da busca por nome '{}':", name));
-                if found {
-                    self.logs.push(format!("   Encontrado(s) {} item(ns) com o nome '{}'", count, name));
+                if summary.on_hand > 0 {
+                    let packaging = self.name_to_id.get(name).map(|id| self.warehouse.packaging.packaging(*id)).unwrap_or_default();
+                    self.logs.push(format!("   Encontrado(s) {} com o nome '{}'", packaging.describe(summary.on_hand), name));
+                    self.logs.push(format!(
+                        "   Em stock: {}, Reservado: {}, Disponível: {}",
+                        packaging.describe(summary.on_hand), packaging.describe(summary.reserved), packaging.describe(summary.available)
+                    ));
                 } else {
                     self.logs.push(format!("   Nenhum item encontrado chamado '{}'", name));
                 }
@@ -374,7 +907,10 @@ impl App {
                     self.logs.push("   Nenhum item encontrado.".to_string());
                 } else {
                     for (itm, locs) in results {
-                        self.logs.push(format!("   ID: {}, Nome: {}, Quantidade: {}, Qualidade: {:?}", itm.num_id, itm.name, itm.item_quantity, itm.item_quality));
+                        self.logs.push(format!(
+                            "   ID: {}, Nome: {}, Quantidade: {}, Qualidade: {}",
+                            itm.num_id, itm.name, self.warehouse.packaging.describe(itm.num_id, itm.item_quantity), itm.quality_label()
+                        ));
                         self.logs.push("   Localizações:".to_string());
                         for loc in locs {
                             self.logs.push(format!("      (F{},P{},N{},Z{})", loc.row, loc.shelf, loc.level, loc.zone));
@@ -406,18 +942,38 @@ impl App {
             ui.label("Zona:");
             ui.text_edit_singleline(&mut self.remove_item_form.zone);
         });
+        ui.horizontal(|ui| {
+            ui.label("Quantidade (vazio remove tudo):");
+            ui.text_edit_singleline(&mut self.remove_item_form.quantity);
+            Self::packaging_combo(ui, "remove_item_packaging", &mut self.remove_item_form.packaging_level);
+        });
 
         if ui.button("Remover Item").clicked() {
             match validate_location_input(&self.remove_item_form.row, &self.remove_item_form.shelf, &self.remove_item_form.level, &self.remove_item_form.zone, self.warehouse.max_dimensions) {
                 Ok((row_num, shelf_num, level_num, zone_num)) => {
                     let location = Location { row: row_num, shelf: shelf_num, level: level_num, zone: zone_num };
-                    match self.warehouse.remove_item(&location) {
+                    let result = match self.remove_item_form.quantity.trim() {
+                        "" => self.warehouse.remove_item(&location),
+                        quantity => quantity.parse::<u32>()
+                            .map_err(|_| "Quantidade inválida.".to_string())
+                            .and_then(|quantity| {
+                                let num_id = self.warehouse.item_at(&location).map(|i| i.num_id)
+                                    .ok_or("Nenhum item encontrado nesta localização.".to_string())?;
+                                self.warehouse.packaging.to_base(num_id, quantity, self.remove_item_form.packaging_level)
+                            })
+                            .and_then(|base| self.warehouse.remove_quantity(&location, base))
+                            .map(|item| vec![item]),
+                    };
+                    match result {
                         Ok(items) => {
                             self.logs.push("--------------------------------------".to_string());
                             if !items.is_empty() {
                                 self.logs.push("Remoção realizada com sucesso!".to_string());
                                 for i in items {
-                                    self.logs.push(format!("   Item removido: ID={}, Nome={}, Quantidade={}", i.num_id, i.name, i.item_quantity));
+                                    self.logs.push(format!(
+                                        "   Item removido: ID={}, Nome={}, Quantidade={}",
+                                        i.num_id, i.name, self.warehouse.packaging.describe(i.num_id, i.item_quantity)
+                                    ));
                                 }
                             } else {
                                 self.logs.push("Nenhum item removido.".to_string());
@@ -440,117 +996,1782 @@ impl App {
         }
     }
 
-    fn render_check_expiring_by_date(&mut self, ui: &mut egui::Ui) {
-        ui.label("Insira a data de referência (DD-MM-YYYY):");
-        ui.text_edit_singleline(&mut self.check_expiring_form.date);
+    fn render_packaging(&mut self, ui: &mut egui::Ui) {
+        ui.label("Unidade base e níveis de embalagem do produto:");
+        ui.horizontal(|ui| {
+            ui.label("Nome do Produto:");
+            ui.text_edit_singleline(&mut self.packaging_form.name);
+            ui.label("Unidade Base:");
+            ui.text_edit_singleline(&mut self.packaging_form.base_unit);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Unidades por Caixa:");
+            ui.text_edit_singleline(&mut self.packaging_form.units_per_case);
+            ui.label("Caixas por Palete:");
+            ui.text_edit_singleline(&mut self.packaging_form.cases_per_pallet);
+        });
 
-        if ui.button("Verificar").clicked() {
-            if let Some(ref_date) = parse_date(&self.check_expiring_form.date) {
-                let results = self.warehouse.find_expiring_items_by_date(ref_date);
-                self.logs.push("--------------------------------------".to_string());
-                self.logs.push(format!("A verificar itens a partir da data {}:", ref_date.format("%d-%m-%Y")));
-                if results.is_empty() {
-                    self.logs.push("   Nenhum item expirado ou próximo de expirar.".to_string());
-                } else {
-                    for (itm, status, locs) in results {
-                        self.logs.push("--------------------------------------".to_string());
-                        self.logs.push(format!("ID: {}, Nome: {}, Status: {}", itm.num_id, itm.name, status));
-                        self.logs.push("Localizações:".to_string());
-                        for loc in locs {
-                            self.logs.push(format!("   (F{},P{},N{},Z{})", loc.row, loc.shelf, loc.level, loc.zone));
-                        }
-                    }
+        if ui.button("Definir Embalagem").clicked() {
+            let name = self.packaging_form.name.trim().to_string();
+            let result = (|| {
+                self.warehouse.authorize(Permission::Configure)?;
+                if name.is_empty() {
+                    return Err("Nome não pode estar vazio.".to_string());
                 }
-                self.logs.push("--------------------------------------".to_string());
-                self.current_action = "Verificação de Validade Concluída".to_string();
-            } else {
-                let msg = "Data inválida. Use DD-MM-YYYY.".to_string();
-                self.logs.push(msg);
-                self.current_action = "Erro na Verificação de Validade".to_string();
+                let units_per_case = self.packaging_form.units_per_case.trim().parse::<u32>().map_err(|_| "Unidades por caixa inválidas.".to_string())?;
+                let cases_per_pallet = self.packaging_form.cases_per_pallet.trim().parse::<u32>().map_err(|_| "Caixas por palete inválidas.".to_string())?;
+                let num_id = self.item_id_for(&name);
+                self.warehouse.packaging.define(num_id, self.packaging_form.base_unit.clone(), units_per_case, cases_per_pallet)?;
+                self.warehouse.mark_changed();
+                Ok(num_id)
+            })();
+            match result {
+                Ok(num_id) => {
+                    let packaging = self.warehouse.packaging.packaging(num_id);
+                    self.logs.push(format!(
+                        "Embalagem de {} (ID {}): 1 Caixa = {} {}, 1 Palete = {} {}",
+                        name, num_id,
+                        packaging.factor(PackagingLevel::Case), packaging.base_unit,
+                        packaging.factor(PackagingLevel::Pallet), packaging.base_unit
+                    ));
+                },
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
             }
         }
     }
 
-    fn list_items(&self) -> String {
-        let mut grouped = self.warehouse.grouped_items();
-        grouped.sort_by(|(a,_),(b,_)| a.name.cmp(&b.name));
-
-        let mut items_strs = Vec::new();
-        for (item, locs) in grouped {
-            let date_str = if let Some(dv) = item.data_validade {
-                dv.format("%d-%m-%Y").to_string()
-            } else {
-                "N/A".to_string()
-            };
-            let extra = match item.item_quality {
-                ItemQuality::Fragile => format!("Validade: {}, Nível Máximo: {:?}", date_str, item.nivel_maximo),
-                ItemQuality::Oversized => format!("Zonas Necessárias: {:?}", item.required_zones),
-                ItemQuality::Normal => "N/A".to_string(),
-            };
-
-            items_strs.push(format!(
-                "Nome: {}, ID: {}, Quantidade: {}, Qualidade: {:?}, Timestamp: {}, {}\n   Localizações: {}",
-                item.name,
-                item.num_id,
-                item.item_quantity,
-                item.item_quality,
-                item.timestamp,
-                extra,
-                locs.iter().map(|l| format!("(F{},P{},N{},Z{})", l.row, l.shelf, l.level, l.zone)).collect::<Vec<_>>().join(", ")
-            ));
-        }
-        items_strs.join("\n--------------------------------------\n")
+    fn packaging_combo(ui: &mut egui::Ui, id: &str, level: &mut PackagingLevel) {
+        egui::ComboBox::from_id_salt(id)
+            .selected_text(level.label())
+            .show_ui(ui, |ui| {
+                for l in PackagingLevel::ALL {
+                    ui.selectable_value(level, l, l.label());
+                }
+            });
     }
 
-    fn create_item_from_form(&self) -> Result<Item, String> {
-        let name = self.add_item_form.name.trim().to_string();
-        if name.is_empty() {
-            return Err("Nome não pode estar vazio.".to_string());
-        }
-        let quantity = self.add_item_form.quantity.trim().parse::<u32>().map_err(|_| "Quantidade inválida.".to_string())?;
-        let item_quality = self.add_item_form.quality.clone();
+    fn render_adjust_quantity(&mut self, ui: &mut egui::Ui) {
+        ui.label("Insira a Localização e a nova Quantidade:");
 
-        let mut data_validade = None;
-        let mut nivel_maximo = None;
-        let mut required_zones = None;
+        ui.horizontal(|ui| {
+            ui.label("Fileira:");
+            ui.text_edit_singleline(&mut self.adjust_quantity_form.row);
+            ui.label("Prateleira:");
+            ui.text_edit_singleline(&mut self.adjust_quantity_form.shelf);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Nível:");
+            ui.text_edit_singleline(&mut self.adjust_quantity_form.level);
+            ui.label("Zona:");
+            ui.text_edit_singleline(&mut self.adjust_quantity_form.zone);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Nova Quantidade:");
+            ui.text_edit_singleline(&mut self.adjust_quantity_form.quantity);
+            ui.label("Motivo:");
+            egui::ComboBox::from_id_salt("adjust_reason")
+                .selected_text(self.adjust_quantity_form.reason.label())
+                .show_ui(ui, |ui| {
+                    for reason in AdjustmentReason::ALL {
+                        ui.selectable_value(&mut self.adjust_quantity_form.reason, reason, reason.label());
+                    }
+                });
+        });
 
-        match item_quality {
-            ItemQuality::Fragile => {
-                data_validade = Some(parse_date(&self.add_item_form.data_validade).ok_or("Formato de data inválido. Use DD-MM-YYYY.")?);
-                nivel_maximo = Some(self.add_item_form.nivel_maximo.trim().parse::<u32>().map_err(|_| "Nível máximo inválido.".to_string())?);
-            },
-            ItemQuality::Oversized => {
-                required_zones = Some(self.add_item_form.required_zones.trim().parse::<u32>().map_err(|_| "Número de zonas contíguas inválido.".to_string())?);
-            },
-            ItemQuality::Normal => {},
+        if ui.button("Ajustar").clicked() {
+            let form = &self.adjust_quantity_form;
+            let result = validate_location_input(&form.row, &form.shelf, &form.level, &form.zone, self.warehouse.max_dimensions)
+                .and_then(|(row, shelf, level, zone)| {
+                    let quantity = form.quantity.trim().parse::<u32>().map_err(|_| "Quantidade inválida.".to_string())?;
+                    let location = Location { row, shelf, level, zone };
+                    let old_quantity = self.warehouse.adjust_quantity(&location, quantity, form.reason)?;
+                    Ok((location, old_quantity, quantity))
+                });
+            match result {
+                Ok((loc, old_quantity, quantity)) => {
+                    self.logs.push(format!(
+                        "Quantidade em (F{},P{},N{},Z{}) ajustada de {} para {} ({})",
+                        loc.row, loc.shelf, loc.level, loc.zone, old_quantity, quantity, self.adjust_quantity_form.reason.label()
+                    ));
+                    self.current_action = "Ajuste Concluído".to_string();
+                },
+                Err(err) => {
+                    self.logs.push(format!("Erro: {}", err));
+                    self.current_action = "Erro no Ajuste".to_string();
+                }
+            }
         }
-
-        Ok(Item::new(
-            0,
-            name,
-            quantity,
-            item_quality,
-            data_validade,
-            nivel_maximo,
-            required_zones,
-        ))
     }
 
-    fn render_warehouse(&mut self, ui: &mut egui::Ui) {
-        let row_spacing = 40.0;
-        let shelf_spacing = 20.0;
-        let level_spacing = 2.0;
-        let zone_spacing = 1.0;
-
-        let (rows, shelves, levels, zones) = self.warehouse.max_dimensions;
-
-        let available_rect = ui.available_rect_before_wrap();
-        ui.allocate_rect(available_rect, egui::Sense::hover());
+    fn render_reservations(&mut self, ui: &mut egui::Ui) {
+        ui.label("Reservar stock de uma localização para uma encomenda:");
 
-        let width = available_rect.width();
-        let height = available_rect.height();
-
-        let shelves_float = shelves as f32;
+        ui.horizontal(|ui| {
+            ui.label("Fileira:");
+            ui.text_edit_singleline(&mut self.reservation_form.row);
+            ui.label("Prateleira:");
+            ui.text_edit_singleline(&mut self.reservation_form.shelf);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Nível:");
+            ui.text_edit_singleline(&mut self.reservation_form.level);
+            ui.label("Zona:");
+            ui.text_edit_singleline(&mut self.reservation_form.zone);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Quantidade:");
+            ui.text_edit_singleline(&mut self.reservation_form.quantity);
+            ui.label("Encomenda:");
+            ui.text_edit_singleline(&mut self.reservation_form.order_ref);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Expira em (DD-MM-YYYY):");
+            ui.text_edit_singleline(&mut self.reservation_form.expires);
+        });
+
+        if ui.button("Reservar").clicked() {
+            let form = &self.reservation_form;
+            let result = validate_location_input(&form.row, &form.shelf, &form.level, &form.zone, self.warehouse.max_dimensions)
+                .and_then(|(row, shelf, level, zone)| {
+                    let quantity = form.quantity.trim().parse::<u32>().map_err(|_| "Quantidade inválida.".to_string())?;
+                    let order_ref = form.order_ref.trim().to_string();
+                    if order_ref.is_empty() {
+                        return Err("Referência da encomenda não pode estar vazia.".to_string());
+                    }
+                    let expires = parse_date(&form.expires).ok_or("Data inválida. Use DD-MM-YYYY.".to_string())?;
+                    if expires < today() {
+                        return Err("A data de expiração da reserva já passou.".to_string());
+                    }
+                    self.warehouse.reserve(&Location { row, shelf, level, zone }, quantity, order_ref, expires)
+                });
+            match result {
+                Ok(reservation) => {
+                    self.logs.push(format!("Reserva {} criada: {} unidade(s) para a encomenda {}", reservation.id, reservation.quantity, reservation.order_ref));
+                    self.current_action = "Managing Reservations".to_string();
+                },
+                Err(err) => {
+                    self.logs.push(format!("Erro: {}", err));
+                }
+            }
+        }
+
+        ui.separator();
+        ui.label("Reservas ativas:");
+        let mut to_release = None;
+        for reservation in &self.warehouse.reservations {
+            let item_name = self.warehouse.items()
+                .find(|(_, itm)| itm.record_id == reservation.record_id)
+                .map(|(_, itm)| itm.name.clone())
+                .unwrap_or_default();
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "#{} {} x{} para {} (expira {})",
+                    reservation.id, item_name, reservation.quantity, reservation.order_ref, reservation.expires.format("%d-%m-%Y")
+                ));
+                if ui.button("Libertar").clicked() {
+                    to_release = Some(reservation.id);
+                }
+            });
+        }
+        if let Some(id) = to_release {
+            match self.warehouse.release_reservation(id) {
+                Ok(reservation) => self.logs.push(format!("Reserva {} libertada.", reservation.id)),
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+    }
+
+    // Orders whose reservations lapsed go back to Open for the quantities they still have to pick
+    fn reopen_lapsed_orders(&mut self) {
+        match self.orders.reopen_lapsed(&mut self.warehouse) {
+            Ok(reopened) => {
+                for order_id in reopened {
+                    self.logs.push(format!("Encomenda {} reaberta: as suas reservas expiraram ou foram libertadas.", order_id));
+                }
+            },
+            Err(err) => self.logs.push(format!("Erro: {}", err)),
+        }
+    }
+
+    fn render_orders(&mut self, ui: &mut egui::Ui) {
+        ui.label("Nova encomenda:");
+        ui.horizontal(|ui| {
+            ui.label("Cliente:");
+            ui.text_edit_singleline(&mut self.order_form.customer);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Linhas (ID:QTD, ID:QTD):");
+            ui.text_edit_singleline(&mut self.order_form.lines);
+        });
+        if ui.button("Criar Encomenda").clicked() {
+            let result = parse_order_lines(&self.order_form.lines)
+                .and_then(|lines| self.orders.create_order(self.order_form.customer.trim().to_string(), lines));
+            match result {
+                Ok(id) => self.logs.push(format!("Encomenda {} criada.", id)),
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+
+        ui.separator();
+        ui.label("Encomendas:");
+        let mut to_allocate = None;
+        for order in &self.orders.orders {
+            ui.horizontal(|ui| {
+                let lines = order.lines.iter().map(|l| format!("{}x{}", l.num_id, l.quantity)).collect::<Vec<_>>().join(", ");
+                ui.label(format!("{} - {} [{}] ({})", order.reference(), order.customer, lines, order.status.label()));
+                if order.status == OrderStatus::Open && ui.button("Alocar").clicked() {
+                    to_allocate = Some(order.id);
+                }
+            });
+        }
+        if let Some(order_id) = to_allocate {
+            let expires = today() + Duration::days(ORDER_RESERVATION_DAYS);
+            match self.orders.allocate(order_id, &mut self.warehouse, expires) {
+                Ok(()) => self.logs.push(format!("Encomenda {} alocada ao stock.", order_id)),
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Encomendas por vaga:");
+            ui.text_edit_singleline(&mut self.order_form.wave_size);
+            if ui.button("Criar Vaga").clicked() {
+                self.reopen_lapsed_orders();
+                let result = self.order_form.wave_size.trim().parse::<usize>()
+                    .map_err(|_| "Número de encomendas por vaga inválido.".to_string())
+                    .and_then(|size| self.orders.create_wave(size, &mut self.warehouse));
+                match result {
+                    Ok(wave_id) => self.log_pick_list(wave_id),
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+        });
+
+        let mut to_confirm = None;
+        let mut to_confirm_wave = None;
+        for wave in &self.orders.waves {
+            if wave.tasks.iter().all(|t| t.picked) {
+                continue;
+            }
+            let orders = wave.order_ids.iter().map(|id| format!("ENC-{}", id)).collect::<Vec<_>>().join(", ");
+            ui.horizontal(|ui| {
+                ui.label(format!("Vaga {} ({}):", wave.id, orders));
+                if ui.button("Confirmar Vaga").clicked() {
+                    to_confirm_wave = Some(wave.id);
+                }
+            });
+            for (index, task) in wave.tasks.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "   (F{},P{},N{},Z{}) {} x{} -> ENC-{}",
+                        task.location.row, task.location.shelf, task.location.level, task.location.zone, task.name, task.quantity, task.order_id
+                    ));
+                    if task.picked {
+                        ui.label("✔");
+                    } else if ui.button("Confirmar").clicked() {
+                        to_confirm = Some((wave.id, index));
+                    }
+                });
+            }
+        }
+        if let Some(wave_id) = to_confirm_wave {
+            match self.orders.confirm_wave(wave_id, &mut self.warehouse, today()) {
+                Ok(tasks) => {
+                    self.logs.push("--------------------------------------".to_string());
+                    self.logs.push(format!("Vaga {} confirmada: {} tarefa(s) de picking.", wave_id, tasks.len()));
+                    for task in &tasks {
+                        self.logs.push(format!("   {} x{} para ENC-{}", task.name, task.quantity, task.order_id));
+                        if !task.serials.is_empty() {
+                            self.logs.push(format!("      Números de série: {}", task.serials.join(", ")));
+                        }
+                    }
+                    self.logs.push("--------------------------------------".to_string());
+                },
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+        if let Some((wave_id, index)) = to_confirm {
+            match self.orders.confirm_pick(wave_id, index, &mut self.warehouse, today()) {
+                Ok(task) => {
+                    self.logs.push(format!("Picking confirmado: {} x{} para ENC-{}", task.name, task.quantity, task.order_id));
+                    if let Some(lot) = &task.lot {
+                        self.logs.push(format!("   Lote: {}", lot));
+                    }
+                    if !task.serials.is_empty() {
+                        self.logs.push(format!("   Números de série: {}", task.serials.join(", ")));
+                    }
+                },
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+    }
+
+    fn render_inbound(&mut self, ui: &mut egui::Ui) {
+        ui.label("Nova ordem de compra / aviso de expedição:");
+        ui.horizontal(|ui| {
+            ui.label("Referência PO:");
+            ui.text_edit_singleline(&mut self.inbound_form.po_reference);
+            ui.label("Fornecedor:");
+            ui.text_edit_singleline(&mut self.inbound_form.supplier);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Referência ASN (opcional):");
+            ui.text_edit_singleline(&mut self.inbound_form.asn_reference);
+            if ui.button("Criar PO").clicked() {
+                let asn = self.inbound_form.asn_reference.trim();
+                let asn_reference = if asn.is_empty() { None } else { Some(asn.to_string()) };
+                match self.inbound.create_purchase_order(
+                    self.inbound_form.po_reference.trim().to_string(),
+                    self.inbound_form.supplier.trim().to_string(),
+                    asn_reference,
+                ) {
+                    Ok(id) => {
+                        self.inbound_form.po_id = id.to_string();
+                        self.logs.push(format!("Ordem de compra {} criada.", id));
+                    },
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("ID da PO:");
+            ui.text_edit_singleline(&mut self.inbound_form.po_id);
+        });
+        if ui.button("Adicionar Linha (dados do formulário Adicionar Item)").clicked() {
+            let result = self.inbound_form.po_id.trim().parse::<u32>()
+                .map_err(|_| "Formato de ID inválido.".to_string())
+                .and_then(|po_id| {
+                    let mut template = self.create_item_from_form()?;
+                    template.num_id = self.item_id_for(&template.name);
+                    let expected = template.item_quantity;
+                    self.inbound.add_line(po_id, template, expected)
+                });
+            match result {
+                Ok(line) => self.logs.push(format!("Linha {} adicionada à ordem de compra.", line)),
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label("Linha:");
+            ui.text_edit_singleline(&mut self.inbound_form.line_number);
+            ui.label("Recebido:");
+            ui.text_edit_singleline(&mut self.inbound_form.received);
+            ui.label("Danificado:");
+            ui.text_edit_singleline(&mut self.inbound_form.damaged);
+        });
+        if ui.button("Registar Receção").clicked() {
+            let form = &self.inbound_form;
+            let result = (|| {
+                let po_id = form.po_id.trim().parse::<u32>().map_err(|_| "Formato de ID inválido.".to_string())?;
+                let line = form.line_number.trim().parse::<usize>().map_err(|_| "Número de linha inválido.".to_string())?;
+                let received = form.received.trim().parse::<u32>().map_err(|_| "Quantidade recebida inválida.".to_string())?;
+                let damaged = if form.damaged.trim().is_empty() { 0 } else { form.damaged.trim().parse::<u32>().map_err(|_| "Quantidade danificada inválida.".to_string())? };
+                self.inbound.receive(po_id, line, received, damaged).map(|d| (po_id, line, d))
+            })();
+            match result {
+                Ok((po_id, line, discrepancies)) => {
+                    self.logs.push(format!("Receção registada para a PO {}, linha {}.", po_id, line));
+                    if discrepancies.is_empty() {
+                        self.logs.push("   Quantidade conforme o esperado.".to_string());
+                    }
+                    for d in discrepancies {
+                        self.logs.push(format!("   Discrepância: {}", d.label()));
+                    }
+                },
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+
+        ui.separator();
+        let mut to_put_away = None;
+        for po in &self.inbound.purchase_orders {
+            let asn = po.asn_reference.as_ref().map(|a| format!(", ASN {}", a)).unwrap_or_default();
+            let status = if po.is_complete() { " - concluída" } else { "" };
+            ui.label(format!("PO {} ({}{}) - {}{}", po.id, po.reference, asn, po.supplier, status));
+            for (index, line) in po.lines.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let received = match line.receipt {
+                        Some(r) => format!("recebido {} ({} danificado)", r.received, r.damaged),
+                        None => "por rececionar".to_string(),
+                    };
+                    ui.label(format!("   {}. {} (ID {}) esperado {}, {}", index + 1, line.template.name, line.template.num_id, line.expected, received));
+                    if line.receipt.is_some() && line.put_away.is_empty() && ui.button("Arrumar").clicked() {
+                        to_put_away = Some((po.id, index + 1));
+                    }
+                });
+            }
+        }
+        if let Some((po_id, line)) = to_put_away {
+            match self.inbound.putaway(po_id, line, &mut self.warehouse) {
+                Ok(locations) => {
+                    self.logs.push(format!("Linha {} da PO {} arrumada em:", line, po_id));
+                    for loc in &locations {
+                        self.logs.push(format!("   (F{}, P{}, N{}, Z{})", loc.row, loc.shelf, loc.level, loc.zone));
+                    }
+                },
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+    }
+
+    fn render_cycle_counts(&mut self, ui: &mut egui::Ui) {
+        ui.label("Gerar tarefas de contagem:");
+        ui.horizontal(|ui| {
+            ui.label("Fileira:");
+            ui.text_edit_singleline(&mut self.cycle_count_form.row);
+            ui.label("Prateleira (opcional):");
+            ui.text_edit_singleline(&mut self.cycle_count_form.shelf);
+            if ui.button("Gerar por Localização").clicked() {
+                let form = &self.cycle_count_form;
+                let result = (|| {
+                    let row = form.row.trim().parse::<u32>().map_err(|_| "Fileira inválida.".to_string())?;
+                    let shelf = match form.shelf.trim() {
+                        "" => None,
+                        s => Some(s.parse::<u32>().map_err(|_| "Prateleira inválida.".to_string())?),
+                    };
+                    Ok::<_, String>(self.cycle_counts.create_for_location(&self.warehouse, row, shelf))
+                })();
+                self.log_created_counts(result);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("ID do Produto:");
+            ui.text_edit_singleline(&mut self.cycle_count_form.num_id);
+            if ui.button("Gerar por Produto").clicked() {
+                let result = self.cycle_count_form.num_id.trim().parse::<u32>()
+                    .map_err(|_| "Formato de ID inválido.".to_string())
+                    .map(|num_id| self.cycle_counts.create_for_product(&self.warehouse, num_id));
+                self.log_created_counts(result);
+            }
+        });
+        if ui.button("Gerar Contagens Devidas (ABC)").clicked() {
+            let created = self.cycle_counts.create_due(&self.warehouse, today());
+            self.log_created_counts(Ok(created));
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Contagem:");
+            ui.text_edit_singleline(&mut self.cycle_count_form.task_id);
+            ui.label("Quantidade Contada:");
+            ui.text_edit_singleline(&mut self.cycle_count_form.counted);
+            if ui.button("Registar Contagem").clicked() {
+                let form = &self.cycle_count_form;
+                let result = form.task_id.trim().parse::<u32>().map_err(|_| "Número de contagem inválido.".to_string())
+                    .and_then(|task_id| {
+                        let counted = form.counted.trim().parse::<u32>().map_err(|_| "Quantidade inválida.".to_string())?;
+                        self.cycle_counts.record_count(task_id, counted).map(|_| task_id)
+                    });
+                match result {
+                    Ok(task_id) => self.logs.push(format!("Contagem {} registada.", task_id)),
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Motivo:");
+            egui::ComboBox::from_id_salt("count_reason")
+                .selected_text(self.cycle_count_form.reason.label())
+                .show_ui(ui, |ui| {
+                    for reason in AdjustmentReason::ALL {
+                        ui.selectable_value(&mut self.cycle_count_form.reason, reason, reason.label());
+                    }
+                });
+            if ui.button("Aprovar").clicked() {
+                let reason = self.cycle_count_form.reason;
+                let result = self.cycle_count_form.task_id.trim().parse::<u32>()
+                    .map_err(|_| "Número de contagem inválido.".to_string())
+                    .and_then(|task_id| self.cycle_counts.approve(task_id, reason, &mut self.warehouse, today()).map(|d| (task_id, d)));
+                match result {
+                    Ok((task_id, Some(diff))) => self.logs.push(format!("Contagem {} aprovada: stock ajustado em {:+} ({}).", task_id, diff, reason.label())),
+                    Ok((task_id, None)) => self.logs.push(format!("Contagem {} aprovada sem discrepâncias.", task_id)),
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+            if ui.button("Rejeitar").clicked() {
+                let result = self.cycle_count_form.task_id.trim().parse::<u32>()
+                    .map_err(|_| "Número de contagem inválido.".to_string())
+                    .and_then(|task_id| self.cycle_counts.reject(task_id).map(|_| task_id));
+                match result {
+                    Ok(task_id) => self.logs.push(format!("Contagem {} rejeitada.", task_id)),
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+        });
+        if ui.button("Relatório de Discrepâncias").clicked() {
+            self.logs.push("--------------------------------------".to_string());
+            self.logs.push("Discrepâncias de inventário:".to_string());
+            let report = self.cycle_counts.discrepancy_report();
+            if report.is_empty() {
+                self.logs.push("   Nenhuma discrepância por aprovar.".to_string());
+            }
+            for task in report {
+                let loc = task.current_location(&self.warehouse);
+                self.logs.push(format!(
+                    "   Contagem {} - {} (ID {}) em (F{},P{},N{},Z{}): sistema {}, contado {}, diferença {:+}",
+                    task.id, task.name, task.num_id, loc.row, loc.shelf, loc.level, loc.zone,
+                    task.expected, task.counted.unwrap_or(0), task.discrepancy().unwrap_or(0)
+                ));
+            }
+            self.logs.push("--------------------------------------".to_string());
+        }
+
+        ui.separator();
+        for task in &self.cycle_counts.tasks {
+            let counted = task.counted.map(|c| c.to_string()).unwrap_or("-".to_string());
+            let reason = task.reason.map(|r| format!(", {}", r.label())).unwrap_or_default();
+            let loc = task.current_location(&self.warehouse);
+            let line = format!(
+                "{}. {} (ID {}) em (F{},P{},N{},Z{}) - sistema {}, contado {} [{}{}]",
+                task.id, task.name, task.num_id, loc.row, loc.shelf, loc.level, loc.zone,
+                task.expected, counted, task.status.label(), reason
+            );
+            if task.status == CountStatus::Counted && task.discrepancy().is_some() {
+                ui.colored_label(egui::Color32::RED, line);
+            } else {
+                ui.label(line);
+            }
+        }
+    }
+
+    fn log_created_counts(&mut self, result: Result<Vec<u32>, String>) {
+        match result {
+            Ok(ids) if ids.is_empty() => self.logs.push("Nenhuma contagem nova a gerar.".to_string()),
+            Ok(ids) => self.logs.push(format!(
+                "Contagens criadas: {}",
+                ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", ")
+            )),
+            Err(err) => self.logs.push(format!("Erro: {}", err)),
+        }
+    }
+
+    fn render_replenishment(&mut self, ui: &mut egui::Ui) {
+        ui.label("Níveis mínimo e máximo por produto (deixe a fileira vazia para o total do armazém):");
+        ui.horizontal(|ui| {
+            ui.label("ID do Produto:");
+            ui.text_edit_singleline(&mut self.replenishment_form.num_id);
+            ui.label("Fileira de Picking:");
+            ui.text_edit_singleline(&mut self.replenishment_form.pick_zone);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Mínimo:");
+            ui.text_edit_singleline(&mut self.replenishment_form.min);
+            ui.label("Máximo:");
+            ui.text_edit_singleline(&mut self.replenishment_form.max);
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Definir Regra").clicked() {
+                let form = &self.replenishment_form;
+                let result = self.warehouse.authorize(Permission::Configure)
+                    .and_then(|_| Self::replenishment_key_from_form(form))
+                    .and_then(|(num_id, scope)| {
+                    let min = form.min.trim().parse::<u32>().map_err(|_| "Mínimo inválido.".to_string())?;
+                    let max = form.max.trim().parse::<u32>().map_err(|_| "Máximo inválido.".to_string())?;
+                    self.replenishment.set_rule(num_id, scope, min, max).map(|_| (num_id, scope, min, max))
+                });
+                match result {
+                    Ok((num_id, scope, min, max)) => self.logs.push(format!("Regra de reposição do ID {} ({}) definida: mín. {}, máx. {}", num_id, scope.label(), min, max)),
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+            if ui.button("Remover Regra").clicked() {
+                let result = self.warehouse.authorize(Permission::Configure)
+                    .and_then(|_| Self::replenishment_key_from_form(&self.replenishment_form))
+                    .and_then(|(num_id, scope)| self.replenishment.remove_rule(num_id, scope).map(|_| (num_id, scope)));
+                match result {
+                    Ok((num_id, scope)) => {
+                        self.replenishment_alerts.remove(&(num_id, scope));
+                        self.logs.push(format!("Regra de reposição do ID {} ({}) removida.", num_id, scope.label()));
+                    },
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+            if ui.button("Relatório de Reposição").clicked() {
+                self.logs.push("--------------------------------------".to_string());
+                self.logs.push("Relatório de reposição:".to_string());
+                let report = self.replenishment.report(&self.warehouse);
+                if report.is_empty() {
+                    self.logs.push("   Todos os produtos acima do mínimo.".to_string());
+                }
+                for suggestion in &report {
+                    let line = self.describe_replenishment(suggestion);
+                    self.logs.push(format!("   {}", line));
+                }
+                self.logs.push("--------------------------------------".to_string());
+            }
+        });
+
+        ui.separator();
+        for rule in &self.replenishment.rules {
+            let flag = if self.replenishment_alerts.contains(&(rule.num_id, rule.scope)) { " - abaixo do mínimo" } else { "" };
+            ui.label(format!("ID {} ({}): mín. {}, máx. {}{}", rule.num_id, rule.scope.label(), rule.min, rule.max, flag));
+        }
+    }
+
+    fn replenishment_key_from_form(form: &ReplenishmentForm) -> Result<(u32, ReplenishmentScope), String> {
+        let num_id = form.num_id.trim().parse::<u32>().map_err(|_| "Formato de ID inválido.".to_string())?;
+        let scope = match form.pick_zone.trim() {
+            "" => ReplenishmentScope::Total,
+            row => ReplenishmentScope::PickZone(row.parse::<u32>().map_err(|_| "Fileira inválida.".to_string())?),
+        };
+        Ok((num_id, scope))
+    }
+
+    fn describe_replenishment(&self, suggestion: &ReplenishmentSuggestion) -> String {
+        // Products that ran out are no longer in the warehouse, so fall back to the known names
+        let name = if suggestion.name.is_empty() {
+            self.name_to_id.iter().find(|(_, id)| **id == suggestion.num_id).map(|(n, _)| n.clone()).unwrap_or_default()
+        } else {
+            suggestion.name.clone()
+        };
+        let mut actions = Vec::new();
+        if suggestion.transfer > 0 {
+            actions.push(format!("transferir {} internamente", suggestion.transfer));
+        }
+        if suggestion.purchase > 0 {
+            actions.push(format!("comprar {}", suggestion.purchase));
+        }
+        format!(
+            "{} (ID {}), {}: disponível {} (mín. {}, máx. {}) -> {}",
+            name, suggestion.num_id, suggestion.scope.label(), suggestion.available, suggestion.min, suggestion.max, actions.join(", ")
+        )
+    }
+
+    // Logs an alert the first time a rule drops below its minimum, and again only after it recovered
+    fn check_replenishment_alerts(&mut self) {
+        if self.replenishment.rules.is_empty() {
+            return;
+        }
+        let report = self.replenishment.report(&self.warehouse);
+        let low: HashSet<(u32, ReplenishmentScope)> = report.iter().map(|s| (s.num_id, s.scope)).collect();
+        for suggestion in &report {
+            if !self.replenishment_alerts.contains(&(suggestion.num_id, suggestion.scope)) {
+                let line = self.describe_replenishment(suggestion);
+                self.logs.push(format!("Alerta de reposição: {}", line));
+            }
+        }
+        self.replenishment_alerts = low;
+    }
+
+    fn render_traceability(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Lote:");
+            ui.text_edit_singleline(&mut self.traceability_form.lot);
+            if ui.button("Buscar por Lote").clicked() {
+                let lot = self.traceability_form.lot.trim().to_string();
+                let results = self.warehouse.search_by_lot(&lot);
+                self.logs.push("--------------------------------------".to_string());
+                self.logs.push(format!("Lote {}:", lot));
+                if results.is_empty() {
+                    self.logs.push("   Nenhum registo deste lote no armazém.".to_string());
+                }
+                for (itm, locs) in &results {
+                    self.logs.push(format!("   {} (ID {}) x{} em {}", itm.name, itm.num_id, itm.item_quantity, Self::format_locations(locs)));
+                }
+                self.logs.push("--------------------------------------".to_string());
+            }
+            if ui.button("Relatório de Recolha").clicked() {
+                let report = recall_report(&self.warehouse, self.traceability_form.lot.trim());
+                self.logs.push("--------------------------------------".to_string());
+                self.logs.push(format!(
+                    "Relatório de recolha do lote {}: {} unidade(s) em armazém, {} expedida(s)",
+                    report.lot, report.on_hand(), report.shipped()
+                ));
+                self.logs.push("Localizações:".to_string());
+                for (itm, locs) in &report.stored {
+                    self.logs.push(format!("   {} (ID {}) x{} em {}", itm.name, itm.num_id, itm.item_quantity, Self::format_locations(locs)));
+                }
+                self.logs.push("Movimentos:".to_string());
+                for m in &report.movements {
+                    let serials = if m.serials.is_empty() { "".to_string() } else { format!(", Séries: {}", m.serials.join(", ")) };
+                    self.logs.push(format!("   [{}] {} - {} (ID {}), Quantidade: {:+}{}", m.timestamp, m.kind.label(), m.name, m.num_id, m.quantity_change, serials));
+                }
+                self.logs.push("--------------------------------------".to_string());
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Número de Série:");
+            ui.text_edit_singleline(&mut self.traceability_form.serial);
+            if ui.button("Buscar por Série").clicked() {
+                let serial = self.traceability_form.serial.trim().to_string();
+                match self.warehouse.search_by_serial(&serial) {
+                    Some((itm, locs)) => self.logs.push(format!(
+                        "Série {}: {} (ID {}, Lote: {}) em {}",
+                        serial, itm.name, itm.num_id, itm.lot.as_deref().unwrap_or("N/A"), Self::format_locations(&locs)
+                    )),
+                    None => self.logs.push(format!("Série {} não encontrada no armazém.", serial)),
+                }
+            }
+        });
+    }
+
+    fn format_locations(locations: &[Location]) -> String {
+        locations.iter().map(|l| format!("(F{},P{},N{},Z{})", l.row, l.shelf, l.level, l.zone)).collect::<Vec<_>>().join(", ")
+    }
+
+    fn render_csv(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Exportar para:");
+            ui.text_edit_singleline(&mut self.csv_form.export_path);
+            if ui.button("Exportar Inventário").clicked() {
+                let path = self.csv_form.export_path.trim().to_string();
+                match export_inventory(&self.warehouse, &path) {
+                    Ok(count) => self.logs.push(format!("Inventário exportado para {} ({} registo(s)).", path, count)),
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Importar de:");
+            ui.text_edit_singleline(&mut self.csv_form.import_path);
+            if ui.button("Importar Inventário").clicked() {
+                let path = self.csv_form.import_path.trim().to_string();
+                let (known_names, known_next_id) = (self.name_to_id.clone(), self.next_id);
+                let name_to_id = &mut self.name_to_id;
+                let next_id = &mut self.next_id;
+                let result = import_inventory(&mut self.warehouse, &path, |name, id| resolve_product_id(name_to_id, next_id, name, id));
+                // Nothing was imported, so the names seen in the file get no product id either
+                if !matches!(&result, Ok(report) if report.errors.is_empty()) {
+                    self.name_to_id = known_names;
+                    self.next_id = known_next_id;
+                }
+                match result {
+                    Ok(report) => {
+                        self.logs.push("--------------------------------------".to_string());
+                        self.logs.push(format!(
+                            "Importação de {}: {} linha(s) importada(s), {} com erro",
+                            path, report.imported.len(), report.errors.len()
+                        ));
+                        for (line, name, locs) in &report.imported {
+                            self.logs.push(format!("   Linha {}: {} em {}", line, name, Self::format_locations(locs)));
+                        }
+                        for (line, err) in &report.errors {
+                            self.logs.push(format!("   Linha {}: Erro: {}", line, err));
+                        }
+                        if !report.errors.is_empty() {
+                            self.logs.push("   Nenhuma linha foi importada; corrija os erros e importe o ficheiro de novo.".to_string());
+                        }
+                        self.logs.push("--------------------------------------".to_string());
+                    },
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+        });
+        ui.label("Colunas: id, name, quantity, quality, expiry (DD-MM-YYYY), min_level, max_level, zones, locations, lot, serials (separados por ;), unit_cost");
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Ficheiro JSON:");
+            ui.text_edit_singleline(&mut self.csv_form.json_path);
+            if ui.button("Exportar JSON").clicked() {
+                let path = self.csv_form.json_path.trim().to_string();
+                let site = self.take_site();
+                let json = site.to_json();
+                self.put_site(site);
+                let result = json
+                    .and_then(|json| std::fs::write(&path, json).map_err(|e| format!("Não foi possível escrever {}: {}", path, e)))
+                    .and_then(|_| self.sites.save(&sites_path(&path)));
+                match result {
+                    Ok(()) => self.logs.push(format!("Armazém exportado para {}.", path)),
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+            if ui.button("Importar JSON").clicked() {
+                let path = self.csv_form.json_path.trim().to_string();
+                let result = self.warehouse.authorize(Permission::Configure)
+                    .and_then(|_| std::fs::read_to_string(&path).map_err(|e| format!("Não foi possível ler {}: {}", path, e)))
+                    .and_then(|json| Site::from_json(&json))
+                    .and_then(|site| SiteRegistry::load(&sites_path(&path)).map(|sites| (site, sites)));
+                match result {
+                    Ok((site, sites)) => {
+                        // A snapshot without a sites file leaves the other sites and transfers as they are
+                        if let Some(sites) = sites {
+                            self.sites = sites;
+                        }
+                        let Site { warehouse, orders, inbound, cycle_counts, replenishment } = site;
+                        self.replace_warehouse(warehouse.map_storage(Cells::Memory));
+                        self.orders = orders;
+                        self.inbound = inbound;
+                        self.cycle_counts = cycle_counts;
+                        self.replenishment = replenishment;
+                        self.replenishment_alerts.clear();
+                        self.rebuild_name_index();
+                        self.route.clear();
+                        self.slotting_moves.clear();
+                        self.logs.push(format!("Armazém carregado de {} ({} registo(s)).", path, self.warehouse.grouped_items().len()));
+                    },
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+        });
+
+        #[cfg(feature = "sqlite")]
+        self.render_sqlite(ui);
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn render_sqlite(&mut self, ui: &mut egui::Ui) {
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Base de Dados SQLite:");
+            ui.text_edit_singleline(&mut self.csv_form.db_path);
+            if ui.button("Abrir").clicked() {
+                let path = self.csv_form.db_path.trim().to_string();
+                // An existing database replaces the warehouse in memory; a new one starts from it
+                let result = self.warehouse.authorize(Permission::Configure).and_then(|_| {
+                    self.close_database();
+                    let current = std::mem::replace(&mut self.warehouse, Warehouse::with_storage(Cells::default(), (0, 0, 0, 0)))
+                        .map_storage(Cells::into_memory);
+                    let opened = SqliteStorage::open_warehouse(&path, &current);
+                    self.warehouse = current.map_storage(Cells::Memory);
+                    opened
+                });
+                match result {
+                    Ok(warehouse) => {
+                        self.replace_warehouse(warehouse.map_storage(|storage| Cells::Database(Box::new(storage))));
+                        self.rebuild_name_index();
+                        self.route.clear();
+                        self.slotting_moves.clear();
+                        self.logs.push(format!("Base de dados {} aberta ({} registo(s)).", path, self.warehouse.grouped_items().len()));
+                    },
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+            if matches!(self.warehouse.storage, Cells::Database(_)) && ui.button("Fechar").clicked() {
+                self.close_database();
+                self.logs.push("Base de dados fechada.".to_string());
+            }
+        });
+    }
+
+    // Stock is written by each transaction; reservations, history and configuration follow once
+    // they change
+    #[cfg(feature = "sqlite")]
+    fn save_database(&mut self) {
+        if let Cells::Database(storage) = &self.warehouse.storage {
+            if let Err(err) = storage.save_state(&self.warehouse) {
+                self.logs.push(format!("Erro: {}", err));
+            }
+        }
+    }
+
+    // Keeps working on the same warehouse in memory
+    #[cfg(feature = "sqlite")]
+    fn close_database(&mut self) {
+        if matches!(self.warehouse.storage, Cells::Database(_)) {
+            self.save_database();
+            let warehouse = std::mem::replace(&mut self.warehouse, Warehouse::with_storage(Cells::default(), (0, 0, 0, 0)));
+            self.warehouse = warehouse.map_storage(|cells| Cells::Memory(cells.into_memory()));
+        }
+    }
+
+    fn render_valuation(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Método:");
+            egui::ComboBox::from_id_salt("valuation_method")
+                .selected_text(self.valuation_form.method.label())
+                .show_ui(ui, |ui| {
+                    for method in ValuationMethod::ALL {
+                        ui.selectable_value(&mut self.valuation_form.method, method, method.label());
+                    }
+                });
+            if ui.button("Calcular Valorização").clicked() {
+                let method = self.valuation_form.method;
+                let lines = valuation_report(&self.warehouse, method);
+                self.logs.push("--------------------------------------".to_string());
+                self.logs.push(format!("Valorização do stock ({}):", method.label()));
+                self.logs.push("Por produto:".to_string());
+                for ((num_id, name), (quantity, value)) in subtotals(&lines, |l| (l.num_id, l.name.clone())) {
+                    self.logs.push(format!("   {} (ID {}): {} -> {:.2}", name, num_id, self.warehouse.packaging.describe(num_id, quantity), value));
+                }
+                self.logs.push("Por fileira:".to_string());
+                for (row, (quantity, value)) in subtotals(&lines, |l| l.row) {
+                    self.logs.push(format!("   Fileira {}: {} unidade(s) -> {:.2}", row, quantity, value));
+                }
+                self.logs.push("Por classe de qualidade:".to_string());
+                for (quality, (quantity, value)) in subtotals(&lines, |l| l.quality.clone()) {
+                    self.logs.push(format!("   {}: {} unidade(s) -> {:.2}", quality, quantity, value));
+                }
+                let total: f64 = lines.iter().map(|l| l.value).sum();
+                self.logs.push(format!("Valor total: {:.2}", total));
+                self.logs.push("--------------------------------------".to_string());
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Ficheiro CSV:");
+            ui.text_edit_singleline(&mut self.valuation_form.path);
+            if ui.button("Exportar CSV").clicked() {
+                let method = self.valuation_form.method;
+                let lines = valuation_report(&self.warehouse, method);
+                let path = self.valuation_form.path.trim().to_string();
+                match export_csv(&lines, method, &path) {
+                    Ok(()) => self.logs.push(format!("Valorização exportada para {} ({} linha(s)).", path, lines.len())),
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+        });
+    }
+
+    fn render_routes(&mut self, ui: &mut egui::Ui) {
+        ui.label("Modelo de distâncias do armazém (metros):");
+        // The layout is warehouse configuration: shown to everyone, edited only with Configure
+        let can_configure = self.warehouse.authorize(Permission::Configure).is_ok();
+        let mut layout_changed = false;
+        ui.add_enabled_ui(can_configure, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Distância entre fileiras:");
+                layout_changed |= ui.add(egui::DragValue::new(&mut self.warehouse.layout.row_spacing).speed(0.1).range(0.1..=100.0)).changed();
+                ui.label("Largura da prateleira:");
+                layout_changed |= ui.add(egui::DragValue::new(&mut self.warehouse.layout.shelf_width).speed(0.1).range(0.1..=100.0)).changed();
+                ui.label("Largura da zona:");
+                layout_changed |= ui.add(egui::DragValue::new(&mut self.warehouse.layout.zone_width).speed(0.05).range(0.05..=10.0)).changed();
+            });
+            ui.horizontal(|ui| {
+                ui.label("Cais (x, y):");
+                layout_changed |= ui.add(egui::DragValue::new(&mut self.warehouse.layout.dock.0).speed(0.1)).changed();
+                layout_changed |= ui.add(egui::DragValue::new(&mut self.warehouse.layout.dock.1).speed(0.1)).changed();
+            });
+        });
+        if !can_configure {
+            ui.label(format!("Só um {} pode alterar o modelo de distâncias.", Permission::Configure.minimum_role().label()));
+        }
+        if layout_changed {
+            self.warehouse.mark_changed();
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("ID da Vaga:");
+            ui.text_edit_singleline(&mut self.route_form.wave_id);
+            if ui.button("Calcular Rota").clicked() {
+                let wave = self.route_form.wave_id.trim().parse::<u32>().ok()
+                    .and_then(|id| self.orders.waves.iter().find(|w| w.id == id));
+                match wave {
+                    Some(wave) => {
+                        let locations: Vec<Location> = wave.tasks.iter().filter(|t| !t.picked).map(|t| t.location.clone()).collect();
+                        let route = self.warehouse.optimise_route(&locations);
+                        self.show_route(format!("Rota da vaga {}", wave.id), route);
+                    },
+                    None => self.logs.push("Erro: Vaga não encontrada.".to_string()),
+                }
+            }
+            if ui.button("Limpar Rota").clicked() {
+                self.route.clear();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Lista de picking (ID:QTD, ID:QTD):");
+            ui.text_edit_singleline(&mut self.route_form.pick_lines);
+            if ui.button("Calcular Rota da Lista").clicked() {
+                let result = parse_order_lines(&self.route_form.pick_lines)
+                    .and_then(|lines| pick_list(&self.warehouse, &lines));
+                match result {
+                    Ok(stops) => {
+                        self.logs.push("--------------------------------------".to_string());
+                        self.logs.push("Lista de picking avulsa:".to_string());
+                        for stop in &stops {
+                            self.logs.push(format!(
+                                "   (F{},P{},N{},Z{}) - ID: {}, Nome: {}, Quantidade: {}{}",
+                                stop.location.row, stop.location.shelf, stop.location.level, stop.location.zone,
+                                stop.num_id, stop.name, stop.quantity,
+                                stop.lot.as_ref().map(|l| format!(", Lote: {}", l)).unwrap_or_default()
+                            ));
+                        }
+                        let route = stops.into_iter().map(|s| s.location).collect();
+                        self.show_route("Rota da lista de picking".to_string(), route);
+                    },
+                    Err(err) => self.logs.push(format!("Erro: {}", err)),
+                }
+            }
+        });
+        if ui.button("Rota das Contagens Pendentes").clicked() {
+            let route: Vec<Location> = self.cycle_counts.route(&self.warehouse).into_iter().map(|(_, loc)| loc).collect();
+            if route.is_empty() {
+                self.logs.push("Não há contagens pendentes.".to_string());
+            } else {
+                self.show_route("Rota das contagens pendentes".to_string(), route);
+            }
+        }
+    }
+
+    fn render_slotting(&mut self, ui: &mut egui::Ui) {
+        ui.label("Análise ABC por número de saídas e sugestões de relocalização:");
+        ui.horizontal(|ui| {
+            if ui.button("Analisar").clicked() {
+                self.logs.push("--------------------------------------".to_string());
+                self.logs.push("Análise ABC:".to_string());
+                for v in abc_analysis(&self.warehouse) {
+                    self.logs.push(format!("   {} - ID: {}, Nome: {}, Saídas: {}", v.class.label(), v.num_id, v.name, v.picks));
+                }
+                self.logs.push("--------------------------------------".to_string());
+                self.slotting_moves = slotting_advice(&self.warehouse);
+            }
+            if ui.button("Histórico de Movimentos").clicked() {
+                self.logs.push("--------------------------------------".to_string());
+                self.logs.push("Histórico de movimentos:".to_string());
+                let fmt_loc = |loc: &Option<Location>| match loc {
+                    Some(l) => format!("(F{},P{},N{},Z{})", l.row, l.shelf, l.level, l.zone),
+                    None => "-".to_string(),
+                };
+                for m in &self.warehouse.movements {
+                    self.logs.push(format!(
+                        "   [{}] {} - Registo: {}, ID: {}, Nome: {}, Quantidade: {:+}, De: {}, Para: {}{}",
+                        m.timestamp, m.kind.label(), m.record_id, m.num_id, m.name, m.quantity_change, fmt_loc(&m.from), fmt_loc(&m.to),
+                        m.reason.map(|r| format!(", Motivo: {}", r.label())).unwrap_or_default()
+                    ));
+                }
+                self.logs.push("--------------------------------------".to_string());
+            }
+            if !self.slotting_moves.is_empty() && ui.button("Aplicar Sugestões").clicked() {
+                // The stock may have changed since the advice was shown, so it is worked out again
+                // and applied as a whole or not at all
+                self.slotting_moves.clear();
+                let moves = slotting_advice(&self.warehouse);
+                let result = self.warehouse.transaction(|warehouse| {
+                    for m in &moves {
+                        warehouse.move_record(m.record_id, vec![m.to.clone()]).map_err(|err| format!("{}: {}", m.name, err))?;
+                    }
+                    Ok(())
+                });
+                match result {
+                    Ok(()) => {
+                        for m in &moves {
+                            self.logs.push(format!(
+                                "{} movido de (F{},P{},N{},Z{}) para (F{},P{},N{},Z{})",
+                                m.name, m.from.row, m.from.shelf, m.from.level, m.from.zone, m.to.row, m.to.shelf, m.to.level, m.to.zone
+                            ));
+                        }
+                        if moves.is_empty() {
+                            self.logs.push("Já não há sugestões de relocalização a aplicar.".to_string());
+                        }
+                    },
+                    Err(err) => self.logs.push(format!("Erro ao aplicar sugestões, nenhum item foi movido: {}", err)),
+                }
+            }
+        });
+
+        ui.separator();
+        if self.slotting_moves.is_empty() {
+            ui.label("Sem sugestões de relocalização.");
+        }
+        for m in &self.slotting_moves {
+            ui.label(format!(
+                "[{}] {} (ID {}): (F{},P{},N{},Z{}) -> (F{},P{},N{},Z{}), poupa {:.1} m",
+                m.class.label(), m.name, m.num_id,
+                m.from.row, m.from.shelf, m.from.level, m.from.zone,
+                m.to.row, m.to.shelf, m.to.level, m.to.zone,
+                m.saving
+            ));
+        }
+    }
+
+    fn show_route(&mut self, title: String, route: Vec<Location>) {
+        self.logs.push("--------------------------------------".to_string());
+        self.logs.push(format!("{} ({:.1} m):", title, self.warehouse.route_length(&route)));
+        for (step, loc) in route.iter().enumerate() {
+            self.logs.push(format!("   {}. (F{},P{},N{},Z{})", step + 1, loc.row, loc.shelf, loc.level, loc.zone));
+        }
+        self.logs.push("--------------------------------------".to_string());
+        self.route = route;
+    }
+
+    fn log_pick_list(&mut self, wave_id: u32) {
+        self.logs.push("--------------------------------------".to_string());
+        self.logs.push(format!("Lista de picking da vaga {}:", wave_id));
+        if let Some(wave) = self.orders.waves.iter().find(|w| w.id == wave_id) {
+            for task in &wave.tasks {
+                self.logs.push(format!(
+                    "   (F{},P{},N{},Z{}) ID: {}, Nome: {}, Quantidade: {}, Encomenda: ENC-{}",
+                    task.location.row, task.location.shelf, task.location.level, task.location.zone, task.num_id, task.name, task.quantity, task.order_id
+                ));
+            }
+        }
+        self.logs.push("--------------------------------------".to_string());
+    }
+
+    fn render_check_expiring_by_date(&mut self, ui: &mut egui::Ui) {
+        ui.label("Insira a data de referência (DD-MM-YYYY):");
+        ui.text_edit_singleline(&mut self.check_expiring_form.date);
+
+        if ui.button("Verificar").clicked() {
+            if let Some(ref_date) = parse_date(&self.check_expiring_form.date) {
+                let results = self.warehouse.find_expiring_items_by_date(ref_date);
+                self.logs.push("--------------------------------------".to_string());
+                self.logs.push(format!("A verificar itens a partir da data {}:", ref_date.format("%d-%m-%Y")));
+                if results.is_empty() {
+                    self.logs.push("   Nenhum item expirado ou próximo de expirar.".to_string());
+                } else {
+                    for (itm, status, locs) in results {
+                        self.logs.push("--------------------------------------".to_string());
+                        self.logs.push(format!("ID: {}, Nome: {}, Status: {}", itm.num_id, itm.name, status));
+                        self.logs.push("Localizações:".to_string());
+                        for loc in locs {
+                            self.logs.push(format!("   (F{},P{},N{},Z{})", loc.row, loc.shelf, loc.level, loc.zone));
+                        }
+                    }
+                }
+                self.logs.push("--------------------------------------".to_string());
+                self.current_action = "Verificação de Validade Concluída".to_string();
+            } else {
+                let msg = "Data inválida. Use DD-MM-YYYY.".to_string();
+                self.logs.push(msg);
+                self.current_action = "Erro na Verificação de Validade".to_string();
+            }
+        }
+    }
+
+    fn validate_segregation(&mut self) {
+        let violations = self.warehouse.validate_segregation();
+        self.logs.push("--------------------------------------".to_string());
+        self.logs.push("Validação de segregação:".to_string());
+        if violations.is_empty() {
+            self.logs.push("   Nenhuma violação encontrada.".to_string());
+        } else {
+            for v in violations {
+                self.logs.push(format!(
+                    "   {} (ID {}) em (F{},P{},N{},Z{}) e {} (ID {}) em (F{},P{},N{},Z{})",
+                    v.first.name, v.first.num_id, v.first_location.row, v.first_location.shelf, v.first_location.level, v.first_location.zone,
+                    v.second.name, v.second.num_id, v.second_location.row, v.second_location.shelf, v.second_location.level, v.second_location.zone,
+                ));
+                self.logs.push(format!("      {} / {} exigem separação de {}", v.groups.0.label(), v.groups.1.label(), v.separation.label()));
+            }
+        }
+        self.logs.push("--------------------------------------".to_string());
+    }
+
+    fn render_segregation_rules(&mut self, ui: &mut egui::Ui) {
+        let groups: Vec<SegregationGroup> = std::iter::once(SegregationGroup::Food)
+            .chain(HazardClass::ALL.into_iter().map(SegregationGroup::Hazard))
+            .collect();
+
+        ui.label("Defina a separação mínima entre dois grupos:");
+        ui.horizontal(|ui| {
+            ui.label("Grupo A:");
+            egui::ComboBox::from_id_salt("segregation_first")
+                .selected_text(self.segregation_rule_form.first.label())
+                .show_ui(ui, |ui| {
+                    for g in &groups {
+                        ui.selectable_value(&mut self.segregation_rule_form.first, *g, g.label());
+                    }
+                });
+            ui.label("Grupo B:");
+            egui::ComboBox::from_id_salt("segregation_second")
+                .selected_text(self.segregation_rule_form.second.label())
+                .show_ui(ui, |ui| {
+                    for g in &groups {
+                        ui.selectable_value(&mut self.segregation_rule_form.second, *g, g.label());
+                    }
+                });
+        });
+
+        let current = match self.warehouse.segregation.rule(self.segregation_rule_form.first, self.segregation_rule_form.second) {
+            Some(separation) => separation.label(),
+            None => "sem restrição".to_string(),
+        };
+        ui.label(format!("Regra atual: {}", current));
+
+        ui.horizontal(|ui| {
+            ui.label("Separação:");
+            egui::ComboBox::from_id_salt("segregation_kind")
+                .selected_text(self.segregation_rule_form.kind)
+                .show_ui(ui, |ui| {
+                    for kind in ["Zonas", "Prateleiras", "Fileiras"] {
+                        ui.selectable_value(&mut self.segregation_rule_form.kind, kind, kind);
+                    }
+                });
+            ui.text_edit_singleline(&mut self.segregation_rule_form.distance);
+        });
+
+        if ui.button("Guardar Regra").clicked() {
+            let distance = self.warehouse.authorize(Permission::Configure).and_then(|_| {
+                self.segregation_rule_form.distance.trim().parse::<u32>().map_err(|_| "Distância de separação inválida.".to_string())
+            });
+            match distance {
+                Ok(distance) => {
+                    let separation = match self.segregation_rule_form.kind {
+                        "Zonas" => Separation::Zones(distance),
+                        "Fileiras" => Separation::Rows(distance),
+                        _ => Separation::Shelves(distance),
+                    };
+                    self.warehouse.segregation.set_rule(self.segregation_rule_form.first, self.segregation_rule_form.second, separation);
+                    self.warehouse.mark_changed();
+                    self.logs.push(format!(
+                        "Regra de segregação atualizada: {} / {} -> {}",
+                        self.segregation_rule_form.first.label(),
+                        self.segregation_rule_form.second.label(),
+                        separation.label(),
+                    ));
+                    self.current_action = "Editing Segregation Rules".to_string();
+                },
+                Err(err) => {
+                    self.logs.push(format!("Erro: {}", err));
+                }
+            }
+        }
+    }
+
+    fn render_login(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Gestor de Armazém");
+        ui.separator();
+        if let Some(err) = &self.users_error {
+            ui.label(format!("Erro: {}", err));
+            ui.label(format!("Corrija ou remova o ficheiro {} e reinicie a aplicação.", USERS_FILE));
+            return;
+        }
+
+        // The first account is always an administrator, so that someone can manage the others
+        let first_run = self.users.is_empty();
+        if first_run {
+            ui.label("Ainda não existem utilizadores. Crie a conta de administrador:");
+        } else {
+            ui.label("Inicie sessão:");
+        }
+        ui.horizontal(|ui| {
+            ui.label("Utilizador:");
+            ui.text_edit_singleline(&mut self.login_form.username);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Palavra-passe:");
+            ui.add(egui::TextEdit::singleline(&mut self.login_form.password).password(true));
+        });
+
+        if ui.button(if first_run { "Criar Administrador" } else { "Entrar" }).clicked() {
+            let username = self.login_form.username.trim().to_string();
+            let password = self.login_form.password.clone();
+            let created = if first_run {
+                self.users.add_user(&username, &password, Role::Admin).and_then(|_| self.users.save(USERS_FILE))
+            } else {
+                Ok(())
+            };
+            if created.is_err() {
+                self.users = UserDirectory::default();
+            }
+            match created.and_then(|_| self.users.authenticate(&username, &password)) {
+                Ok(user) => {
+                    self.warehouse.sign_in(&user);
+                    self.login_form.password.clear();
+                    self.login_form.message.clear();
+                    self.logs.push(format!("Sessão iniciada: {} ({}).", user.username, user.role.label()));
+                },
+                Err(err) => self.login_form.message = format!("Erro: {}", err),
+            }
+        }
+        if !self.login_form.message.is_empty() {
+            ui.label(&self.login_form.message);
+        }
+    }
+
+    fn render_users(&mut self, ui: &mut egui::Ui) {
+        if let Err(err) = self.warehouse.authorize(Permission::ManageUsers) {
+            ui.label(err);
+            return;
+        }
+
+        ui.label("Utilizadores registados:");
+        // (username, new role), or no role to remove the account
+        let mut change = None;
+        for user in self.users.users() {
+            ui.horizontal(|ui| {
+                ui.label(&user.username);
+                let mut role = user.role;
+                egui::ComboBox::from_id_salt(("user_role", &user.username))
+                    .selected_text(role.label())
+                    .show_ui(ui, |ui| {
+                        for option in Role::ALL {
+                            ui.selectable_value(&mut role, option, option.label());
+                        }
+                    });
+                if role != user.role {
+                    change = Some((user.username.clone(), Some(role)));
+                }
+                if ui.button("Remover").clicked() {
+                    change = Some((user.username.clone(), None));
+                }
+            });
+        }
+        if let Some((username, role)) = change {
+            let result = match role {
+                Some(role) => self.users.set_role(&username, role).map(|_| format!("Perfil de {} alterado para {}.", username, role.label())),
+                None => self.users.remove_user(&username).map(|_| format!("Utilizador {} removido.", username)),
+            };
+            match result.and_then(|message| self.users.save(USERS_FILE).map(|_| message)) {
+                Ok(message) => {
+                    self.logs.push(message);
+                    self.refresh_session();
+                },
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+
+        ui.separator();
+        ui.label("Novo utilizador:");
+        ui.horizontal(|ui| {
+            ui.label("Utilizador:");
+            ui.text_edit_singleline(&mut self.user_form.username);
+            ui.label("Palavra-passe:");
+            ui.add(egui::TextEdit::singleline(&mut self.user_form.password).password(true));
+            ui.label("Perfil:");
+            egui::ComboBox::from_id_salt("new_user_role")
+                .selected_text(self.user_form.role.label())
+                .show_ui(ui, |ui| {
+                    for role in Role::ALL {
+                        ui.selectable_value(&mut self.user_form.role, role, role.label());
+                    }
+                });
+        });
+        if ui.button("Criar Utilizador").clicked() {
+            let username = self.user_form.username.trim().to_string();
+            let role = self.user_form.role;
+            let result = self.users.add_user(&username, &self.user_form.password, role)
+                .and_then(|_| self.users.save(USERS_FILE));
+            match result {
+                Ok(()) => {
+                    self.user_form.username.clear();
+                    self.user_form.password.clear();
+                    self.logs.push(format!("Utilizador {} criado ({}).", username, role.label()));
+                },
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+    }
+
+    // Role changes and removals of the signed in account apply straight away
+    fn refresh_session(&mut self) {
+        let Some(username) = self.warehouse.session().map(|session| session.username.clone()) else {
+            return;
+        };
+        match self.users.users().iter().find(|user| user.username == username).cloned() {
+            Some(user) => self.warehouse.sign_in(&user),
+            None => {
+                self.stamp_logs();
+                self.warehouse.sign_out();
+            },
+        }
+    }
+
+    fn signed_in_user(&self) -> Option<User> {
+        let session = self.warehouse.session()?;
+        self.users.users().iter().find(|user| user.username == session.username).cloned()
+    }
+
+    // A warehouse loaded from a file or database keeps whoever is signed in
+    fn replace_warehouse(&mut self, warehouse: Warehouse<Cells>) {
+        let user = self.signed_in_user();
+        self.warehouse = warehouse;
+        if let Some(user) = user {
+            self.warehouse.sign_in(&user);
+        }
+    }
+
+    // The active site's warehouse and managers, leaving empty ones in their place
+    fn take_site(&mut self) -> Site<Cells> {
+        Site {
+            warehouse: std::mem::replace(&mut self.warehouse, Warehouse::with_storage(Cells::default(), (0, 0, 0, 0))),
+            orders: std::mem::take(&mut self.orders),
+            inbound: std::mem::take(&mut self.inbound),
+            cycle_counts: std::mem::take(&mut self.cycle_counts),
+            replenishment: std::mem::take(&mut self.replenishment),
+        }
+    }
+
+    fn put_site(&mut self, site: Site<Cells>) {
+        self.warehouse = site.warehouse;
+        self.orders = site.orders;
+        self.inbound = site.inbound;
+        self.cycle_counts = site.cycle_counts;
+        self.replenishment = site.replenishment;
+    }
+
+    // Orders, receipts, counts and replenishment rules are swapped along with the warehouse;
+    // product names and ids are shared by every site
+    fn switch_site(&mut self, name: &str) {
+        #[cfg(feature = "sqlite")]
+        if matches!(self.warehouse.storage, Cells::Database(_)) {
+            self.close_database();
+            self.logs.push("Base de dados fechada.".to_string());
+        }
+
+        let user = self.signed_in_user();
+        let mut site = self.take_site().map_storage(Cells::into_memory);
+        let result = self.sites.switch_to(name, &mut site);
+        self.put_site(site.map_storage(Cells::Memory));
+
+        match result {
+            Ok(()) => {
+                if let Some(user) = user {
+                    self.warehouse.sign_in(&user);
+                }
+                self.replenishment_alerts.clear();
+                self.route.clear();
+                self.slotting_moves.clear();
+                self.rebuild_name_index();
+                self.logs.push(format!("Armazém ativo: {}.", name));
+            },
+            Err(err) => self.logs.push(format!("Erro: {}", err)),
+        }
+    }
+
+    fn render_sites(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!("Armazém ativo: {}", self.sites.active()));
+
+        ui.separator();
+        ui.label("Novo armazém:");
+        ui.horizontal(|ui| {
+            ui.label("Nome:");
+            ui.text_edit_singleline(&mut self.site_form.name);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Fileiras:");
+            ui.text_edit_singleline(&mut self.site_form.rows);
+            ui.label("Prateleiras:");
+            ui.text_edit_singleline(&mut self.site_form.shelves);
+            ui.label("Níveis:");
+            ui.text_edit_singleline(&mut self.site_form.levels);
+            ui.label("Zonas:");
+            ui.text_edit_singleline(&mut self.site_form.zones);
+        });
+        if ui.button("Criar Armazém").clicked() {
+            let form = &self.site_form;
+            let dimension = |text: &str, what: &str| text.trim().parse::<u32>().map_err(|_| format!("{} inválido(as).", what));
+            let result = self.warehouse.authorize(Permission::Configure).and_then(|_| {
+                let dimensions = (
+                    dimension(&form.rows, "Fileiras")?,
+                    dimension(&form.shelves, "Prateleiras")?,
+                    dimension(&form.levels, "Níveis")?,
+                    dimension(&form.zones, "Zonas")?,
+                );
+                self.sites.add_site(&form.name, dimensions, &self.warehouse).map(|_| dimensions)
+            });
+            match result {
+                Ok((rows, shelves, levels, zones)) => {
+                    self.logs.push(format!("Armazém {} criado ({} x {} x {} x {}).", self.site_form.name.trim(), rows, shelves, levels, zones));
+                    self.site_form.name.clear();
+                },
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+
+        ui.separator();
+        if ui.button("Stock por Produto (todos os armazéns)").clicked() {
+            let stock = self.sites.stock_by_product(&self.warehouse);
+            self.logs.push("--------------------------------------".to_string());
+            self.logs.push("Stock por produto em todos os armazéns:".to_string());
+            self.logs.push("--------------------------------------".to_string());
+            if stock.is_empty() {
+                self.logs.push("   Nenhum item em stock.".to_string());
+            }
+            for product in stock {
+                let sites: Vec<String> = product.by_site.iter().map(|(site, quantity)| format!("{}: {}", site, quantity)).collect();
+                self.logs.push(format!("ID {} ({}): total {}", product.num_id, product.name, product.total()));
+                self.logs.push(format!("   {} | Em trânsito: {}", sites.join(" | "), product.in_transit));
+            }
+        }
+
+        ui.separator();
+        ui.label("Transferir a partir do armazém ativo:");
+        ui.horizontal(|ui| {
+            ui.label("Destino:");
+            egui::ComboBox::from_id_salt("transfer_to")
+                .selected_text(&self.site_form.transfer_to)
+                .show_ui(ui, |ui| {
+                    for name in self.sites.names().into_iter().filter(|n| n != self.sites.active()) {
+                        ui.selectable_value(&mut self.site_form.transfer_to, name.clone(), name);
+                    }
+                });
+            ui.label("ID do Produto:");
+            ui.text_edit_singleline(&mut self.site_form.transfer_id);
+            ui.label("Quantidade:");
+            ui.text_edit_singleline(&mut self.site_form.transfer_quantity);
+        });
+        if ui.button("Expedir Transferência").clicked() {
+            let form = &self.site_form;
+            let result = form.transfer_id.trim().parse::<u32>().map_err(|_| "ID inválido.".to_string())
+                .and_then(|num_id| {
+                    let quantity = form.transfer_quantity.trim().parse::<u32>().map_err(|_| "Quantidade inválida.".to_string())?;
+                    self.sites.ship(&form.transfer_to, num_id, quantity, &mut self.warehouse)
+                });
+            match result {
+                Ok(id) => {
+                    if let Some(transfer) = self.sites.transfers.iter().find(|t| t.id == id) {
+                        self.logs.push(format!("Transferência {} expedida: {} x {} de {} para {}.", id, transfer.quantity, transfer.name, transfer.from, transfer.to));
+                    }
+                },
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+
+        ui.separator();
+        ui.label("Transferências:");
+        let mut to_receive = None;
+        for transfer in self.sites.transfers.iter().rev() {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "#{} {} x {} (ID {}): {} -> {} [{}]",
+                    transfer.id, transfer.quantity, transfer.name, transfer.num_id, transfer.from, transfer.to, transfer.status.label(),
+                ));
+                if transfer.status == TransferStatus::InTransit && transfer.to == self.sites.active() && ui.button("Receber").clicked() {
+                    to_receive = Some(transfer.id);
+                }
+            });
+        }
+        if let Some(id) = to_receive {
+            match self.sites.receive(id, &mut self.warehouse) {
+                Ok(locations) => {
+                    self.rebuild_name_index();
+                    self.logs.push(format!("Transferência {} recebida em {} ({} localização(ões)).", id, self.sites.active(), locations.len()));
+                },
+                Err(err) => self.logs.push(format!("Erro: {}", err)),
+            }
+        }
+    }
+
+    // Prefixes the log lines written since the last call with the signed in user
+    fn stamp_logs(&mut self) {
+        if let Some(session) = self.warehouse.session() {
+            for line in &mut self.logs[self.stamped_logs..] {
+                if !line.is_empty() && !line.starts_with("---") && !line.starts_with(char::is_whitespace) {
+                    *line = format!("[{}] {}", session.username, line);
+                }
+            }
+        }
+        self.stamped_logs = self.logs.len();
+    }
+
+    fn render_climate_zones(&mut self, ui: &mut egui::Ui) {
+        ui.label("Atribua uma zona climática a uma fileira, prateleira ou nível:");
+        ui.horizontal(|ui| {
+            ui.label("Âmbito:");
+            egui::ComboBox::from_id_salt("climate_scope")
+                .selected_text(self.climate_form.scope)
+                .show_ui(ui, |ui| {
+                    for scope in ["Fileira", "Prateleira", "Nível"] {
+                        ui.selectable_value(&mut self.climate_form.scope, scope, scope);
+                    }
+                });
+            ui.label("Clima:");
+            egui::ComboBox::from_id_salt("climate_zone")
+                .selected_text(self.climate_form.zone.label())
+                .show_ui(ui, |ui| {
+                    for zone in ClimateZone::ALL {
+                        ui.selectable_value(&mut self.climate_form.zone, zone, zone.label());
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Fileira:");
+            ui.text_edit_singleline(&mut self.climate_form.row);
+            if self.climate_form.scope != "Fileira" {
+                ui.label("Prateleira:");
+                ui.text_edit_singleline(&mut self.climate_form.shelf);
+            }
+            if self.climate_form.scope == "Nível" {
+                ui.label("Nível:");
+                ui.text_edit_singleline(&mut self.climate_form.level);
+            }
+        });
+
+        if ui.button("Atribuir").clicked() {
+            let zone = self.climate_form.zone;
+            match self.warehouse.authorize(Permission::Configure)
+                .and_then(|_| self.climate_scope_from_form())
+                .and_then(|scope| self.warehouse.assign_climate(scope, zone).map(|_| scope)) {
+                Ok(scope) => {
+                    self.logs.push(format!("Zona climática {} atribuída a ({})", self.climate_form.zone.label(), scope.label()));
+                    self.current_action = "Editing Climate Zones".to_string();
+                },
+                Err(err) => {
+                    self.logs.push(format!("Erro: {}", err));
+                }
+            }
+        }
+    }
+
+    fn climate_scope_from_form(&self) -> Result<ClimateScope, String> {
+        let (rows, shelves, levels, _) = self.warehouse.max_dimensions;
+        let row = self.climate_form.row.trim().parse::<u32>().map_err(|_| "Número de fileira inválido.".to_string())?;
+        if row >= rows {
+            return Err("Localização excede as dimensões do armazém.".to_string());
+        }
+        if self.climate_form.scope == "Fileira" {
+            return Ok(ClimateScope::Row(row));
+        }
+        let shelf = self.climate_form.shelf.trim().parse::<u32>().map_err(|_| "Número de prateleira inválido.".to_string())?;
+        if shelf >= shelves {
+            return Err("Localização excede as dimensões do armazém.".to_string());
+        }
+        if self.climate_form.scope == "Prateleira" {
+            return Ok(ClimateScope::Shelf(row, shelf));
+        }
+        let level = self.climate_form.level.trim().parse::<u32>().map_err(|_| "Número de nível inválido.".to_string())?;
+        if level >= levels {
+            return Err("Localização excede as dimensões do armazém.".to_string());
+        }
+        Ok(ClimateScope::Level(row, shelf, level))
+    }
+
+    fn list_items(&self) -> String {
+        let mut grouped = self.warehouse.grouped_items();
+        grouped.sort_by(|(a,_),(b,_)| a.name.cmp(&b.name));
+
+        let mut items_strs = Vec::new();
+        for (item, locs) in grouped {
+            let date_str = if let Some(dv) = item.data_validade {
+                dv.format("%d-%m-%Y").to_string()
+            } else {
+                "N/A".to_string()
+            };
+            let mut extras = Vec::new();
+            if item.is_fragile() {
+                extras.push(format!("Validade: {}", date_str));
+            }
+            if let Some((nivel_minimo, nivel_maximo)) = item.level_range() {
+                extras.push(format!("Níveis: {} a {}", nivel_minimo, nivel_maximo));
+            }
+            if item.is_oversized() {
+                let (zones, levels, shelves) = item.footprint();
+                extras.push(format!("Dimensões: {} zonas x {} níveis x {} prateleiras", zones, levels, shelves));
+            }
+            if item.unit_cost > 0.0 {
+                extras.push(format!("Custo Unitário: {:.2}", item.unit_cost));
+            }
+            if let Some(lot) = &item.lot {
+                extras.push(format!("Lote: {}", lot));
+            }
+            if item.is_serialised() {
+                extras.push(format!("Séries: {}", item.serials.join(", ")));
+            }
+            if let Some(provenance) = &item.provenance {
+                extras.push(format!("Fornecedor: {}, PO: {}", provenance.supplier, provenance.po_reference));
+            }
+            let extra = if extras.is_empty() { "N/A".to_string() } else { extras.join(", ") };
+
+            items_strs.push(format!(
+                "Nome: {}, ID: {}, Quantidade: {}, Qualidade: {}, Timestamp: {}, {}\n   Localizações: {}",
+                item.name,
+                item.num_id,
+                self.warehouse.packaging.describe(item.num_id, item.item_quantity),
+                item.quality_label(),
+                item.timestamp,
+                extra,
+                locs.iter().map(|l| format!("(F{},P{},N{},Z{})", l.row, l.shelf, l.level, l.zone)).collect::<Vec<_>>().join(", ")
+            ));
+        }
+        items_strs.join("\n--------------------------------------\n")
+    }
+
+    fn create_item_from_form(&self) -> Result<Item, String> {
+        let name = self.add_item_form.name.trim().to_string();
+        if name.is_empty() {
+            return Err("Nome não pode estar vazio.".to_string());
+        }
+        let quantity = self.add_item_form.quantity.trim().parse::<u32>().map_err(|_| "Quantidade inválida.".to_string())?;
+        // Stock is always kept in the product's base unit
+        let level = self.add_item_form.packaging_level;
+        let quantity = match self.name_to_id.get(&name) {
+            Some(num_id) => self.warehouse.packaging.to_base(*num_id, quantity, level)?,
+            None if level == PackagingLevel::Each => quantity,
+            None => return Err("Produto novo sem embalagens definidas; use a unidade base.".to_string()),
+        };
+
+        let mut attributes = Vec::new();
+        let mut data_validade = None;
+
+        if self.add_item_form.fragile {
+            data_validade = Some(parse_date(&self.add_item_form.data_validade).ok_or("Formato de data inválido. Use DD-MM-YYYY.")?);
+            if self.add_item_form.nivel_maximo.trim().is_empty() {
+                return Err("Item frágil requer um nível máximo de armazenamento.".to_string());
+            }
+            attributes.push(HandlingAttribute::Fragile);
+        }
+
+        let levels = self.warehouse.max_dimensions.2;
+        let nivel_minimo = self.add_item_form.nivel_minimo.trim();
+        let nivel_maximo = self.add_item_form.nivel_maximo.trim();
+        if !nivel_minimo.is_empty() || !nivel_maximo.is_empty() {
+            let nivel_minimo = if nivel_minimo.is_empty() { 0 } else { nivel_minimo.parse::<u32>().map_err(|_| "Nível mínimo inválido.".to_string())? };
+            let nivel_maximo = if nivel_maximo.is_empty() { levels.saturating_sub(1) } else { nivel_maximo.parse::<u32>().map_err(|_| "Nível máximo inválido.".to_string())? };
+            attributes.push(HandlingAttribute::LevelRange { nivel_minimo, nivel_maximo });
+        }
+        if self.add_item_form.oversized {
+            let required_zones = self.add_item_form.required_zones.trim().parse::<u32>().map_err(|_| "Número de zonas contíguas inválido.".to_string())?;
+            // Empty level/shelf counts keep the old single-row behaviour
+            let required_levels = self.parse_footprint_field(&self.add_item_form.required_levels, "Número de níveis contíguos inválido.")?;
+            let required_shelves = self.parse_footprint_field(&self.add_item_form.required_shelves, "Número de prateleiras contíguas inválido.")?;
+            attributes.push(HandlingAttribute::Oversized { required_zones, required_levels, required_shelves });
+        }
+        if self.add_item_form.hazardous {
+            attributes.push(HandlingAttribute::Hazardous(self.add_item_form.hazard_class));
+        }
+        if self.add_item_form.food {
+            attributes.push(HandlingAttribute::Food);
+        }
+        if self.add_item_form.climate != ClimateZone::Ambient {
+            attributes.push(HandlingAttribute::Climate(self.add_item_form.climate));
+        }
+        if self.add_item_form.high_value {
+            attributes.push(HandlingAttribute::HighValue);
+        }
+        if self.add_item_form.stack_limited {
+            let stack_limit = self.add_item_form.stack_limit.trim().parse::<u32>().map_err(|_| "Limite de empilhamento inválido.".to_string())?;
+            attributes.push(HandlingAttribute::StackLimit(stack_limit));
+        }
+
+        let mut item = Item::new(
+            0,
+            name,
+            quantity,
+            attributes,
+            data_validade,
+        );
+        let lot = self.add_item_form.lot.trim();
+        if !lot.is_empty() {
+            item.lot = Some(lot.to_string());
+        }
+        item.serials = self.add_item_form.serials
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        item.validate_levels(levels)?;
+        item.validate_tracking()?;
+        let unit_cost = self.add_item_form.unit_cost.trim().replace(',', ".");
+        if !unit_cost.is_empty() {
+            item.unit_cost = unit_cost.parse::<f64>()
+                .ok()
+                .filter(|c| c.is_finite() && *c >= 0.0)
+                .ok_or("Custo unitário inválido.".to_string())?;
+        }
+        Ok(item)
+    }
+
+    fn parse_footprint_field(&self, value: &str, error: &str) -> Result<u32, String> {
+        let value = value.trim();
+        if value.is_empty() {
+            Ok(1)
+        } else {
+            value.parse::<u32>().map_err(|_| error.to_string())
+        }
+    }
+
+    fn render_warehouse(&mut self, ui: &mut egui::Ui) {
+        let row_spacing = 40.0;
+        let shelf_spacing = 20.0;
+        let level_spacing = 2.0;
+        let zone_spacing = 1.0;
+
+        let (rows, shelves, levels, zones) = self.warehouse.max_dimensions;
+
+        let available_rect = ui.available_rect_before_wrap();
+        ui.allocate_rect(available_rect, egui::Sense::hover());
+
+        let width = available_rect.width();
+        let height = available_rect.height();
+
+        let shelves_float = shelves as f32;
         let zones_float = zones as f32;
         let rows_float = rows as f32;
         let levels_float = levels as f32;
@@ -571,6 +2792,7 @@ impl App {
         let cell_size = base_cell_size * self.grid_scale;
 
         let painter = ui.painter();
+        let mut cell_rects = HashMap::new();
 
         for row in 0..rows {
             let mut y = available_rect.min.y;
@@ -602,38 +2824,72 @@ impl App {
                         );
 
                         let location = Location { row, shelf, level, zone };
-                        let item = self.warehouse.items.get(&location);
-
-                        let color = if let Some(item) = item {
-                            self.color_for_item(item)
-                        } else {
-                            egui::Color32::from_gray(180)
-                        };
+                        let item = self.warehouse.item_at(&location);
 
-                        painter.rect_filled(cell_rect, 0.0, color);
+                        // The climate colour fills the whole cell so it stays visible around stored items
+                        painter.rect_filled(cell_rect, 0.0, self.color_for_climate(self.warehouse.climate.zone_at(&location)));
+                        if let Some(item) = item {
+                            painter.rect_filled(cell_rect.shrink(cell_size * 0.15), 0.0, self.color_for_item(item));
+                        }
+                        cell_rects.insert(location, cell_rect);
                     }
                 }
             }
         }
+
+        // Preview of the suggested slotting moves
+        let move_stroke = egui::Stroke::new(2.0, egui::Color32::BLUE);
+        for m in &self.slotting_moves {
+            if let (Some(from), Some(to)) = (cell_rects.get(&m.from), cell_rects.get(&m.to)) {
+                painter.arrow(from.center(), to.center() - from.center(), move_stroke);
+            }
+        }
+
+        if !self.route.is_empty() && rows > 0 {
+            // The front cross aisle is drawn along the left edge of the grid; the dock goes beside the
+            // row nearest its y coordinate (its x, the distance into the aisles, is not drawn)
+            let dock_row = ((self.warehouse.layout.dock.1 / self.warehouse.layout.row_spacing).round().max(0.0) as u32).min(rows - 1);
+            let dock_y = cell_rects.get(&Location { row: dock_row, shelf: 0, level: 0, zone: 0 })
+                .map(|r| r.center().y)
+                .unwrap_or(available_rect.min.y);
+            let dock = egui::pos2(available_rect.min.x, dock_y);
+
+            let mut points = vec![dock];
+            points.extend(self.route.iter().filter_map(|loc| cell_rects.get(loc)).map(|r| r.center()));
+            points.push(dock);
+
+            let stroke = egui::Stroke::new(2.0, egui::Color32::RED);
+            painter.add(egui::Shape::line(points.clone(), stroke));
+            for point in &points[1..points.len() - 1] {
+                painter.circle_filled(*point, 3.0, egui::Color32::RED);
+            }
+            painter.circle_filled(dock, 6.0, egui::Color32::DARK_RED);
+        }
     }
 
     fn render_legend(&self, ui: &mut egui::Ui) {
         let mut item_map = HashMap::new();
-        for item in self.warehouse.items.values() {
-            let color = self.color_for_quality_name(item.item_quality.clone(), &item.name);
-            item_map.entry((item.item_quality.clone(), item.name.clone())).or_insert(color);
+        for (_, item) in self.warehouse.items() {
+            let color = self.color_for_item(item);
+            item_map.entry((item.quality_label(), item.name.clone())).or_insert(color);
         }
 
-        // Replace "Empty (Normal)" with just "Empty"
-        item_map.entry((ItemQuality::Normal, "Empty".to_string())).or_insert(egui::Color32::from_gray(180));
-
         let mut entries: Vec<_> = item_map.into_iter().collect();
         entries.sort_by(|((q1, n1), _), ((q2, n2), _)| {
             n1.cmp(n2).then(q1.cmp(q2))
         });
 
+        for zone in ClimateZone::ALL {
+            ui.horizontal(|ui| {
+                let rect = ui.allocate_exact_size(egui::vec2(20.0, 20.0), egui::Sense::hover()).0;
+                ui.painter().rect_filled(rect, 0.0, self.color_for_climate(zone));
+                ui.label(format!("Vazio ({})", zone.label()));
+            });
+        }
+        ui.separator();
+
         for ((quality, name), color) in entries {
-            let label_text = if name == "Empty" { "Empty".to_string() } else { format!("{} ({:?})", name, quality) };
+            let label_text = format!("{} ({})", name, quality);
             ui.horizontal(|ui| {
                 let rect = ui.allocate_exact_size(egui::vec2(20.0, 20.0), egui::Sense::hover()).0;
                 ui.painter().rect_filled(rect, 0.0, color);
@@ -642,11 +2898,19 @@ impl App {
         }
     }
 
+    fn color_for_climate(&self, zone: ClimateZone) -> egui::Color32 {
+        match zone {
+            ClimateZone::Ambient => egui::Color32::from_gray(180),
+            ClimateZone::Chilled => egui::Color32::from_rgb(150, 200, 235),
+            ClimateZone::Frozen => egui::Color32::from_rgb(200, 235, 255),
+        }
+    }
+
     fn color_for_item(&self, item: &Item) -> egui::Color32 {
-        self.color_for_quality_name(item.item_quality.clone(), &item.name)
+        self.color_for_quality_name(&item.quality_label(), &item.name)
     }
 
-    fn color_for_quality_name(&self, q: ItemQuality, name: &str) -> egui::Color32 {
+    fn color_for_quality_name(&self, q: &str, name: &str) -> egui::Color32 {
         let mut hasher = DefaultHasher::new();
         q.hash(&mut hasher);
         name.hash(&mut hasher);
@@ -681,6 +2945,42 @@ impl App {
     }
 }
 
+// Product id for an imported row: known names keep their id, and an id given in the file must not clash
+fn resolve_product_id(name_to_id: &mut HashMap<String, u32>, next_id: &mut u32, name: &str, id: Option<u32>) -> Result<u32, String> {
+    match (name_to_id.get(name), id) {
+        (Some(&known), Some(id)) if known != id => Err(format!("'{}' já tem o ID {}, não {}.", name, known, id)),
+        (Some(&known), _) => Ok(known),
+        (None, Some(id)) => {
+            if let Some((other, _)) = name_to_id.iter().find(|(_, existing)| **existing == id) {
+                return Err(format!("O ID {} já pertence a '{}'.", id, other));
+            }
+            let following = id.checked_add(1).ok_or(format!("O ID {} está fora do intervalo permitido.", id))?;
+            name_to_id.insert(name.to_string(), id);
+            *next_id = (*next_id).max(following);
+            Ok(id)
+        },
+        (None, None) => {
+            let id = *next_id;
+            *next_id = id.checked_add(1).ok_or("Não há mais IDs de produto disponíveis.".to_string())?;
+            name_to_id.insert(name.to_string(), id);
+            Ok(id)
+        },
+    }
+}
+
+fn parse_order_lines(input: &str) -> Result<Vec<OrderLine>, String> {
+    input
+        .split(',')
+        .filter(|part| !part.trim().is_empty())
+        .map(|part| {
+            let (id, quantity) = part.split_once(':').ok_or(format!("Linha inválida '{}'. Use ID:QTD.", part.trim()))?;
+            let num_id = id.trim().parse::<u32>().map_err(|_| format!("ID inválido na linha '{}'.", part.trim()))?;
+            let quantity = quantity.trim().parse::<u32>().map_err(|_| format!("Quantidade inválida na linha '{}'.", part.trim()))?;
+            Ok(OrderLine { num_id, quantity })
+        })
+        .collect()
+}
+
 fn main() -> Result<(), eframe::Error> {
     let options = eframe::NativeOptions::default();
     eframe::run_native(
@@ -1,9 +1,13 @@
-use chrono::NaiveDate;
+use chrono::{Local, NaiveDate};
 
 pub fn parse_date(date_str: &str) -> Option<NaiveDate> {
     NaiveDate::parse_from_str(date_str, "%d-%m-%Y").ok()
 }
 
+pub fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
 pub fn validate_location_input(row: &str, shelf: &str, level: &str, zone: &str, max_dimensions: (u32, u32, u32, u32)) -> Result<(u32, u32, u32, u32), String> {
     let row_num = row.parse::<u32>().map_err(|_| "Número de fileira inválido.".to_string())?;
     let shelf_num = shelf.parse::<u32>().map_err(|_| "Número de prateleira inválido.".to_string())?;
@@ -0,0 +1,118 @@
+use super::warehouse::Location;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClimateZone {
+    Ambient,
+    Chilled,
+    Frozen,
+}
+
+impl ClimateZone {
+    pub const ALL: [ClimateZone; 3] = [ClimateZone::Ambient, ClimateZone::Chilled, ClimateZone::Frozen];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ClimateZone::Ambient => "Ambient",
+            ClimateZone::Chilled => "Chilled",
+            ClimateZone::Frozen => "Frozen",
+        }
+    }
+}
+
+// Part of the grid a climate zone is assigned to; more specific scopes override broader ones
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClimateScope {
+    Row(u32),
+    Shelf(u32, u32),        // (row, shelf)
+    Level(u32, u32, u32),   // (row, shelf, level)
+}
+
+impl ClimateScope {
+    pub fn label(&self) -> String {
+        match self {
+            ClimateScope::Row(row) => format!("F{}", row),
+            ClimateScope::Shelf(row, shelf) => format!("F{}, P{}", row, shelf),
+            ClimateScope::Level(row, shelf, level) => format!("F{}, P{}, N{}", row, shelf, level),
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ClimateMap {
+    #[serde(with = "super::serde_map")]
+    assignments: HashMap<ClimateScope, ClimateZone>,
+}
+
+impl ClimateMap {
+    pub fn assign(&mut self, scope: ClimateScope, zone: ClimateZone) {
+        self.assignments.insert(scope, zone);
+    }
+
+    pub fn zone_at(&self, loc: &Location) -> ClimateZone {
+        self.assignments.get(&ClimateScope::Level(loc.row, loc.shelf, loc.level))
+            .or_else(|| self.assignments.get(&ClimateScope::Shelf(loc.row, loc.shelf)))
+            .or_else(|| self.assignments.get(&ClimateScope::Row(loc.row)))
+            .copied()
+            .unwrap_or(ClimateZone::Ambient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warehouse::item::{HandlingAttribute, Item};
+    use crate::warehouse::warehouse::Warehouse;
+
+    fn frozen(num_id: u32) -> Item {
+        Item::new(num_id, format!("Congelado {}", num_id), 1, vec![HandlingAttribute::Climate(ClimateZone::Frozen)], None)
+    }
+
+    #[test]
+    fn the_most_specific_scope_wins() {
+        let mut climate = ClimateMap::default();
+        climate.assign(ClimateScope::Row(1), ClimateZone::Chilled);
+        climate.assign(ClimateScope::Shelf(1, 2), ClimateZone::Frozen);
+        climate.assign(ClimateScope::Level(1, 2, 3), ClimateZone::Ambient);
+        let at = |row, shelf, level| climate.zone_at(&Location { row, shelf, level, zone: 0 });
+
+        assert_eq!(at(0, 2, 3), ClimateZone::Ambient);
+        assert_eq!(at(1, 0, 0), ClimateZone::Chilled);
+        assert_eq!(at(1, 2, 0), ClimateZone::Frozen);
+        assert_eq!(at(1, 2, 3), ClimateZone::Ambient);
+    }
+
+    #[test]
+    fn items_are_stored_only_in_their_climate() {
+        let mut warehouse = Warehouse::new((3, 2, 2, 2));
+        assert!(warehouse.add_item(frozen(1)).is_err());
+
+        warehouse.climate.assign(ClimateScope::Shelf(2, 1), ClimateZone::Frozen);
+        let locations = warehouse.add_item(frozen(1)).unwrap();
+        assert_eq!((locations[0].row, locations[0].shelf), (2, 1));
+
+        // Ambient stock stays out of the freezer
+        let ambient = warehouse.add_item(Item::new(2, "Caixa".to_string(), 1, vec![], None)).unwrap();
+        assert_ne!((ambient[0].row, ambient[0].shelf), (2, 1));
+    }
+
+    #[test]
+    fn occupied_cells_keep_the_climate_their_stock_needs() {
+        let mut warehouse = Warehouse::new((2, 2, 2, 2));
+        warehouse.assign_climate(ClimateScope::Row(1), ClimateZone::Frozen).unwrap();
+        let locations = warehouse.add_item(frozen(7)).unwrap();
+        let loc = &locations[0];
+
+        let error = warehouse.assign_climate(ClimateScope::Shelf(loc.row, loc.shelf), ClimateZone::Ambient).unwrap_err();
+        assert!(error.contains("ID 7"), "{}", error);
+        assert_eq!(warehouse.climate.zone_at(loc), ClimateZone::Frozen);
+
+        // Empty cells of the same row can still change
+        let other_shelf = 1 - loc.shelf;
+        warehouse.assign_climate(ClimateScope::Shelf(1, other_shelf), ClimateZone::Chilled).unwrap();
+        assert_eq!(warehouse.climate.zone_at(&Location { row: 1, shelf: other_shelf, level: 0, zone: 0 }), ClimateZone::Chilled);
+    }
+}
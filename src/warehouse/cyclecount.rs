@@ -0,0 +1,311 @@
+use super::movement::AdjustmentReason;
+use super::slotting::{abc_analysis, AbcClass};
+use super::storage::StorageBackend;
+use super::warehouse::{Location, Warehouse};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CountStatus {
+    Pending,
+    Counted,
+    Approved,
+    Rejected,
+}
+
+impl CountStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CountStatus::Pending => "Pendente",
+            CountStatus::Counted => "Contado",
+            CountStatus::Approved => "Aprovado",
+            CountStatus::Rejected => "Rejeitado",
+        }
+    }
+}
+
+// One stored record to be counted; `expected` is the system quantity and `location` where the
+// record was when the task was created
+#[derive(Serialize, Deserialize)]
+pub struct CountTask {
+    pub id: u32,
+    pub record_id: u64,
+    pub location: Location,
+    #[serde(rename = "product_id")]
+    pub num_id: u32,
+    pub name: String,
+    pub expected: u32,
+    pub counted: Option<u32>,
+    pub status: CountStatus,
+    pub reason: Option<AdjustmentReason>,
+}
+
+impl CountTask {
+    // Counted minus expected, only when a count was entered and it differs
+    pub fn discrepancy(&self) -> Option<i64> {
+        self.counted
+            .map(|c| c as i64 - self.expected as i64)
+            .filter(|d| *d != 0)
+    }
+
+    // Where to count now: the record's first cell, which moves with it, or the recorded one if the
+    // record has left the warehouse
+    pub fn current_location<S: StorageBackend>(&self, warehouse: &Warehouse<S>) -> Location {
+        warehouse.record_locations(self.record_id).into_iter().next().unwrap_or_else(|| self.location.clone())
+    }
+
+    fn is_open(&self) -> bool {
+        matches!(self.status, CountStatus::Pending | CountStatus::Counted)
+    }
+}
+
+// Fast movers drift more, so they are counted more often
+pub fn count_interval_days(class: AbcClass) -> i64 {
+    match class {
+        AbcClass::A => 30,
+        AbcClass::B => 90,
+        AbcClass::C => 180,
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct CycleCountManager {
+    pub tasks: Vec<CountTask>,
+    last_counted: HashMap<u32, NaiveDate>,
+}
+
+impl CycleCountManager {
+    // Creates one task per matching record that has no open count yet, returning the new task ids
+    fn create_tasks<F, S: StorageBackend>(&mut self, warehouse: &Warehouse<S>, mut wanted: F) -> Vec<u32>
+    where
+        F: FnMut(u32, &[Location]) -> bool,
+    {
+        let mut created = Vec::new();
+        for (itm, locs) in warehouse.grouped_items() {
+            if !wanted(itm.num_id, &locs) {
+                continue;
+            }
+            if self.tasks.iter().any(|t| t.record_id == itm.record_id && t.is_open()) {
+                continue;
+            }
+            let id = self.tasks.len() as u32 + 1;
+            self.tasks.push(CountTask {
+                id,
+                record_id: itm.record_id,
+                location: locs[0].clone(),
+                num_id: itm.num_id,
+                name: itm.name.clone(),
+                expected: itm.item_quantity,
+                counted: None,
+                status: CountStatus::Pending,
+                reason: None,
+            });
+            created.push(id);
+        }
+        created
+    }
+
+    // Pending tasks with where their records are now, in the order a counter walks past them,
+    // starting and ending at the dock
+    pub fn route<S: StorageBackend>(&self, warehouse: &Warehouse<S>) -> Vec<(&CountTask, Location)> {
+        let mut pending: Vec<(&CountTask, Location)> = self.tasks.iter()
+            .filter(|t| t.status == CountStatus::Pending)
+            .map(|t| (t, t.current_location(warehouse)))
+            .collect();
+        let locations: Vec<Location> = pending.iter().map(|(_, loc)| loc.clone()).collect();
+        let route = warehouse.optimise_route(&locations);
+        pending.sort_by_key(|(_, location)| route.iter().position(|loc| loc == location));
+        pending
+    }
+
+    // Counts everything stored in a row, or in a single shelf of it
+    pub fn create_for_location<S: StorageBackend>(&mut self, warehouse: &Warehouse<S>, row: u32, shelf: Option<u32>) -> Vec<u32> {
+        self.create_tasks(warehouse, |_, locs| {
+            locs.iter().any(|l| l.row == row && shelf.is_none_or(|s| l.shelf == s))
+        })
+    }
+
+    pub fn create_for_product<S: StorageBackend>(&mut self, warehouse: &Warehouse<S>, num_id: u32) -> Vec<u32> {
+        self.create_tasks(warehouse, |id, _| id == num_id)
+    }
+
+    // Products whose last approved count is older than their ABC class allows (or never counted)
+    pub fn create_due<S: StorageBackend>(&mut self, warehouse: &Warehouse<S>, today: NaiveDate) -> Vec<u32> {
+        let classes: HashMap<u32, AbcClass> = abc_analysis(warehouse).into_iter().map(|v| (v.num_id, v.class)).collect();
+        let last_counted = self.last_counted.clone();
+        self.create_tasks(warehouse, |num_id, _| {
+            let class = classes.get(&num_id).copied().unwrap_or(AbcClass::C);
+            match last_counted.get(&num_id) {
+                Some(date) => (today - *date).num_days() >= count_interval_days(class),
+                None => true,
+            }
+        })
+    }
+
+    fn task_mut(&mut self, task_id: u32) -> Result<&mut CountTask, String> {
+        self.tasks.iter_mut().find(|t| t.id == task_id)
+            .ok_or(format!("Contagem {} não encontrada.", task_id))
+    }
+
+    pub fn record_count(&mut self, task_id: u32, counted: u32) -> Result<(), String> {
+        let task = self.task_mut(task_id)?;
+        if !task.is_open() {
+            return Err(format!("A contagem {} já está fechada ({}).", task_id, task.status.label()));
+        }
+        task.counted = Some(counted);
+        task.status = CountStatus::Counted;
+        Ok(())
+    }
+
+    // Counted tasks whose quantity differs from the system
+    pub fn discrepancy_report(&self) -> Vec<&CountTask> {
+        self.tasks.iter()
+            .filter(|t| t.status == CountStatus::Counted && t.discrepancy().is_some())
+            .collect()
+    }
+
+    // Closes a counted task, writing the counted quantity back to the warehouse when it differs
+    pub fn approve<S: StorageBackend>(&mut self, task_id: u32, reason: AdjustmentReason, warehouse: &mut Warehouse<S>, today: NaiveDate) -> Result<Option<i64>, String> {
+        let task = self.task_mut(task_id)?;
+        let counted = match (task.status, task.counted) {
+            (CountStatus::Counted, Some(counted)) => counted,
+            _ => return Err(format!("A contagem {} não tem quantidade contada por aprovar.", task_id)),
+        };
+
+        // The record may have been relocated since the task was created
+        let location = warehouse.record_locations(task.record_id).into_iter().next()
+            .ok_or("O registo contado já não está no armazém.".to_string())?;
+        let current = warehouse.item_at(&location).map(|i| i.item_quantity).unwrap_or(0);
+        if current != task.expected {
+            return Err(format!(
+                "O stock mudou desde a criação da contagem ({} -> {}); rejeite e volte a contar.",
+                task.expected, current
+            ));
+        }
+
+        let discrepancy = task.discrepancy();
+        if discrepancy.is_some() {
+            warehouse.adjust_quantity(&location, counted, reason)?;
+            task.reason = Some(reason);
+        }
+        task.status = CountStatus::Approved;
+        let num_id = task.num_id;
+        self.last_counted.insert(num_id, today);
+        Ok(discrepancy)
+    }
+
+    pub fn reject(&mut self, task_id: u32) -> Result<(), String> {
+        let task = self.task_mut(task_id)?;
+        if !task.is_open() {
+            return Err(format!("A contagem {} já está fechada ({}).", task_id, task.status.label()));
+        }
+        task.status = CountStatus::Rejected;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warehouse::item::Item;
+    use crate::warehouse::movement::{Movement, MovementKind};
+
+    // A warehouse holding products 1 to 4, where product n was picked picks[n - 1] times
+    fn picked(picks: [u32; 4]) -> Warehouse {
+        let mut warehouse = Warehouse::new((2, 2, 2, 2));
+        for (index, count) in picks.into_iter().enumerate() {
+            let num_id = index as u32 + 1;
+            let locations = warehouse.add_item(Item::new(num_id, format!("Produto {}", num_id), 10, vec![], None)).unwrap();
+            let itm = warehouse.item_at(&locations[0]).unwrap().clone();
+            for _ in 0..count {
+                warehouse.movements.push(Movement {
+                    timestamp: 0,
+                    kind: MovementKind::Outbound,
+                    record_id: itm.record_id,
+                    num_id,
+                    name: itm.name.clone(),
+                    quantity_change: -1,
+                    from: Some(locations[0].clone()),
+                    to: None,
+                    reason: None,
+                    lot: None,
+                    serials: Vec::new(),
+                    unit_cost: 0.0,
+                    user: None,
+                });
+            }
+        }
+        warehouse
+    }
+
+    fn date(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2030, month, day).unwrap()
+    }
+
+    #[test]
+    fn classes_split_at_80_and_95_percent_of_picks() {
+        // The share picked before each product is 0%, 80% and 95%; the last was never picked
+        let warehouse = picked([80, 15, 5, 0]);
+        let classes: HashMap<u32, AbcClass> = abc_analysis(&warehouse).into_iter().map(|v| (v.num_id, v.class)).collect();
+        assert_eq!(classes[&1], AbcClass::A);
+        assert_eq!(classes[&2], AbcClass::B);
+        assert_eq!(classes[&3], AbcClass::C);
+        assert_eq!(classes[&4], AbcClass::C);
+
+        // Just below each boundary: 79% and 94%
+        let warehouse = picked([79, 15, 5, 1]);
+        let classes: HashMap<u32, AbcClass> = abc_analysis(&warehouse).into_iter().map(|v| (v.num_id, v.class)).collect();
+        assert_eq!((classes[&1], classes[&2], classes[&3], classes[&4]), (AbcClass::A, AbcClass::A, AbcClass::B, AbcClass::C));
+    }
+
+    #[test]
+    fn products_come_due_after_their_class_interval() {
+        let mut warehouse = picked([80, 15, 5, 0]);
+        let mut counts = CycleCountManager::default();
+        assert_eq!(counts.create_due(&warehouse, date(1, 1)).len(), 4);
+        for id in 1..=4 {
+            let expected = counts.tasks[id - 1].expected;
+            counts.record_count(id as u32, expected).unwrap();
+            counts.approve(id as u32, AdjustmentReason::CycleCount, &mut warehouse, date(1, 1)).unwrap();
+        }
+
+        // 30 days for A, 90 for B, 180 for C
+        assert!(counts.create_due(&warehouse, date(1, 30)).is_empty());
+        let due = counts.create_due(&warehouse, date(1, 31));
+        assert_eq!(due.iter().map(|id| counts.tasks[*id as usize - 1].num_id).collect::<Vec<_>>(), vec![1]);
+        let due = counts.create_due(&warehouse, date(4, 1));
+        assert_eq!(due.iter().map(|id| counts.tasks[*id as usize - 1].num_id).collect::<Vec<_>>(), vec![2]);
+        assert!(counts.create_due(&warehouse, date(6, 29)).is_empty());
+        assert_eq!(counts.create_due(&warehouse, date(6, 30)).len(), 2);
+    }
+
+    #[test]
+    fn approving_a_discrepancy_adjusts_the_stock() {
+        let mut warehouse = picked([0, 0, 0, 0]);
+        let mut counts = CycleCountManager::default();
+        let task = counts.create_for_product(&warehouse, 2)[0];
+        assert!(counts.approve(task, AdjustmentReason::CycleCount, &mut warehouse, date(1, 1)).is_err());
+
+        counts.record_count(task, 7).unwrap();
+        assert_eq!(counts.discrepancy_report().len(), 1);
+        assert_eq!(counts.approve(task, AdjustmentReason::Lost, &mut warehouse, date(1, 1)).unwrap(), Some(-3));
+        assert_eq!(warehouse.search_by_id(2).on_hand, 7);
+        assert!(counts.reject(task).is_err());
+    }
+
+    #[test]
+    fn the_route_follows_records_that_moved() {
+        let mut warehouse = picked([0, 0, 0, 0]);
+        let mut counts = CycleCountManager::default();
+        let task = counts.create_for_product(&warehouse, 3)[0];
+        let record_id = counts.tasks[0].record_id;
+        let to = Location { row: 1, shelf: 1, level: 1, zone: 1 };
+        assert_ne!(counts.tasks[0].location, to);
+        warehouse.move_record(record_id, vec![to.clone()]).unwrap();
+
+        let route = counts.route(&warehouse);
+        assert_eq!((route[0].0.id, &route[0].1), (task, &to));
+    }
+}
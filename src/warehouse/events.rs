@@ -0,0 +1,166 @@
+use super::item::Item;
+use super::storage::StorageBackend;
+use super::warehouse::{Location, Warehouse};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+// Change notifications for clients that keep their own copy of the warehouse cells. A client
+// starts from a `Snapshot` and applies the events that follow to stay in step.
+#[derive(Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WarehouseEvent {
+    // Every occupied cell, in the same `[[Location, Item], ...]` form as the `items` field of the JSON snapshot
+    Snapshot { items: Vec<(Location, Item)> },
+    Added { record_id: u64, locations: Vec<Location>, item: Item },
+    Removed { record_id: u64, locations: Vec<Location> },
+    Moved { record_id: u64, from: Vec<Location>, to: Vec<Location> },
+    QuantityChanged { record_id: u64, locations: Vec<Location>, old_quantity: u32, new_quantity: u32, item: Item },
+}
+
+// Remembers the stored records as last reported and turns the difference with the current
+// warehouse into events, so it does not matter which operation made the change
+pub struct EventTracker {
+    records: HashMap<u64, (Item, Vec<Location>)>,
+}
+
+impl EventTracker {
+    pub fn new<S: StorageBackend>(warehouse: &Warehouse<S>) -> EventTracker {
+        EventTracker { records: Self::records(warehouse) }
+    }
+
+    fn records<S: StorageBackend>(warehouse: &Warehouse<S>) -> HashMap<u64, (Item, Vec<Location>)> {
+        warehouse.grouped_items()
+            .into_iter()
+            .map(|(itm, locs)| (itm.record_id, (itm, locs)))
+            .collect()
+    }
+
+    pub fn snapshot<S: StorageBackend>(warehouse: &Warehouse<S>) -> WarehouseEvent {
+        let mut items: Vec<(Location, Item)> = warehouse.items().map(|(loc, itm)| (loc.clone(), itm.clone())).collect();
+        items.sort_by(|(l1, _), (l2, _)| l1.cmp(l2));
+        WarehouseEvent::Snapshot { items }
+    }
+
+    // Events since the previous call, in an order that never puts two records in one cell:
+    // removals first, then each move or addition once the cells it fills have been left, then
+    // quantity changes. Moves that wait on each other (two records swapping cells) are broken by
+    // sending one of them as a removal and, once its cells are free, an addition.
+    pub fn changes<S: StorageBackend>(&mut self, warehouse: &Warehouse<S>) -> Vec<WarehouseEvent> {
+        let current = Self::records(warehouse);
+        let mut events = Vec::new();
+        let mut placements = BTreeMap::new();
+        let mut quantities = Vec::new();
+
+        let mut removed: Vec<u64> = self.records.keys().filter(|id| !current.contains_key(id)).cloned().collect();
+        removed.sort();
+        for record_id in removed {
+            events.push(WarehouseEvent::Removed { record_id, locations: self.records[&record_id].1.clone() });
+        }
+        let mut records: Vec<_> = current.iter().collect();
+        records.sort_by_key(|(record_id, _)| **record_id);
+        for (record_id, (itm, locations)) in records {
+            let Some((old, old_locations)) = self.records.get(record_id) else {
+                placements.insert(*record_id, WarehouseEvent::Added { record_id: *record_id, locations: locations.clone(), item: itm.clone() });
+                continue;
+            };
+            if old_locations != locations {
+                placements.insert(*record_id, WarehouseEvent::Moved { record_id: *record_id, from: old_locations.clone(), to: locations.clone() });
+            }
+            if old.item_quantity != itm.item_quantity || old.serials != itm.serials {
+                quantities.push(WarehouseEvent::QuantityChanged {
+                    record_id: *record_id,
+                    locations: locations.clone(),
+                    old_quantity: old.item_quantity,
+                    new_quantity: itm.item_quantity,
+                    item: itm.clone(),
+                });
+            }
+        }
+
+        // Cells the client still shows as taken: the old cells of every record that stays
+        let mut occupied: HashMap<&Location, u64> = self.records.iter()
+            .filter(|(record_id, _)| current.contains_key(record_id))
+            .flat_map(|(record_id, (_, locations))| locations.iter().map(move |loc| (loc, *record_id)))
+            .collect();
+        while let Some(&first) = placements.keys().next() {
+            let ready = placements.keys().find(|record_id| {
+                current[*record_id].1.iter().all(|loc| occupied.get(loc).is_none_or(|holder| holder == *record_id))
+            }).cloned();
+            let record_id = ready.unwrap_or(first);
+            let Some(event) = placements.remove(&record_id) else {
+                break;
+            };
+            let (itm, locations) = &current[&record_id];
+            occupied.retain(|_, holder| *holder != record_id);
+            match (ready, event) {
+                (None, WarehouseEvent::Moved { from, .. }) => {
+                    events.push(WarehouseEvent::Removed { record_id, locations: from });
+                    placements.insert(record_id, WarehouseEvent::Added { record_id, locations: locations.clone(), item: itm.clone() });
+                },
+                (_, event) => {
+                    occupied.extend(locations.iter().map(|loc| (loc, record_id)));
+                    events.push(event);
+                },
+            }
+        }
+
+        self.records = current;
+        events.extend(quantities);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Replays `events` on the cells as a client would, failing if two records share a cell
+    fn apply(cells: &mut HashMap<Location, u64>, events: &[WarehouseEvent]) {
+        for event in events {
+            let (record_id, from, to) = match event {
+                WarehouseEvent::Added { record_id, locations, .. } => (*record_id, &[][..], &locations[..]),
+                WarehouseEvent::Removed { record_id, locations } => (*record_id, &locations[..], &[][..]),
+                WarehouseEvent::Moved { record_id, from, to } => (*record_id, &from[..], &to[..]),
+                _ => continue,
+            };
+            for loc in from {
+                assert_eq!(cells.remove(loc), Some(record_id));
+            }
+            for loc in to {
+                assert!(cells.insert(loc.clone(), record_id).is_none(), "duas existências em {:?}", loc);
+            }
+        }
+    }
+
+    fn client(warehouse: &Warehouse) -> HashMap<Location, u64> {
+        warehouse.items().map(|(loc, itm)| (loc.clone(), itm.record_id)).collect()
+    }
+
+    #[test]
+    fn a_record_moves_in_only_after_the_cell_is_left() {
+        let mut warehouse = Warehouse::new((1, 1, 1, 3));
+        let first = warehouse.add_item(Item::new(1, "Caixa".to_string(), 1, vec![], None)).unwrap();
+        let second = warehouse.add_item(Item::new(2, "Saco".to_string(), 1, vec![], None)).unwrap();
+        let free = Location { zone: 3 - first[0].zone - second[0].zone, ..first[0].clone() };
+        let mut tracker = EventTracker::new(&warehouse);
+        let mut cells = client(&warehouse);
+
+        // The later record leaves the cell the earlier one moves into
+        let (a, b) = (warehouse.item_at(&first[0]).unwrap().record_id, warehouse.item_at(&second[0]).unwrap().record_id);
+        warehouse.move_record(b, vec![free.clone()]).unwrap();
+        warehouse.move_record(a, second.clone()).unwrap();
+        let events = tracker.changes(&warehouse);
+        assert!(events.iter().all(|event| matches!(event, WarehouseEvent::Moved { .. })));
+        apply(&mut cells, &events);
+        assert_eq!(cells, client(&warehouse));
+
+        // Two records swapping cells arrive as a removal and an addition
+        warehouse.move_record(a, first).unwrap();
+        warehouse.move_record(b, second).unwrap();
+        warehouse.move_record(a, vec![free]).unwrap();
+        let events = tracker.changes(&warehouse);
+        assert_eq!(events.len(), 3);
+        apply(&mut cells, &events);
+        assert_eq!(cells, client(&warehouse));
+    }
+}
@@ -0,0 +1,192 @@
+use super::item::Item;
+use super::warehouse::Location;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HazardClass {
+    Explosive,
+    Gas,
+    Flammable,
+    Oxidizer,
+    Toxic,
+    Corrosive,
+}
+
+impl HazardClass {
+    pub const ALL: [HazardClass; 6] = [
+        HazardClass::Explosive,
+        HazardClass::Gas,
+        HazardClass::Flammable,
+        HazardClass::Oxidizer,
+        HazardClass::Toxic,
+        HazardClass::Corrosive,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            HazardClass::Explosive => "Explosive",
+            HazardClass::Gas => "Gas",
+            HazardClass::Flammable => "Flammable",
+            HazardClass::Oxidizer => "Oxidizer",
+            HazardClass::Toxic => "Toxic",
+            HazardClass::Corrosive => "Corrosive",
+        }
+    }
+}
+
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SegregationGroup {
+    Hazard(HazardClass),
+    Food,
+}
+
+impl SegregationGroup {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SegregationGroup::Hazard(class) => class.label(),
+            SegregationGroup::Food => "Food",
+        }
+    }
+}
+
+// Minimum distance two incompatible groups must keep between each other
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Separation {
+    Zones(u32),   // within the same row and shelf, at least N zones apart (any level)
+    Shelves(u32), // within the same row, at least N shelves apart
+    Rows(u32),    // at least N rows apart
+}
+
+impl Separation {
+    pub fn is_violated(&self, a: &Location, b: &Location) -> bool {
+        match *self {
+            Separation::Zones(n) => a.row == b.row && a.shelf == b.shelf && a.zone.abs_diff(b.zone) < n,
+            Separation::Shelves(n) => a.row == b.row && a.shelf.abs_diff(b.shelf) < n,
+            Separation::Rows(n) => a.row.abs_diff(b.row) < n,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Separation::Zones(n) => format!("{} zona(s)", n),
+            Separation::Shelves(n) => format!("{} prateleira(s)", n),
+            Separation::Rows(n) => format!("{} fileira(s)", n),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SegregationMatrix {
+    #[serde(with = "super::serde_map")]
+    rules: HashMap<(SegregationGroup, SegregationGroup), Separation>,
+}
+
+impl SegregationMatrix {
+    // Default rules loosely based on the ADR segregation table
+    pub fn standard() -> Self {
+        let mut matrix = SegregationMatrix { rules: HashMap::new() };
+
+        for class in HazardClass::ALL {
+            let separation = match class {
+                HazardClass::Toxic | HazardClass::Corrosive | HazardClass::Explosive => Separation::Rows(1),
+                _ => Separation::Shelves(1),
+            };
+            matrix.set_rule(SegregationGroup::Food, SegregationGroup::Hazard(class), separation);
+
+            if class != HazardClass::Explosive {
+                matrix.set_rule(SegregationGroup::Hazard(HazardClass::Explosive), SegregationGroup::Hazard(class), Separation::Rows(1));
+            }
+        }
+
+        matrix.set_rule(SegregationGroup::Hazard(HazardClass::Flammable), SegregationGroup::Hazard(HazardClass::Oxidizer), Separation::Shelves(2));
+        matrix.set_rule(SegregationGroup::Hazard(HazardClass::Gas), SegregationGroup::Hazard(HazardClass::Flammable), Separation::Shelves(1));
+        matrix.set_rule(SegregationGroup::Hazard(HazardClass::Flammable), SegregationGroup::Hazard(HazardClass::Corrosive), Separation::Zones(2));
+        matrix.set_rule(SegregationGroup::Hazard(HazardClass::Toxic), SegregationGroup::Hazard(HazardClass::Corrosive), Separation::Zones(2));
+
+        matrix
+    }
+
+    pub fn set_rule(&mut self, a: SegregationGroup, b: SegregationGroup, separation: Separation) {
+        self.rules.insert(Self::key(a, b), separation);
+    }
+
+    pub fn rule(&self, a: SegregationGroup, b: SegregationGroup) -> Option<Separation> {
+        self.rules.get(&Self::key(a, b)).copied()
+    }
+
+    // First rule between the groups of `a` and `b` that the two spots break, if any
+    pub fn conflict(&self, a: &Item, loc_a: &Location, b: &Item, loc_b: &Location) -> Option<(SegregationGroup, SegregationGroup, Separation)> {
+        for group_a in a.segregation_groups() {
+            for group_b in b.segregation_groups() {
+                if let Some(separation) = self.rule(group_a, group_b) {
+                    if separation.is_violated(loc_a, loc_b) {
+                        return Some((group_a, group_b, separation));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn key(a: SegregationGroup, b: SegregationGroup) -> (SegregationGroup, SegregationGroup) {
+        if a <= b { (a, b) } else { (b, a) }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SegregationViolation {
+    pub first: Item,
+    pub first_location: Location,
+    pub second: Item,
+    pub second_location: Location,
+    pub groups: (SegregationGroup, SegregationGroup),
+    pub separation: Separation,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warehouse::item::HandlingAttribute;
+    use crate::warehouse::warehouse::Warehouse;
+
+    fn hazardous(num_id: u32, class: HazardClass) -> Item {
+        Item::new(num_id, format!("Produto {}", num_id), 1, vec![HandlingAttribute::Hazardous(class)], None)
+    }
+
+    #[test]
+    fn rules_apply_in_either_order() {
+        let matrix = SegregationMatrix::standard();
+        let (food, toxic) = (SegregationGroup::Food, SegregationGroup::Hazard(HazardClass::Toxic));
+        assert_eq!(matrix.rule(food, toxic), Some(Separation::Rows(1)));
+        assert_eq!(matrix.rule(toxic, food), Some(Separation::Rows(1)));
+        assert_eq!(matrix.rule(food, food), None);
+    }
+
+    #[test]
+    fn incompatible_classes_are_not_stored_side_by_side() {
+        // A single row: toxic stock can never keep a row away from food
+        let mut warehouse = Warehouse::new((1, 3, 1, 3));
+        let food = Item::new(1, "Arroz".to_string(), 1, vec![HandlingAttribute::Food], None);
+        warehouse.add_item(food).unwrap();
+        assert!(warehouse.add_item(hazardous(2, HazardClass::Toxic)).is_err());
+        assert_eq!(warehouse.grouped_items().len(), 1);
+    }
+
+    #[test]
+    fn zone_separation_skips_the_cells_too_close() {
+        let mut warehouse = Warehouse::new((1, 1, 1, 5));
+        let flammable = warehouse.add_item(hazardous(1, HazardClass::Flammable)).unwrap();
+        let corrosive = warehouse.add_item(hazardous(2, HazardClass::Corrosive)).unwrap();
+        assert_eq!(flammable[0].zone, 0);
+        assert_eq!(corrosive[0].zone, 2);
+
+        // Stock outside every segregation group may use the cell in between
+        let plain = warehouse.add_item(Item::new(3, "Caixa".to_string(), 1, vec![], None)).unwrap();
+        assert_eq!(plain[0].zone, 1);
+        assert!(warehouse.validate_segregation().is_empty());
+    }
+}
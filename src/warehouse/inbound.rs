@@ -0,0 +1,160 @@
+use super::item::Item;
+use super::storage::StorageBackend;
+use super::warehouse::{Location, Warehouse};
+use serde::{Deserialize, Serialize};
+
+// Where a stored record came from
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Provenance {
+    pub supplier: String,
+    pub po_reference: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Discrepancy {
+    Over(u32),
+    Short(u32),
+    Damaged(u32),
+}
+
+impl Discrepancy {
+    pub fn label(&self) -> String {
+        match self {
+            Discrepancy::Over(n) => format!("{} unidade(s) a mais", n),
+            Discrepancy::Short(n) => format!("{} unidade(s) em falta", n),
+            Discrepancy::Damaged(n) => format!("{} unidade(s) danificada(s)", n),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Receipt {
+    pub received: u32,
+    pub damaged: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExpectedLine {
+    pub template: Item, // attributes and identity of the goods; its quantity is ignored
+    pub expected: u32,
+    pub receipt: Option<Receipt>,
+    pub put_away: Vec<Location>,
+}
+
+impl ExpectedLine {
+    pub fn discrepancies(&self) -> Vec<Discrepancy> {
+        let mut result = Vec::new();
+        if let Some(receipt) = self.receipt {
+            if receipt.received > self.expected {
+                result.push(Discrepancy::Over(receipt.received - self.expected));
+            } else if receipt.received < self.expected {
+                result.push(Discrepancy::Short(self.expected - receipt.received));
+            }
+            if receipt.damaged > 0 {
+                result.push(Discrepancy::Damaged(receipt.damaged));
+            }
+        }
+        result
+    }
+}
+
+// Purchase order, optionally announced by the supplier through an advance ship notice
+#[derive(Serialize, Deserialize)]
+pub struct PurchaseOrder {
+    pub id: u32,
+    pub reference: String,
+    pub supplier: String,
+    pub asn_reference: Option<String>,
+    pub lines: Vec<ExpectedLine>,
+}
+
+impl PurchaseOrder {
+    pub fn is_complete(&self) -> bool {
+        !self.lines.is_empty() && self.lines.iter().all(|l| l.receipt.is_some() && !l.put_away.is_empty())
+    }
+}
+
+// Received purchase orders stay on record as the audit trail of what came in; a new one takes the next number
+#[derive(Default, Serialize, Deserialize)]
+pub struct InboundManager {
+    pub purchase_orders: Vec<PurchaseOrder>,
+}
+
+impl InboundManager {
+    pub fn create_purchase_order(&mut self, reference: String, supplier: String, asn_reference: Option<String>) -> Result<u32, String> {
+        if reference.is_empty() || supplier.is_empty() {
+            return Err("Referência e fornecedor não podem estar vazios.".to_string());
+        }
+        if self.purchase_orders.iter().any(|po| po.reference == reference) {
+            return Err(format!("Já existe uma ordem de compra com a referência {}.", reference));
+        }
+
+        let id = self.purchase_orders.len() as u32 + 1;
+        self.purchase_orders.push(PurchaseOrder {
+            id,
+            reference,
+            supplier,
+            asn_reference,
+            lines: Vec::new(),
+        });
+        Ok(id)
+    }
+
+    pub fn add_line(&mut self, po_id: u32, template: Item, expected: u32) -> Result<usize, String> {
+        let po = self.purchase_order_mut(po_id)?;
+        if expected == 0 {
+            return Err("Quantidade esperada tem de ser maior que zero.".to_string());
+        }
+        po.lines.push(ExpectedLine { template, expected, receipt: None, put_away: Vec::new() });
+        Ok(po.lines.len())
+    }
+
+    // Records what physically arrived for a line and reports how it differs from what was expected
+    pub fn receive(&mut self, po_id: u32, line_number: usize, received: u32, damaged: u32) -> Result<Vec<Discrepancy>, String> {
+        let line = self.line_mut(po_id, line_number)?;
+        if !line.put_away.is_empty() {
+            return Err("Esta linha já foi arrumada.".to_string());
+        }
+        if damaged > received {
+            return Err("Quantidade danificada não pode exceder a quantidade recebida.".to_string());
+        }
+        line.receipt = Some(Receipt { received, damaged });
+        Ok(line.discrepancies())
+    }
+
+    // Stores the undamaged units of a received line through the normal allocation logic
+    pub fn putaway<S: StorageBackend>(&mut self, po_id: u32, line_number: usize, warehouse: &mut Warehouse<S>) -> Result<Vec<Location>, String> {
+        let po = self.purchase_order_mut(po_id)?;
+        let provenance = Provenance { supplier: po.supplier.clone(), po_reference: po.reference.clone() };
+        let line = po.lines.get_mut(line_number.wrapping_sub(1)).ok_or(format!("Linha {} não encontrada.", line_number))?;
+        if !line.put_away.is_empty() {
+            return Err("Esta linha já foi arrumada.".to_string());
+        }
+        let receipt = line.receipt.ok_or("A linha ainda não foi rececionada.".to_string())?;
+        let good = receipt.received - receipt.damaged;
+        if good == 0 {
+            return Err("Não há unidades em bom estado para arrumar.".to_string());
+        }
+
+        // Serial numbers announced for the line go with the good units, in the order they were listed
+        let template = &line.template;
+        let mut item = Item::new(template.num_id, template.name.clone(), good, template.attributes.clone(), template.data_validade);
+        item.provenance = Some(provenance);
+        item.lot = template.lot.clone();
+        item.serials = template.serials.iter().take(good as usize).cloned().collect();
+        item.unit_cost = template.unit_cost;
+        let locations = warehouse.add_item(item)?;
+        line.put_away = locations.clone();
+        Ok(locations)
+    }
+
+    fn purchase_order_mut(&mut self, po_id: u32) -> Result<&mut PurchaseOrder, String> {
+        self.purchase_orders.iter_mut().find(|po| po.id == po_id).ok_or(format!("Ordem de compra {} não encontrada.", po_id))
+    }
+
+    fn line_mut(&mut self, po_id: u32, line_number: usize) -> Result<&mut ExpectedLine, String> {
+        let po = self.purchase_order_mut(po_id)?;
+        po.lines.get_mut(line_number.wrapping_sub(1)).ok_or(format!("Linha {} não encontrada.", line_number))
+    }
+}
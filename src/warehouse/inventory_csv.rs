@@ -0,0 +1,164 @@
+use super::item::{HandlingAttribute, Item};
+use super::storage::StorageBackend;
+use super::warehouse::{Location, Warehouse};
+use chrono::NaiveDate;
+
+const HEADERS: [&str; 12] = [
+    "id", "name", "quantity", "quality", "expiry", "min_level", "max_level", "zones", "locations", "lot", "serials", "unit_cost",
+];
+const DATE_FORMAT: &str = "%d-%m-%Y";
+
+pub struct ImportReport {
+    pub imported: Vec<(usize, String, Vec<Location>)>, // (line, name, locations)
+    pub errors: Vec<(usize, String)>,                   // (line, message)
+}
+
+fn format_location(loc: &Location) -> String {
+    format!("F{}P{}N{}Z{}", loc.row, loc.shelf, loc.level, loc.zone)
+}
+
+// One line per stored record, sorted by name like the on-screen listing
+pub fn export_inventory<S: StorageBackend>(warehouse: &Warehouse<S>, path: &str) -> Result<usize, String> {
+    let mut grouped = warehouse.grouped_items();
+    grouped.sort_by(|(a, l1), (b, l2)| a.name.cmp(&b.name).then(l1.cmp(l2)));
+
+    let mut writer = csv::Writer::from_path(path).map_err(|e| format!("Não foi possível criar {}: {}", path, e))?;
+    writer.write_record(HEADERS).map_err(|e| e.to_string())?;
+    for (itm, locs) in &grouped {
+        let (min_level, max_level) = match itm.level_range() {
+            Some((min, max)) => (min.to_string(), max.to_string()),
+            None => ("".to_string(), "".to_string()),
+        };
+        writer.write_record([
+            itm.num_id.to_string(),
+            itm.name.clone(),
+            itm.item_quantity.to_string(),
+            itm.quality_label(),
+            itm.data_validade.map(|d| d.format(DATE_FORMAT).to_string()).unwrap_or_default(),
+            min_level,
+            max_level,
+            itm.footprint().0.to_string(),
+            locs.iter().map(format_location).collect::<Vec<_>>().join(";"),
+            itm.lot.clone().unwrap_or_default(),
+            itm.serials.join(";"),
+            format!("{}", itm.unit_cost),
+        ]).map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())?;
+    Ok(grouped.len())
+}
+
+// Builds an item from one CSV row. Level range and zone count columns fill in what the quality
+// column does not already say; the locations column is ignored since placement is recomputed.
+fn item_from_row(row: &csv::StringRecord, headers: &csv::StringRecord, levels: u32) -> Result<(Option<u32>, Item), String> {
+    let field = |name: &str| headers.iter().position(|h| h.trim() == name)
+        .and_then(|i| row.get(i))
+        .map(|v| v.trim())
+        .unwrap_or("");
+
+    let name = field("name");
+    if name.is_empty() {
+        return Err("Nome não pode estar vazio.".to_string());
+    }
+    let id = match field("id") {
+        "" => None,
+        id => Some(id.parse::<u32>().map_err(|_| format!("ID inválido: {}", id))?),
+    };
+    let quantity = field("quantity").parse::<u32>().map_err(|_| format!("Quantidade inválida: {}", field("quantity")))?;
+
+    let mut attributes = Vec::new();
+    let quality = field("quality");
+    if !quality.is_empty() && quality != "Normal" {
+        for token in quality.split(" + ") {
+            attributes.push(HandlingAttribute::parse_label(token).ok_or(format!("Atributo desconhecido: {}", token))?);
+        }
+    }
+
+    let (min_level, max_level) = (field("min_level"), field("max_level"));
+    if (!min_level.is_empty() || !max_level.is_empty()) && !attributes.iter().any(|a| matches!(a, HandlingAttribute::LevelRange { .. })) {
+        let nivel_minimo = if min_level.is_empty() { 0 } else { min_level.parse::<u32>().map_err(|_| "Nível mínimo inválido.".to_string())? };
+        let nivel_maximo = if max_level.is_empty() { levels.saturating_sub(1) } else { max_level.parse::<u32>().map_err(|_| "Nível máximo inválido.".to_string())? };
+        attributes.push(HandlingAttribute::LevelRange { nivel_minimo, nivel_maximo });
+    }
+    let zones = field("zones");
+    if !zones.is_empty() {
+        let required_zones = zones.parse::<u32>().map_err(|_| "Número de zonas inválido.".to_string())?;
+        if required_zones > 1 && !attributes.iter().any(|a| matches!(a, HandlingAttribute::Oversized { .. })) {
+            attributes.push(HandlingAttribute::Oversized { required_zones, required_levels: 1, required_shelves: 1 });
+        }
+    }
+
+    let data_validade = match field("expiry") {
+        "" => None,
+        date => Some(NaiveDate::parse_from_str(date, DATE_FORMAT).map_err(|_| format!("Data inválida: {} (use DD-MM-YYYY)", date))?),
+    };
+
+    let mut item = Item::new(0, name.to_string(), quantity, attributes, data_validade);
+    // Same rules as the add item form
+    if item.is_fragile() && (item.data_validade.is_none() || item.level_range().is_none()) {
+        return Err("Item frágil requer data de validade e nível máximo de armazenamento.".to_string());
+    }
+    if !field("lot").is_empty() {
+        item.lot = Some(field("lot").to_string());
+    }
+    // Separated like the locations column; one serial number per unit
+    item.serials = field("serials").split(';').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect();
+    item.validate_tracking()?;
+    if !field("unit_cost").is_empty() {
+        item.unit_cost = field("unit_cost").replace(',', ".").parse::<f64>().map_err(|_| "Custo unitário inválido.".to_string())?;
+    }
+    item.validate_levels(levels)?;
+    Ok((id, item))
+}
+
+// All or nothing: every row is validated first and, if all are valid, placed with `add_item` in one
+// warehouse transaction. If any row is bad or cannot be placed, nothing is imported and `errors`
+// lists every such row, so the file can be fixed in one pass and imported again.
+// `resolve_id` maps a product name (and the id given in the file, if any) to its product id.
+pub fn import_inventory<F, S: StorageBackend>(warehouse: &mut Warehouse<S>, path: &str, mut resolve_id: F) -> Result<ImportReport, String>
+where
+    F: FnMut(&str, Option<u32>) -> Result<u32, String>,
+{
+    let mut reader = csv::Reader::from_path(path).map_err(|e| format!("Não foi possível abrir {}: {}", path, e))?;
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    for required in ["name", "quantity"] {
+        if !headers.iter().any(|h| h.trim() == required) {
+            return Err(format!("Coluna obrigatória em falta: {}", required));
+        }
+    }
+
+    let mut report = ImportReport { imported: Vec::new(), errors: Vec::new() };
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let line = index + 2; // the header is line 1
+        let result = record.map_err(|e| e.to_string())
+            .and_then(|row| item_from_row(&row, &headers, warehouse.max_dimensions.2))
+            .and_then(|(id, mut item)| {
+                item.num_id = resolve_id(&item.name, id)?;
+                Ok(item)
+            });
+        match result {
+            Ok(item) => rows.push((line, item)),
+            Err(err) => report.errors.push((line, err)),
+        }
+    }
+    if !report.errors.is_empty() {
+        return Ok(report);
+    }
+
+    let mut current_line = 0;
+    let result = warehouse.transaction(|warehouse| {
+        let mut imported = Vec::new();
+        for (line, item) in rows {
+            current_line = line;
+            let name = item.name.clone();
+            imported.push((line, name, warehouse.add_item(item)?));
+        }
+        Ok(imported)
+    });
+    match result {
+        Ok(imported) => report.imported = imported,
+        Err(err) => report.errors.push((current_line, err)),
+    }
+    Ok(report)
+}
@@ -1,22 +1,92 @@
+use super::climate::ClimateZone;
+use super::hazard::{HazardClass, SegregationGroup};
+use super::inbound::Provenance;
 use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
 
-#[derive(Hash, PartialEq, Eq, Clone, Debug, PartialOrd, Ord)]
-pub enum ItemQuality {
+#[derive(Hash, PartialEq, Eq, Clone, Debug, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HandlingAttribute {
     Fragile,
-    Oversized,
-    Normal,
+    LevelRange {
+        #[serde(rename = "min_level")]
+        nivel_minimo: u32,
+        #[serde(rename = "max_level")]
+        nivel_maximo: u32,
+    },
+    Oversized { required_zones: u32, required_levels: u32, required_shelves: u32 },
+    Hazardous(HazardClass),
+    Food,
+    Climate(ClimateZone),
+    HighValue,
+    StackLimit(u32),
 }
 
-#[derive(Clone)]
+impl HandlingAttribute {
+    pub fn label(&self) -> String {
+        match self {
+            HandlingAttribute::Fragile => "Fragile".to_string(),
+            HandlingAttribute::LevelRange { nivel_minimo, nivel_maximo } => format!("Níveis {}-{}", nivel_minimo, nivel_maximo),
+            HandlingAttribute::Oversized { required_zones, required_levels, required_shelves } => {
+                format!("Oversized ({} zonas x {} níveis x {} prateleiras)", required_zones, required_levels, required_shelves)
+            },
+            HandlingAttribute::Hazardous(class) => format!("Hazardous ({})", class.label()),
+            HandlingAttribute::Food => "Food".to_string(),
+            HandlingAttribute::Climate(zone) => zone.label().to_string(),
+            HandlingAttribute::HighValue => "High-Value".to_string(),
+            HandlingAttribute::StackLimit(limit) => format!("Stack Limit ({})", limit),
+        }
+    }
+
+    // Inverse of `label`, used when reading attributes back from exported files
+    pub fn parse_label(label: &str) -> Option<HandlingAttribute> {
+        let label = label.trim();
+        let inner = |prefix: &str| label.strip_prefix(prefix).and_then(|rest| rest.strip_prefix('(')).and_then(|rest| rest.strip_suffix(')'));
+        match label {
+            "Fragile" => return Some(HandlingAttribute::Fragile),
+            "Food" => return Some(HandlingAttribute::Food),
+            "High-Value" => return Some(HandlingAttribute::HighValue),
+            _ => {},
+        }
+        if let Some(zone) = ClimateZone::ALL.into_iter().find(|z| z.label() == label) {
+            return Some(HandlingAttribute::Climate(zone));
+        }
+        if let Some(class) = inner("Hazardous ") {
+            return HazardClass::ALL.into_iter().find(|c| c.label() == class).map(HandlingAttribute::Hazardous);
+        }
+        if let Some(limit) = inner("Stack Limit ") {
+            return limit.parse().ok().map(HandlingAttribute::StackLimit);
+        }
+        if let Some(range) = label.strip_prefix("Níveis ") {
+            let (nivel_minimo, nivel_maximo) = range.split_once('-')?;
+            return Some(HandlingAttribute::LevelRange { nivel_minimo: nivel_minimo.parse().ok()?, nivel_maximo: nivel_maximo.parse().ok()? });
+        }
+        if let Some(dims) = inner("Oversized ") {
+            let numbers: Vec<u32> = dims.split(" x ").filter_map(|part| part.split_whitespace().next()?.parse().ok()).collect();
+            if let [required_zones, required_levels, required_shelves] = numbers[..] {
+                return Some(HandlingAttribute::Oversized { required_zones, required_levels, required_shelves });
+            }
+        }
+        None
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Item {
-    pub(crate) num_id: u32,
+    pub record_id: u64, // assigned by the warehouse when the item is stored
+    #[serde(rename = "product_id")]
+    pub num_id: u32,
     pub name: String,
-    pub(crate) item_quantity: u32,
-    pub(crate) item_quality: ItemQuality,
-    pub(crate) timestamp: i64,
-    pub(crate) data_validade: Option<NaiveDate>,
-    pub(crate) nivel_maximo: Option<u32>,
-    pub(crate) required_zones: Option<u32>,
+    #[serde(rename = "quantity")]
+    pub item_quantity: u32,
+    pub attributes: Vec<HandlingAttribute>,
+    pub timestamp: i64,
+    #[serde(rename = "expiry_date")]
+    pub data_validade: Option<NaiveDate>,
+    pub provenance: Option<Provenance>,
+    pub lot: Option<String>,
+    pub serials: Vec<String>, // one per unit when the product is serialised
+    pub unit_cost: f64, // purchase cost per base unit, set on receipt
 }
 
 impl Item {
@@ -24,21 +94,182 @@ impl Item {
         num_id: u32,
         name: String,
         item_quantity: u32,
-        item_quality: ItemQuality,
+        mut attributes: Vec<HandlingAttribute>,
         data_validade: Option<NaiveDate>,
-        nivel_maximo: Option<u32>,
-        required_zones: Option<u32>,
     ) -> Item {
+        // Keep attributes in a canonical order so equal sets hash and display the same way
+        attributes.sort();
+        attributes.dedup();
         let timestamp: i64 = Utc::now().timestamp();
         Item {
+            record_id: 0,
             num_id,
             name,
             item_quantity,
-            item_quality,
+            attributes,
             timestamp,
             data_validade,
-            nivel_maximo,
-            required_zones,
+            provenance: None,
+            lot: None,
+            serials: Vec::new(),
+            unit_cost: 0.0,
+        }
+    }
+
+    pub fn is_fragile(&self) -> bool {
+        self.attributes.contains(&HandlingAttribute::Fragile)
+    }
+
+    pub fn is_serialised(&self) -> bool {
+        !self.serials.is_empty()
+    }
+
+    // Serialised records carry exactly one distinct serial number per unit
+    pub fn validate_tracking(&self) -> Result<(), String> {
+        if self.lot.as_ref().is_some_and(|lot| lot.trim().is_empty()) {
+            return Err("Número de lote não pode estar vazio.".to_string());
+        }
+        if !self.is_serialised() {
+            return Ok(());
+        }
+        if self.serials.len() != self.item_quantity as usize {
+            return Err(format!(
+                "Foram indicados {} número(s) de série para {} unidade(s).",
+                self.serials.len(), self.item_quantity
+            ));
+        }
+        let mut unique = self.serials.clone();
+        unique.sort();
+        unique.dedup();
+        if unique.len() != self.serials.len() {
+            return Err("Números de série repetidos.".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn is_oversized(&self) -> bool {
+        self.attributes.iter().any(|a| matches!(a, HandlingAttribute::Oversized { .. }))
+    }
+
+    // Explicit (nivel_minimo, nivel_maximo) the item may be stored on, if any
+    pub fn level_range(&self) -> Option<(u32, u32)> {
+        self.attributes.iter().find_map(|a| match a {
+            HandlingAttribute::LevelRange { nivel_minimo, nivel_maximo } => Some((*nivel_minimo, *nivel_maximo)),
+            _ => None,
+        })
+    }
+
+    // Checks the level constraints against a warehouse with `levels` levels so impossible items are rejected up front
+    pub fn validate_levels(&self, levels: u32) -> Result<(), String> {
+        if levels == 0 {
+            return Err("O armazém não tem níveis de armazenamento.".to_string());
+        }
+        let top = levels - 1;
+
+        let (nivel_minimo, nivel_maximo) = self.level_range().unwrap_or((0, top));
+        if nivel_minimo > nivel_maximo {
+            return Err(format!("Intervalo de níveis impossível: mínimo {} acima do máximo {}.", nivel_minimo, nivel_maximo));
         }
+        if nivel_minimo > top {
+            return Err(format!("Nível mínimo {} não existe; o armazém só tem os níveis 0 a {}.", nivel_minimo, top));
+        }
+
+        let mut highest = nivel_maximo.min(top);
+        for attribute in &self.attributes {
+            match attribute {
+                HandlingAttribute::StackLimit(0) => {
+                    return Err("Limite de empilhamento tem de ser pelo menos 1.".to_string());
+                },
+                HandlingAttribute::StackLimit(limit) => highest = highest.min(limit - 1),
+                _ => {}
+            }
+        }
+        if nivel_minimo > highest {
+            return Err(format!("Nenhum nível a partir de {} é compatível com os atributos do item.", nivel_minimo));
+        }
+
+        let (_, required_levels, _) = self.footprint();
+        if required_levels > highest - nivel_minimo + 1 {
+            return Err(format!("O item ocupa {} níveis mas só os níveis {} a {} são permitidos.", required_levels, nivel_minimo, highest));
+        }
+
+        Ok(())
+    }
+
+    // Block of contiguous cells the item occupies as (zones, levels, shelves); a single cell unless it is oversized
+    pub fn footprint(&self) -> (u32, u32, u32) {
+        self.attributes.iter().find_map(|a| match a {
+            HandlingAttribute::Oversized { required_zones, required_levels, required_shelves } => Some((*required_zones, *required_levels, *required_shelves)),
+            _ => None,
+        }).unwrap_or((1, 1, 1))
+    }
+
+    // Storage climate the item requires; anything without an explicit requirement is ambient
+    pub fn climate(&self) -> ClimateZone {
+        self.attributes.iter().find_map(|a| match a {
+            HandlingAttribute::Climate(zone) => Some(*zone),
+            _ => None,
+        }).unwrap_or(ClimateZone::Ambient)
+    }
+
+    pub fn segregation_groups(&self) -> Vec<SegregationGroup> {
+        self.attributes.iter().filter_map(|a| match a {
+            HandlingAttribute::Hazardous(class) => Some(SegregationGroup::Hazard(*class)),
+            HandlingAttribute::Food => Some(SegregationGroup::Food),
+            _ => None,
+        }).collect()
+    }
+
+    pub fn quality_label(&self) -> String {
+        if self.attributes.is_empty() {
+            "Normal".to_string()
+        } else {
+            self.attributes.iter().map(|a| a.label()).collect::<Vec<_>>().join(" + ")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(attributes: Vec<HandlingAttribute>) -> Item {
+        Item::new(1, "Caixa".to_string(), 1, attributes, None)
+    }
+
+    fn range(nivel_minimo: u32, nivel_maximo: u32) -> HandlingAttribute {
+        HandlingAttribute::LevelRange { nivel_minimo, nivel_maximo }
+    }
+
+    #[test]
+    fn a_warehouse_without_levels_is_rejected() {
+        assert!(item(vec![]).validate_levels(0).is_err());
+        assert!(item(vec![range(0, 0)]).validate_levels(0).is_err());
+        assert!(item(vec![HandlingAttribute::StackLimit(1)]).validate_levels(0).is_err());
+    }
+
+    #[test]
+    fn the_minimum_cannot_be_above_the_maximum() {
+        assert!(item(vec![range(3, 1)]).validate_levels(5).is_err());
+        assert!(item(vec![range(2, 2)]).validate_levels(5).is_ok());
+    }
+
+    #[test]
+    fn a_maximum_past_the_top_level_is_capped() {
+        assert!(item(vec![range(1, 4)]).validate_levels(4).is_ok());
+        assert!(item(vec![range(1, 9)]).validate_levels(4).is_ok());
+        assert!(item(vec![range(4, 9)]).validate_levels(4).is_err());
+
+        // The cap still has to leave room for the whole footprint
+        let tall = HandlingAttribute::Oversized { required_zones: 1, required_levels: 3, required_shelves: 1 };
+        assert!(item(vec![range(1, 9), tall.clone()]).validate_levels(4).is_ok());
+        assert!(item(vec![range(2, 9), tall]).validate_levels(4).is_err());
+    }
+
+    #[test]
+    fn stack_limits_lower_the_highest_level() {
+        assert!(item(vec![HandlingAttribute::StackLimit(0)]).validate_levels(4).is_err());
+        assert!(item(vec![range(2, 3), HandlingAttribute::StackLimit(2)]).validate_levels(4).is_err());
+        assert!(item(vec![range(1, 3), HandlingAttribute::StackLimit(2)]).validate_levels(4).is_ok());
     }
 }
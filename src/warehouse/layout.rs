@@ -0,0 +1,104 @@
+use super::warehouse::Location;
+use serde::{Deserialize, Serialize};
+
+// Physical model of the building used to measure walking distance (all values in metres).
+// Rows are parallel aisles running along the shelves, joined by a cross aisle at the front
+// (where the dock is) and another at the back.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WarehouseLayout {
+    pub row_spacing: f32,
+    pub shelf_width: f32,
+    pub zone_width: f32,
+    pub dock: (f32, f32), // (x, y): x along the aisles from the front cross aisle, y across the rows (row r is at r * row_spacing)
+}
+
+impl Default for WarehouseLayout {
+    fn default() -> Self {
+        WarehouseLayout {
+            row_spacing: 3.0,
+            shelf_width: 2.5,
+            zone_width: 0.5,
+            dock: (0.0, 0.0),
+        }
+    }
+}
+
+impl WarehouseLayout {
+    fn position(&self, loc: &Location) -> (f32, f32) {
+        let x = loc.shelf as f32 * self.shelf_width + (loc.zone as f32 + 0.5) * self.zone_width;
+        let y = loc.row as f32 * self.row_spacing;
+        (x, y)
+    }
+
+    pub fn distance(&self, a: &Location, b: &Location, aisle_length: f32) -> f32 {
+        let (xa, ya) = self.position(a);
+        let (xb, yb) = self.position(b);
+        if a.row == b.row {
+            (xa - xb).abs()
+        } else {
+            // Changing aisle means leaving through the front or the back cross aisle
+            let via_front = xa + xb;
+            let via_back = (aisle_length - xa) + (aisle_length - xb);
+            via_front.min(via_back) + (ya - yb).abs()
+        }
+    }
+
+    pub fn distance_from_dock(&self, loc: &Location) -> f32 {
+        let (x, y) = self.position(loc);
+        (x - self.dock.0).abs() + (y - self.dock.1).abs()
+    }
+
+    pub fn aisle_length(&self, max_dimensions: (u32, u32, u32, u32)) -> f32 {
+        let (_, shelves, _, _) = max_dimensions;
+        shelves as f32 * self.shelf_width
+    }
+
+    // Length of a round trip that starts and ends at the dock
+    pub fn route_length(&self, route: &[Location], aisle_length: f32) -> f32 {
+        match (route.first(), route.last()) {
+            (Some(first), Some(last)) => {
+                let legs: f32 = route.windows(2).map(|w| self.distance(&w[0], &w[1], aisle_length)).sum();
+                self.distance_from_dock(first) + legs + self.distance_from_dock(last)
+            },
+            _ => 0.0,
+        }
+    }
+
+    // Nearest-neighbour tour from the dock, then improved with 2-opt until no swap shortens it
+    pub fn optimise_route(&self, locations: &[Location], aisle_length: f32) -> Vec<Location> {
+        let mut remaining: Vec<Location> = locations.to_vec();
+        let mut route = Vec::with_capacity(remaining.len());
+
+        while !remaining.is_empty() {
+            let next = match route.last() {
+                None => (0..remaining.len())
+                    .min_by(|&i, &j| self.distance_from_dock(&remaining[i]).total_cmp(&self.distance_from_dock(&remaining[j])))
+                    .unwrap_or(0),
+                Some(current) => (0..remaining.len())
+                    .min_by(|&i, &j| self.distance(current, &remaining[i], aisle_length).total_cmp(&self.distance(current, &remaining[j], aisle_length)))
+                    .unwrap_or(0),
+            };
+            route.push(remaining.remove(next));
+        }
+
+        let mut best = self.route_length(&route, aisle_length);
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for i in 0..route.len() {
+                for j in (i + 1)..route.len() {
+                    route[i..=j].reverse();
+                    let length = self.route_length(&route, aisle_length);
+                    if length + 1e-3 < best {
+                        best = length;
+                        improved = true;
+                    } else {
+                        route[i..=j].reverse();
+                    }
+                }
+            }
+        }
+
+        route
+    }
+}
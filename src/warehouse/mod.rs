@@ -1,2 +1,25 @@
+pub mod climate;
+pub mod cyclecount;
+pub mod events;
+pub mod hazard;
+pub mod inbound;
+pub mod inventory_csv;
 pub mod item;
-pub mod warehouse;
\ No newline at end of file
+pub mod layout;
+pub mod movement;
+pub mod order;
+pub mod recall;
+pub mod replenishment;
+pub mod reservation;
+pub mod serde_map;
+pub mod shared;
+pub mod sites;
+pub mod slotting;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod storage;
+pub mod uom;
+pub mod users;
+pub mod valuation;
+#[allow(clippy::module_inception)]
+pub mod warehouse;
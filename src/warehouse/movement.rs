@@ -0,0 +1,74 @@
+use super::warehouse::Location;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MovementKind {
+    Inbound,
+    Outbound,
+    Removal,
+    Adjustment,
+    Relocation,
+}
+
+impl MovementKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MovementKind::Inbound => "Entrada",
+            MovementKind::Outbound => "Saída",
+            MovementKind::Removal => "Remoção",
+            MovementKind::Adjustment => "Ajuste",
+            MovementKind::Relocation => "Relocalização",
+        }
+    }
+}
+
+// Why an on-hand quantity was corrected
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdjustmentReason {
+    CycleCount,
+    Damaged,
+    Lost,
+    Found,
+    Correction,
+}
+
+impl AdjustmentReason {
+    pub const ALL: [AdjustmentReason; 5] = [
+        AdjustmentReason::CycleCount,
+        AdjustmentReason::Damaged,
+        AdjustmentReason::Lost,
+        AdjustmentReason::Found,
+        AdjustmentReason::Correction,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AdjustmentReason::CycleCount => "Contagem Cíclica",
+            AdjustmentReason::Damaged => "Danificado",
+            AdjustmentReason::Lost => "Perda/Furto",
+            AdjustmentReason::Found => "Encontrado",
+            AdjustmentReason::Correction => "Correção de Registo",
+        }
+    }
+}
+
+// One entry of the stock movement history; `quantity_change` is the effect on on-hand stock
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Movement {
+    pub timestamp: i64,
+    pub kind: MovementKind,
+    pub record_id: u64,
+    #[serde(rename = "product_id")]
+    pub num_id: u32,
+    pub name: String,
+    pub quantity_change: i64,
+    pub from: Option<Location>,
+    pub to: Option<Location>,
+    pub reason: Option<AdjustmentReason>, // only set for adjustments
+    pub lot: Option<String>,
+    pub serials: Vec<String>, // serial numbers that moved, if the product is serialised
+    pub unit_cost: f64,
+    pub user: Option<String>, // who made the change, when signed in
+}
@@ -0,0 +1,342 @@
+use super::item::Item;
+use super::storage::StorageBackend;
+use super::warehouse::{Location, Warehouse};
+use chrono::NaiveDate;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    Open,
+    Allocated,
+    Picking,
+    Shipped,
+}
+
+impl OrderStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            OrderStatus::Open => "Aberta",
+            OrderStatus::Allocated => "Alocada",
+            OrderStatus::Picking => "Em Picking",
+            OrderStatus::Shipped => "Expedida",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OrderLine {
+    #[serde(rename = "product_id")]
+    pub num_id: u32,
+    pub quantity: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Order {
+    pub id: u32,
+    pub customer: String,
+    pub lines: Vec<OrderLine>,
+    pub status: OrderStatus,
+    pub reservation_ids: Vec<u32>, // reservations still held, i.e. not yet picked
+}
+
+impl Order {
+    pub fn reference(&self) -> String {
+        format!("ENC-{}", self.id)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PickTask {
+    pub order_id: u32,
+    pub reservation_id: u32,
+    pub location: Location,
+    #[serde(rename = "product_id")]
+    pub num_id: u32,
+    pub name: String,
+    pub quantity: u32,
+    pub picked: bool,
+    pub lot: Option<String>,
+    pub serials: Vec<String>, // filled in when the pick is confirmed
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Wave {
+    pub id: u32,
+    pub order_ids: Vec<u32>,
+    pub tasks: Vec<PickTask>,
+}
+
+// A stop on an ad-hoc pick list, for stock taken without an order or reservation
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PickStop {
+    pub location: Location,
+    #[serde(rename = "product_id")]
+    pub num_id: u32,
+    pub name: String,
+    pub quantity: u32,
+    pub lot: Option<String>,
+}
+
+// Where to take `lines` from, chosen the way `allocate` chooses stock, in walking order.
+// Nothing is reserved, so the list is only good for as long as the stock stays put.
+pub fn pick_list<S: StorageBackend>(warehouse: &Warehouse<S>, lines: &[OrderLine]) -> Result<Vec<PickStop>, String> {
+    let mut taken: HashMap<u64, u32> = HashMap::new();
+    let mut stops = Vec::new();
+    for line in lines {
+        let mut remaining = line.quantity;
+        for (itm, locs) in fefo_records(warehouse, line.num_id) {
+            if remaining == 0 {
+                break;
+            }
+            let already = taken.entry(itm.record_id).or_insert(0);
+            let available = itm.item_quantity.saturating_sub(warehouse.reserved_quantity(itm.record_id) + *already);
+            let take = available.min(remaining);
+            if take == 0 {
+                continue;
+            }
+            *already += take;
+            remaining -= take;
+            stops.push(PickStop { location: locs[0].clone(), num_id: itm.num_id, name: itm.name.clone(), quantity: take, lot: itm.lot.clone() });
+        }
+        if remaining > 0 {
+            return Err(format!("Stock insuficiente para o ID {}: faltam {} unidade(s).", line.num_id, remaining));
+        }
+    }
+
+    let locations: Vec<Location> = stops.iter().map(|s| s.location.clone()).collect();
+    let route = warehouse.optimise_route(&locations);
+    stops.sort_by_key(|s| route.iter().position(|loc| *loc == s.location));
+    Ok(stops)
+}
+
+// Stored records of a product, earliest expiry first and then oldest stock first
+pub fn fefo_records<S: StorageBackend>(warehouse: &Warehouse<S>, num_id: u32) -> Vec<(Item, Vec<Location>)> {
+    let mut records = warehouse.search_locations_by_id(num_id);
+    records.sort_by(|(a, _), (b, _)| {
+        let expiry = match (a.data_validade, b.data_validade) {
+            (Some(da), Some(db)) => da.cmp(&db),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        expiry.then(a.timestamp.cmp(&b.timestamp))
+    });
+    records
+}
+
+// Orders and waves are never deleted, so their IDs simply follow the list length
+#[derive(Default, Serialize, Deserialize)]
+pub struct OrderManager {
+    pub orders: Vec<Order>,
+    pub waves: Vec<Wave>,
+}
+
+impl OrderManager {
+    pub fn create_order(&mut self, customer: String, lines: Vec<OrderLine>) -> Result<u32, String> {
+        if customer.trim().is_empty() {
+            return Err("Cliente não pode estar vazio.".to_string());
+        }
+        if lines.is_empty() || lines.iter().any(|l| l.quantity == 0) {
+            return Err("A encomenda precisa de pelo menos uma linha com quantidade maior que zero.".to_string());
+        }
+
+        let id = self.orders.len() as u32 + 1;
+        self.orders.push(Order {
+            id,
+            customer,
+            lines,
+            status: OrderStatus::Open,
+            reservation_ids: Vec::new(),
+        });
+        Ok(id)
+    }
+
+    // Units of each product not picked yet, over every wave the order has been in, in line order
+    fn outstanding(&self, order: &Order) -> Vec<(u32, u32)> {
+        let mut outstanding: Vec<(u32, u32)> = Vec::new();
+        for line in &order.lines {
+            match outstanding.iter_mut().find(|(num_id, _)| *num_id == line.num_id) {
+                Some((_, quantity)) => *quantity += line.quantity,
+                None => outstanding.push((line.num_id, line.quantity)),
+            }
+        }
+        let picked = self.waves.iter().flat_map(|w| &w.tasks).filter(|t| t.order_id == order.id && t.picked);
+        for task in picked {
+            if let Some((_, quantity)) = outstanding.iter_mut().find(|(num_id, _)| *num_id == task.num_id) {
+                *quantity = quantity.saturating_sub(task.quantity);
+            }
+        }
+        outstanding.retain(|(_, quantity)| *quantity > 0);
+        outstanding
+    }
+
+    // Reserves stock for what is still to be picked of every line, earliest expiry first and then
+    // oldest stock first. Either every line is covered or nothing stays reserved.
+    pub fn allocate<S: StorageBackend>(&mut self, order_id: u32, warehouse: &mut Warehouse<S>, expires: NaiveDate) -> Result<(), String> {
+        let index = self.orders.iter().position(|o| o.id == order_id).ok_or(format!("Encomenda {} não encontrada.", order_id))?;
+        let order = &self.orders[index];
+        if order.status != OrderStatus::Open {
+            return Err(format!("Encomenda {} já está {}.", order_id, order.status.label()));
+        }
+
+        let reference = order.reference();
+        let outstanding = self.outstanding(order);
+        let reservation_ids = warehouse.transaction(|warehouse| {
+            let mut reservation_ids = Vec::new();
+            for (num_id, quantity) in outstanding {
+                let mut remaining = quantity;
+                for (itm, locs) in fefo_records(warehouse, num_id) {
+                    if remaining == 0 {
+                        break;
+                    }
+                    let available = itm.item_quantity.saturating_sub(warehouse.reserved_quantity(itm.record_id));
+                    let take = available.min(remaining);
+                    if take == 0 {
+                        continue;
+                    }
+                    let reservation = warehouse.reserve(&locs[0], take, reference.clone(), expires)?;
+                    reservation_ids.push(reservation.id);
+                    remaining -= take;
+                }
+
+                if remaining > 0 {
+                    return Err(format!("Stock insuficiente para o ID {}: faltam {} unidade(s).", num_id, remaining));
+                }
+            }
+            Ok(reservation_ids)
+        })?;
+
+        let order = &mut self.orders[index];
+        order.reservation_ids = reservation_ids;
+        order.status = OrderStatus::Allocated;
+        Ok(())
+    }
+
+    // Allocated or picking orders that lost a reservation (expired, released or its stock gone) go
+    // back to Open, giving up the reservations they still hold so they can be allocated again.
+    // Tasks already picked stay in their waves; the rest are dropped and allocated afresh.
+    pub fn reopen_lapsed<S: StorageBackend>(&mut self, warehouse: &mut Warehouse<S>) -> Result<Vec<u32>, String> {
+        let mut reopened = Vec::new();
+        for order in self.orders.iter_mut().filter(|o| matches!(o.status, OrderStatus::Allocated | OrderStatus::Picking)) {
+            let lapsed = order.reservation_ids.iter().any(|id| {
+                !warehouse.reservations.iter().any(|r| r.id == *id && !warehouse.record_locations(r.record_id).is_empty())
+            });
+            if !lapsed {
+                continue;
+            }
+            for id in &order.reservation_ids {
+                if warehouse.reservations.iter().any(|r| r.id == *id) {
+                    warehouse.release_reservation(*id)?;
+                }
+            }
+            for wave in &mut self.waves {
+                wave.tasks.retain(|t| t.order_id != order.id || t.picked);
+            }
+            order.reservation_ids.clear();
+            order.status = OrderStatus::Open;
+            reopened.push(order.id);
+        }
+        Ok(reopened)
+    }
+
+    // Groups up to `max_orders` allocated orders into a wave whose pick list follows the walking path.
+    // Orders whose reservations lapsed are reopened first instead of being picked short.
+    pub fn create_wave<S: StorageBackend>(&mut self, max_orders: usize, warehouse: &mut Warehouse<S>) -> Result<u32, String> {
+        if max_orders == 0 {
+            return Err("Uma vaga precisa de pelo menos uma encomenda.".to_string());
+        }
+        self.reopen_lapsed(warehouse)?;
+
+        let mut order_ids = Vec::new();
+        let mut tasks = Vec::new();
+        for order in self.orders.iter_mut().filter(|o| o.status == OrderStatus::Allocated).take(max_orders) {
+            for reservation in warehouse.reservations.iter().filter(|r| order.reservation_ids.contains(&r.id)) {
+                let locations = warehouse.record_locations(reservation.record_id);
+                if let Some(itm) = locations.first().and_then(|loc| warehouse.item_at(loc)) {
+                    tasks.push(PickTask {
+                        order_id: order.id,
+                        reservation_id: reservation.id,
+                        location: locations[0].clone(),
+                        num_id: itm.num_id,
+                        name: itm.name.clone(),
+                        quantity: reservation.quantity,
+                        picked: false,
+                        lot: itm.lot.clone(),
+                        serials: Vec::new(),
+                    });
+                }
+            }
+            order.status = OrderStatus::Picking;
+            order_ids.push(order.id);
+        }
+
+        if order_ids.is_empty() {
+            return Err("Não há encomendas alocadas para criar uma vaga.".to_string());
+        }
+
+        // Follow the optimised route; tasks sharing a location stay together
+        let locations: Vec<Location> = tasks.iter().map(|t| t.location.clone()).collect();
+        let route = warehouse.optimise_route(&locations);
+        tasks.sort_by_key(|t| route.iter().position(|loc| *loc == t.location));
+
+        let id = self.waves.len() as u32 + 1;
+        self.waves.push(Wave { id, order_ids, tasks });
+        Ok(id)
+    }
+
+    // Takes the picked quantity out of the warehouse and ships the order once all its tasks are done
+    pub fn confirm_pick<S: StorageBackend>(&mut self, wave_id: u32, task_index: usize, warehouse: &mut Warehouse<S>, today: NaiveDate) -> Result<PickTask, String> {
+        let wave_index = self.waves.iter().position(|w| w.id == wave_id).ok_or(format!("Vaga {} não encontrada.", wave_id))?;
+        let task = self.waves[wave_index].tasks.get(task_index).ok_or("Tarefa de picking não encontrada.".to_string())?;
+        if task.picked {
+            return Err("Esta tarefa já foi confirmada.".to_string());
+        }
+
+        let picked = warehouse.pick_reservation(task.reservation_id, today)?;
+        Ok(self.mark_picked(wave_index, task_index, picked.serials))
+    }
+
+    // Confirms every outstanding task of a wave in one warehouse transaction: if any pick fails,
+    // nothing is taken out and no task changes
+    pub fn confirm_wave<S: StorageBackend>(&mut self, wave_id: u32, warehouse: &mut Warehouse<S>, today: NaiveDate) -> Result<Vec<PickTask>, String> {
+        let wave_index = self.waves.iter().position(|w| w.id == wave_id).ok_or(format!("Vaga {} não encontrada.", wave_id))?;
+        let pending: Vec<(usize, u32)> = self.waves[wave_index].tasks.iter()
+            .enumerate()
+            .filter(|(_, t)| !t.picked)
+            .map(|(index, t)| (index, t.reservation_id))
+            .collect();
+        if pending.is_empty() {
+            return Err("Todas as tarefas desta vaga já foram confirmadas.".to_string());
+        }
+
+        let picked = warehouse.transaction(|warehouse| {
+            pending.iter()
+                .map(|(index, reservation_id)| warehouse.pick_reservation(*reservation_id, today).map(|itm| (*index, itm.serials)))
+                .collect::<Result<Vec<_>, String>>()
+        })?;
+        Ok(picked.into_iter().map(|(index, serials)| self.mark_picked(wave_index, index, serials)).collect())
+    }
+
+    // Marks a task done and ships its order once picked tasks, in this wave or earlier ones, cover
+    // every one of its lines
+    fn mark_picked(&mut self, wave_index: usize, task_index: usize, serials: Vec<String>) -> PickTask {
+        let task = &mut self.waves[wave_index].tasks[task_index];
+        task.picked = true;
+        task.serials = serials;
+        let task = task.clone();
+
+        let Some(index) = self.orders.iter().position(|o| o.id == task.order_id) else {
+            return task;
+        };
+        let shipped = self.outstanding(&self.orders[index]).is_empty();
+        let order = &mut self.orders[index];
+        order.reservation_ids.retain(|id| *id != task.reservation_id);
+        if shipped {
+            order.status = OrderStatus::Shipped;
+        }
+        task
+    }
+}
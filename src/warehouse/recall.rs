@@ -0,0 +1,35 @@
+use super::item::Item;
+use super::movement::{Movement, MovementKind};
+use super::storage::StorageBackend;
+use super::warehouse::{Location, Warehouse};
+use serde::{Deserialize, Serialize};
+
+// Everything known about a lot: where it still sits and every movement it went through
+#[derive(Serialize, Deserialize)]
+pub struct RecallReport {
+    pub lot: String,
+    pub stored: Vec<(Item, Vec<Location>)>,
+    pub movements: Vec<Movement>,
+}
+
+impl RecallReport {
+    pub fn on_hand(&self) -> u32 {
+        self.stored.iter().map(|(itm, _)| itm.item_quantity).sum()
+    }
+
+    // Units of the lot that already left the warehouse to customers
+    pub fn shipped(&self) -> u32 {
+        self.movements.iter()
+            .filter(|m| m.kind == MovementKind::Outbound)
+            .map(|m| m.quantity_change.unsigned_abs() as u32)
+            .sum()
+    }
+}
+
+pub fn recall_report<S: StorageBackend>(warehouse: &Warehouse<S>, lot: &str) -> RecallReport {
+    RecallReport {
+        lot: lot.to_string(),
+        stored: warehouse.search_by_lot(lot),
+        movements: warehouse.movements.iter().filter(|m| m.lot.as_deref() == Some(lot)).cloned().collect(),
+    }
+}
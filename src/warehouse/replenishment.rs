@@ -0,0 +1,176 @@
+use super::storage::StorageBackend;
+use super::warehouse::Warehouse;
+use serde::{Deserialize, Serialize};
+
+// Where a min/max rule is measured: the whole warehouse or a single row used as pick zone
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplenishmentScope {
+    Total,
+    PickZone(u32),
+}
+
+impl ReplenishmentScope {
+    pub fn label(&self) -> String {
+        match self {
+            ReplenishmentScope::Total => "Total".to_string(),
+            ReplenishmentScope::PickZone(row) => format!("Zona de picking (fileira {})", row),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReplenishmentRule {
+    #[serde(rename = "product_id")]
+    pub num_id: u32,
+    pub scope: ReplenishmentScope,
+    pub min: u32,
+    pub max: u32,
+}
+
+// Brings a product back up to its maximum: pick zones are refilled from the rest of the
+// warehouse first, and whatever cannot be moved internally has to be bought
+#[derive(Serialize, Deserialize)]
+pub struct ReplenishmentSuggestion {
+    #[serde(rename = "product_id")]
+    pub num_id: u32,
+    pub name: String,
+    pub scope: ReplenishmentScope,
+    pub available: u32,
+    pub min: u32,
+    pub max: u32,
+    pub transfer: u32,
+    pub purchase: u32,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct ReplenishmentRules {
+    pub rules: Vec<ReplenishmentRule>,
+}
+
+impl ReplenishmentRules {
+    // Adds the rule, replacing any previous one for the same product and scope
+    pub fn set_rule(&mut self, num_id: u32, scope: ReplenishmentScope, min: u32, max: u32) -> Result<(), String> {
+        if min > max {
+            return Err(format!("O mínimo ({}) não pode ser maior que o máximo ({}).", min, max));
+        }
+        if max == 0 {
+            return Err("O máximo tem de ser maior que zero.".to_string());
+        }
+        self.rules.retain(|r| !(r.num_id == num_id && r.scope == scope));
+        self.rules.push(ReplenishmentRule { num_id, scope, min, max });
+        Ok(())
+    }
+
+    pub fn remove_rule(&mut self, num_id: u32, scope: ReplenishmentScope) -> Result<(), String> {
+        let before = self.rules.len();
+        self.rules.retain(|r| !(r.num_id == num_id && r.scope == scope));
+        if self.rules.len() == before {
+            return Err("Regra de reposição não encontrada.".to_string());
+        }
+        Ok(())
+    }
+
+    // Unreserved stock of a product within the scope
+    fn available<S: StorageBackend>(warehouse: &Warehouse<S>, num_id: u32, scope: ReplenishmentScope) -> u32 {
+        match scope {
+            ReplenishmentScope::Total => warehouse.search_by_id(num_id).available,
+            ReplenishmentScope::PickZone(row) => warehouse.grouped_items()
+                .into_iter()
+                .filter(|(itm, locs)| itm.num_id == num_id && locs[0].row == row)
+                .map(|(itm, _)| itm.item_quantity.saturating_sub(warehouse.reserved_quantity(itm.record_id)))
+                .sum(),
+        }
+    }
+
+    // Every rule whose scope has dropped below its minimum, with the quantities to restore the maximum
+    pub fn report<S: StorageBackend>(&self, warehouse: &Warehouse<S>) -> Vec<ReplenishmentSuggestion> {
+        let mut suggestions = Vec::new();
+        for rule in &self.rules {
+            let available = Self::available(warehouse, rule.num_id, rule.scope);
+            if available >= rule.min {
+                continue;
+            }
+            let needed = rule.max - available;
+            let transfer = match rule.scope {
+                ReplenishmentScope::Total => 0,
+                ReplenishmentScope::PickZone(_) => {
+                    let elsewhere = warehouse.search_by_id(rule.num_id).available.saturating_sub(available);
+                    needed.min(elsewhere)
+                },
+            };
+            let name = warehouse.search_locations_by_id(rule.num_id)
+                .first()
+                .map(|(itm, _)| itm.name.clone())
+                .unwrap_or_default();
+            suggestions.push(ReplenishmentSuggestion {
+                num_id: rule.num_id,
+                name,
+                scope: rule.scope,
+                available,
+                min: rule.min,
+                max: rule.max,
+                transfer,
+                purchase: needed - transfer,
+            });
+        }
+        suggestions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::warehouse::item::Item;
+    use chrono::NaiveDate;
+
+    fn stocked(quantity: u32) -> Warehouse {
+        let mut warehouse = Warehouse::new((3, 2, 2, 2));
+        warehouse.add_item(Item::new(1, "Parafuso".to_string(), quantity, vec![], None)).unwrap();
+        warehouse
+    }
+
+    #[test]
+    fn a_rule_triggers_only_below_its_minimum() {
+        let mut rules = ReplenishmentRules::default();
+        rules.set_rule(1, ReplenishmentScope::Total, 5, 20).unwrap();
+        assert!(rules.report(&stocked(5)).is_empty());
+
+        let suggestions = rules.report(&stocked(4));
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!((suggestions[0].available, suggestions[0].transfer, suggestions[0].purchase), (4, 0, 16));
+        assert_eq!(suggestions[0].name, "Parafuso");
+    }
+
+    #[test]
+    fn reserved_stock_does_not_count_as_available() {
+        let mut warehouse = stocked(8);
+        let loc = warehouse.record_locations(1).remove(0);
+        warehouse.reserve(&loc, 4, "ENC-1".to_string(), NaiveDate::from_ymd_opt(2099, 1, 1).unwrap()).unwrap();
+        let mut rules = ReplenishmentRules::default();
+        rules.set_rule(1, ReplenishmentScope::Total, 5, 10).unwrap();
+        assert_eq!(rules.report(&warehouse)[0].available, 4);
+    }
+
+    #[test]
+    fn pick_zones_are_refilled_from_the_rest_of_the_warehouse_first() {
+        let warehouse = stocked(6);
+        let row = warehouse.record_locations(1)[0].row;
+        let pick_row = (row + 1) % 3;
+        let mut rules = ReplenishmentRules::default();
+        rules.set_rule(1, ReplenishmentScope::PickZone(pick_row), 2, 10).unwrap();
+
+        let suggestion = &rules.report(&warehouse)[0];
+        assert_eq!((suggestion.available, suggestion.transfer, suggestion.purchase), (0, 6, 4));
+    }
+
+    #[test]
+    fn rules_need_a_sensible_range() {
+        let mut rules = ReplenishmentRules::default();
+        assert!(rules.set_rule(1, ReplenishmentScope::Total, 6, 5).is_err());
+        assert!(rules.set_rule(1, ReplenishmentScope::Total, 0, 0).is_err());
+        rules.set_rule(1, ReplenishmentScope::Total, 1, 5).unwrap();
+        rules.set_rule(1, ReplenishmentScope::Total, 2, 8).unwrap();
+        assert_eq!((rules.rules.len(), rules.rules[0].max), (1, 8));
+    }
+}
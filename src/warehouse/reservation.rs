@@ -0,0 +1,33 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+// Soft allocation of part of a stored record to an outbound order before it is picked
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Reservation {
+    pub id: u32,
+    pub record_id: u64,
+    pub quantity: u32,
+    pub order_ref: String,
+    pub expires: NaiveDate,
+}
+
+impl Reservation {
+    pub fn is_expired(&self, today: NaiveDate) -> bool {
+        self.expires < today
+    }
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct StockSummary {
+    pub on_hand: u32,
+    pub reserved: u32,
+    pub available: u32,
+}
+
+impl StockSummary {
+    pub fn add(&mut self, on_hand: u32, reserved: u32) {
+        self.on_hand += on_hand;
+        self.reserved += reserved;
+        self.available += on_hand.saturating_sub(reserved);
+    }
+}
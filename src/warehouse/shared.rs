@@ -0,0 +1,48 @@
+use super::storage::{MemoryStorage, StorageBackend};
+use super::warehouse::Warehouse;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, RwLock};
+
+// Handle to one warehouse shared between threads (server, background sweeps, GUI). Clones refer
+// to the same warehouse. Readers run side by side; a writer has the warehouse to itself for the
+// whole closure, so an allocation sees every cell taken by earlier ones and two writers can never
+// be handed the same location. Several operations that must not interleave with other threads
+// belong in one `write` call; a `Warehouse::transaction` inside it can still undo its own part.
+pub struct SharedWarehouse<S = MemoryStorage> {
+    inner: Arc<RwLock<Warehouse<S>>>,
+}
+
+impl<S> Clone for SharedWarehouse<S> {
+    fn clone(&self) -> Self {
+        SharedWarehouse { inner: Arc::clone(&self.inner) }
+    }
+}
+
+impl<S: StorageBackend> SharedWarehouse<S> {
+    pub fn new(warehouse: Warehouse<S>) -> Self {
+        SharedWarehouse { inner: Arc::new(RwLock::new(warehouse)) }
+    }
+
+    pub fn read<T>(&self, f: impl FnOnce(&Warehouse<S>) -> T) -> T {
+        let guard = self.inner.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(&guard)
+    }
+
+    // Runs `f` as one transaction, committed when it returns. If the backend cannot persist the
+    // changes they are rolled back and its error returned. If `f` panics its changes are rolled
+    // back before the panic carries on, so the next writer finds the warehouse as it was before.
+    pub fn write<T>(&self, f: impl FnOnce(&mut Warehouse<S>) -> T) -> Result<T, String> {
+        let mut guard = self.inner.write().unwrap_or_else(|poisoned| {
+            self.inner.clear_poison();
+            poisoned.into_inner()
+        });
+        guard.begin()?;
+        match panic::catch_unwind(AssertUnwindSafe(|| f(&mut guard))) {
+            Ok(result) => guard.commit().map(|_| result),
+            Err(panic) => {
+                guard.rollback();
+                panic::resume_unwind(panic)
+            },
+        }
+    }
+}
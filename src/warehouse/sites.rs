@@ -0,0 +1,287 @@
+use super::cyclecount::CycleCountManager;
+use super::inbound::InboundManager;
+use super::item::Item;
+use super::order::{fefo_records, OrderManager};
+use super::replenishment::ReplenishmentRules;
+use super::storage::{MemoryStorage, StorageBackend};
+use super::warehouse::{Location, Warehouse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+
+// One physical warehouse with the work that belongs to it. Orders, receipts and counts refer to
+// record and reservation ids of their own warehouse, so they travel with it.
+#[derive(Serialize, Deserialize)]
+pub struct Site<S = MemoryStorage> {
+    pub warehouse: Warehouse<S>,
+    pub orders: OrderManager,
+    pub inbound: InboundManager,
+    pub cycle_counts: CycleCountManager,
+    pub replenishment: ReplenishmentRules,
+}
+
+impl Site {
+    pub fn from_json(json: &str) -> Result<Site, String> {
+        serde_json::from_str(json).map_err(|e| format!("JSON inválido: {}", e))
+    }
+}
+
+impl<S: StorageBackend + Serialize> Site<S> {
+    // The warehouse snapshot together with the site's orders, receipts, counts and rules
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+}
+
+impl<S: StorageBackend> Site<S> {
+    pub fn new(warehouse: Warehouse<S>) -> Site<S> {
+        Site {
+            warehouse,
+            orders: OrderManager::default(),
+            inbound: InboundManager::default(),
+            cycle_counts: CycleCountManager::default(),
+            replenishment: ReplenishmentRules::default(),
+        }
+    }
+
+    // The same site with the warehouse's backend converted, as in `Warehouse::map_storage`
+    pub fn map_storage<T: StorageBackend>(self, convert: impl FnOnce(S) -> T) -> Site<T> {
+        Site {
+            warehouse: self.warehouse.map_storage(convert),
+            orders: self.orders,
+            inbound: self.inbound,
+            cycle_counts: self.cycle_counts,
+            replenishment: self.replenishment,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferStatus {
+    InTransit,
+    Received,
+}
+
+impl TransferStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TransferStatus::InTransit => "Em Trânsito",
+            TransferStatus::Received => "Recebida",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TransferOrder {
+    pub id: u32,
+    pub from: String,
+    pub to: String,
+    #[serde(rename = "product_id")]
+    pub num_id: u32,
+    pub name: String,
+    pub quantity: u32,
+    pub items: Vec<Item>, // what left the source, one entry per record it was taken from
+    pub status: TransferStatus,
+    pub shipped_at: i64,
+    pub received_at: Option<i64>,
+}
+
+// Stock of one product across every site
+pub struct SiteStock {
+    pub num_id: u32,
+    pub name: String,
+    pub by_site: Vec<(String, u32)>, // on hand per site, sites without stock included
+    pub in_transit: u32,
+}
+
+impl SiteStock {
+    pub fn total(&self) -> u32 {
+        self.by_site.iter().map(|(_, quantity)| quantity).sum::<u32>() + self.in_transit
+    }
+}
+
+// The company's warehouses by name. The application works on one of them at a time (the active
+// site); the others are kept here until it switches to them. Transfers are shipped by the active
+// site and received by the active site, so each side is checked against whoever is signed in there.
+#[derive(Serialize, Deserialize)]
+pub struct SiteRegistry {
+    active: String,
+    parked: BTreeMap<String, Site>,
+    pub transfers: Vec<TransferOrder>,
+}
+
+impl SiteRegistry {
+    pub fn new(active: &str) -> SiteRegistry {
+        SiteRegistry { active: active.to_string(), parked: BTreeMap::new(), transfers: Vec::new() }
+    }
+
+    // None when the file does not exist, i.e. the snapshot was saved with a single site
+    pub fn load(path: &str) -> Result<Option<SiteRegistry>, String> {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map(Some).map_err(|e| format!("Ficheiro de armazéns inválido: {}", e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Não foi possível ler {}: {}", path, e)),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("Não foi possível gravar {}: {}", path, e))
+    }
+
+    pub fn active(&self) -> &str {
+        &self.active
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.parked.keys().cloned().collect();
+        names.push(self.active.clone());
+        names.sort();
+        names
+    }
+
+    // The new site starts with the climate zones, secure rows, segregation rules and packaging of
+    // `template` (the active warehouse); its stock, reservations, layout and history start empty
+    pub fn add_site<S: StorageBackend>(&mut self, name: &str, max_dimensions: (u32, u32, u32, u32), template: &Warehouse<S>) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("O nome do armazém não pode estar vazio.".to_string());
+        }
+        if name == self.active || self.parked.contains_key(name) {
+            return Err(format!("O armazém {} já existe.", name));
+        }
+        let (rows, shelves, levels, zones) = max_dimensions;
+        if rows == 0 || shelves == 0 || levels == 0 || zones == 0 {
+            return Err("As dimensões do armazém têm de ser maiores que zero.".to_string());
+        }
+        let mut warehouse = Warehouse::new(max_dimensions);
+        warehouse.climate = template.climate.clone();
+        warehouse.secure_rows = template.secure_rows.clone();
+        warehouse.segregation = template.segregation.clone();
+        warehouse.packaging = template.packaging.clone();
+        self.parked.insert(name.to_string(), Site::new(warehouse));
+        Ok(())
+    }
+
+    // Parks `current` under the active name and puts the site switched to in its place.
+    // The parked warehouse is signed out; signing in to the new one is up to the caller.
+    pub fn switch_to(&mut self, name: &str, current: &mut Site) -> Result<(), String> {
+        if name == self.active {
+            return Ok(());
+        }
+        let mut next = self.parked.remove(name).ok_or(format!("Armazém {} não encontrado.", name))?;
+        std::mem::swap(current, &mut next);
+        next.warehouse.sign_out();
+        self.parked.insert(std::mem::replace(&mut self.active, name.to_string()), next);
+        Ok(())
+    }
+
+    // Every warehouse's stock records by name, the active one being `current`
+    fn records_by_site<S: StorageBackend>(&self, current: &Warehouse<S>) -> Vec<(&str, Vec<Item>)> {
+        let records = |grouped: Vec<(Item, Vec<Location>)>| grouped.into_iter().map(|(itm, _)| itm).collect();
+        let mut sites: Vec<(&str, Vec<Item>)> = self.parked.iter().map(|(name, site)| (name.as_str(), records(site.warehouse.grouped_items()))).collect();
+        sites.push((self.active.as_str(), records(current.grouped_items())));
+        sites.sort_by_key(|(name, _)| *name);
+        sites
+    }
+
+    // Totals by product over every site plus what is on its way between them, by product id
+    pub fn stock_by_product<S: StorageBackend>(&self, current: &Warehouse<S>) -> Vec<SiteStock> {
+        let sites = self.records_by_site(current);
+        let mut stock: BTreeMap<u32, SiteStock> = BTreeMap::new();
+        let new_entry = |num_id: u32, name: &str| SiteStock {
+            num_id,
+            name: name.to_string(),
+            by_site: sites.iter().map(|(site, _)| (site.to_string(), 0)).collect(),
+            in_transit: 0,
+        };
+
+        for (index, (_, records)) in sites.iter().enumerate() {
+            for itm in records {
+                stock.entry(itm.num_id).or_insert_with(|| new_entry(itm.num_id, &itm.name)).by_site[index].1 += itm.item_quantity;
+            }
+        }
+        for transfer in self.transfers.iter().filter(|t| t.status == TransferStatus::InTransit) {
+            stock.entry(transfer.num_id).or_insert_with(|| new_entry(transfer.num_id, &transfer.name)).in_transit += transfer.quantity;
+        }
+        stock.into_values().collect()
+    }
+
+    // Takes `quantity` units of a product out of the active site (earliest expiry first) and puts
+    // them in transit to `to`. Either the whole quantity leaves or nothing does.
+    pub fn ship<S: StorageBackend>(&mut self, to: &str, num_id: u32, quantity: u32, current: &mut Warehouse<S>) -> Result<u32, String> {
+        if to == self.active {
+            return Err("O armazém de destino tem de ser diferente do de origem.".to_string());
+        }
+        if !self.parked.contains_key(to) {
+            return Err(format!("Armazém {} não encontrado.", to));
+        }
+        if quantity == 0 {
+            return Err("Quantidade a transferir tem de ser maior que zero.".to_string());
+        }
+
+        let items = current.transaction(|warehouse| {
+            let mut items = Vec::new();
+            let mut remaining = quantity;
+            for (itm, locs) in fefo_records(warehouse, num_id) {
+                if remaining == 0 {
+                    break;
+                }
+                let available = itm.item_quantity.saturating_sub(warehouse.reserved_quantity(itm.record_id));
+                let take = available.min(remaining);
+                if take == 0 {
+                    continue;
+                }
+                items.push(warehouse.remove_quantity(&locs[0], take)?);
+                remaining -= take;
+            }
+            if remaining > 0 {
+                return Err(format!("Stock insuficiente para o ID {}: faltam {} unidade(s).", num_id, remaining));
+            }
+            Ok(items)
+        })?;
+
+        let id = self.transfers.len() as u32 + 1;
+        self.transfers.push(TransferOrder {
+            id,
+            from: self.active.clone(),
+            to: to.to_string(),
+            num_id,
+            name: items[0].name.clone(),
+            quantity,
+            items,
+            status: TransferStatus::InTransit,
+            shipped_at: Utc::now().timestamp(),
+            received_at: None,
+        });
+        Ok(id)
+    }
+
+    // Stores a transfer addressed to the active site; it stays in transit if anything does not fit
+    pub fn receive<S: StorageBackend>(&mut self, transfer_id: u32, current: &mut Warehouse<S>) -> Result<Vec<Location>, String> {
+        let transfer = self.transfers.iter_mut().find(|t| t.id == transfer_id).ok_or(format!("Transferência {} não encontrada.", transfer_id))?;
+        if transfer.status != TransferStatus::InTransit {
+            return Err(format!("Transferência {} já foi recebida.", transfer_id));
+        }
+        if transfer.to != self.active {
+            return Err(format!("Transferência {} destina-se ao armazém {}.", transfer_id, transfer.to));
+        }
+
+        let items = &transfer.items;
+        let locations = current.transaction(|warehouse| {
+            let mut locations = Vec::new();
+            for itm in items {
+                // The id belonged to the source site; this one numbers its records itself
+                let mut itm = itm.clone();
+                itm.record_id = 0;
+                locations.extend(warehouse.add_item(itm)?);
+            }
+            Ok(locations)
+        })?;
+        transfer.status = TransferStatus::Received;
+        transfer.received_at = Some(Utc::now().timestamp());
+        Ok(locations)
+    }
+}
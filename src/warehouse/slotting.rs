@@ -0,0 +1,139 @@
+use super::movement::MovementKind;
+use super::storage::StorageBackend;
+use super::warehouse::{Location, Warehouse};
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+
+// Extra effort of reaching one level up, expressed in metres of walking
+const LEVEL_PENALTY: f32 = 2.0;
+// Moves that save less than this are not worth the labour
+const MIN_SAVING: f32 = 1.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AbcClass {
+    A,
+    B,
+    C,
+}
+
+impl AbcClass {
+    pub fn label(&self) -> &'static str {
+        match self {
+            AbcClass::A => "A",
+            AbcClass::B => "B",
+            AbcClass::C => "C",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Velocity {
+    #[serde(rename = "product_id")]
+    pub num_id: u32,
+    pub name: String,
+    pub picks: u32,
+    pub class: AbcClass,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SlotMove {
+    pub record_id: u64,
+    #[serde(rename = "product_id")]
+    pub num_id: u32,
+    pub name: String,
+    pub class: AbcClass,
+    pub from: Location,
+    pub to: Location,
+    pub saving: f32,
+}
+
+// Ranks products by outbound picks: the fastest movers making up 80% of picks are A, the next 15% B, the rest C
+pub fn abc_analysis<S: StorageBackend>(warehouse: &Warehouse<S>) -> Vec<Velocity> {
+    let mut picks: HashMap<u32, (String, u32)> = HashMap::new();
+    for (itm, _) in warehouse.grouped_items() {
+        picks.entry(itm.num_id).or_insert((itm.name.clone(), 0));
+    }
+    for movement in warehouse.movements.iter().filter(|m| m.kind == MovementKind::Outbound) {
+        picks.entry(movement.num_id).or_insert((movement.name.clone(), 0)).1 += 1;
+    }
+
+    let mut ranked: Vec<(u32, String, u32)> = picks.into_iter().map(|(id, (name, n))| (id, name, n)).collect();
+    ranked.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+
+    let total: u32 = ranked.iter().map(|r| r.2).sum();
+    let mut cumulative = 0;
+    ranked.into_iter().map(|(num_id, name, n)| {
+        // A product's class is decided by the share of picks accumulated before it
+        let share = if total == 0 { 1.0 } else { cumulative as f32 / total as f32 };
+        cumulative += n;
+        let class = if n == 0 {
+            AbcClass::C
+        } else if share < 0.8 {
+            AbcClass::A
+        } else if share < 0.95 {
+            AbcClass::B
+        } else {
+            AbcClass::C
+        };
+        Velocity { num_id, name, picks: n, class }
+    }).collect()
+}
+
+pub fn access_cost<S: StorageBackend>(warehouse: &Warehouse<S>, loc: &Location) -> f32 {
+    warehouse.layout.distance_from_dock(loc) + loc.level as f32 * LEVEL_PENALTY
+}
+
+// Suggests moving A and then B products into the most accessible free cells they are allowed in.
+// Oversized records are left alone since their footprint rarely fits the prime spots.
+pub fn slotting_advice<S: StorageBackend>(warehouse: &Warehouse<S>) -> Vec<SlotMove> {
+    let velocities = abc_analysis(warehouse);
+    let classes: HashMap<u32, (AbcClass, u32)> = velocities.iter().map(|v| (v.num_id, (v.class, v.picks))).collect();
+
+    let mut records: Vec<_> = warehouse.grouped_items()
+        .into_iter()
+        .filter(|(itm, locs)| locs.len() == 1 && classes.get(&itm.num_id).is_some_and(|(c, _)| *c != AbcClass::C))
+        .collect();
+    records.sort_by_key(|(itm, _)| {
+        let (class, picks) = classes[&itm.num_id];
+        (class, u32::MAX - picks, itm.record_id)
+    });
+
+    let (rows, shelves, levels, zones) = warehouse.max_dimensions;
+    let mut free_cells = Vec::new();
+    for row in 0..rows {
+        for shelf in 0..shelves {
+            for level in 0..levels {
+                for zone in 0..zones {
+                    let loc = Location { row, shelf, level, zone };
+                    if warehouse.item_at(&loc).is_none() {
+                        free_cells.push((access_cost(warehouse, &loc), loc));
+                    }
+                }
+            }
+        }
+    }
+    free_cells.sort_by(|(c1, l1), (c2, l2)| c1.total_cmp(c2).then(l1.cmp(l2)));
+
+    let mut claimed = HashSet::new();
+    let mut moves = Vec::new();
+    for (itm, locs) in records {
+        let current = access_cost(warehouse, &locs[0]);
+        let best = free_cells.iter().find(|(cost, loc)| {
+            *cost + MIN_SAVING <= current && !claimed.contains(loc) && warehouse.location_fits(&itm, loc)
+        });
+        if let Some((cost, loc)) = best {
+            claimed.insert(loc.clone());
+            moves.push(SlotMove {
+                record_id: itm.record_id,
+                num_id: itm.num_id,
+                name: itm.name.clone(),
+                class: classes[&itm.num_id].0,
+                from: locs[0].clone(),
+                to: loc.clone(),
+                saving: current - cost,
+            });
+        }
+    }
+    moves
+}
@@ -0,0 +1,494 @@
+use super::item::Item;
+use super::movement::Movement;
+use super::storage::{MemoryStorage, StorageBackend};
+use super::uom::Packaging;
+use super::warehouse::{Location, Warehouse};
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::de::DeserializeOwned;
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS products (
+        product_id       INTEGER PRIMARY KEY,
+        name             TEXT NOT NULL,
+        base_unit        TEXT NOT NULL,
+        units_per_case   INTEGER NOT NULL,
+        cases_per_pallet INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS stock_records (
+        record_id    INTEGER PRIMARY KEY,
+        product_id   INTEGER NOT NULL REFERENCES products(product_id),
+        quantity     INTEGER NOT NULL,
+        attributes   TEXT NOT NULL,
+        timestamp    INTEGER NOT NULL,
+        expiry_date  TEXT,
+        provenance   TEXT,
+        lot          TEXT,
+        serials      TEXT NOT NULL,
+        unit_cost    REAL NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS locations (
+        row       INTEGER NOT NULL,
+        shelf     INTEGER NOT NULL,
+        level     INTEGER NOT NULL,
+        zone      INTEGER NOT NULL,
+        record_id INTEGER NOT NULL REFERENCES stock_records(record_id) ON DELETE CASCADE,
+        PRIMARY KEY (row, shelf, level, zone)
+    );
+    CREATE TABLE IF NOT EXISTS movements (
+        id              INTEGER PRIMARY KEY AUTOINCREMENT,
+        timestamp       INTEGER NOT NULL,
+        kind            TEXT NOT NULL,
+        record_id       INTEGER NOT NULL,
+        product_id      INTEGER NOT NULL,
+        name            TEXT NOT NULL,
+        quantity_change INTEGER NOT NULL,
+        from_location   TEXT,
+        to_location     TEXT,
+        reason          TEXT,
+        lot             TEXT,
+        serials         TEXT NOT NULL,
+        unit_cost       REAL NOT NULL,
+        user            TEXT
+    );
+    CREATE TABLE IF NOT EXISTS settings (
+        key   TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS cell_usage (
+        row         INTEGER NOT NULL,
+        shelf       INTEGER NOT NULL,
+        level       INTEGER NOT NULL,
+        zone        INTEGER NOT NULL,
+        usage_count INTEGER NOT NULL,
+        PRIMARY KEY (row, shelf, level, zone)
+    );
+";
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+fn db_error(err: rusqlite::Error) -> String {
+    format!("Erro na base de dados: {}", err)
+}
+
+fn to_text<T: Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| e.to_string())
+}
+
+fn from_text<T: DeserializeOwned>(text: &str) -> Result<T, String> {
+    serde_json::from_str(text).map_err(|e| format!("Dados inválidos na base de dados: {}", e))
+}
+
+// Enums as their bare JSON name ("inbound" rather than "\"inbound\"") so they read well in SQL
+fn enum_text<T: Serialize>(value: &T) -> Result<String, String> {
+    match serde_json::to_value(value).map_err(|e| e.to_string())? {
+        serde_json::Value::String(s) => Ok(s),
+        other => Ok(other.to_string()),
+    }
+}
+
+fn enum_from_text<T: DeserializeOwned>(text: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(text.to_string()))
+        .or_else(|_| from_text(text))
+}
+
+// What the database holds, as of the last write
+struct Synced {
+    revision: u64,
+    movements: usize,
+    last_movement: Option<Movement>,
+}
+
+fn connect(path: &str) -> Result<Connection, String> {
+    let conn = Connection::open(path).map_err(db_error)?;
+    conn.execute_batch("PRAGMA foreign_keys = ON;").map_err(db_error)?;
+    conn.execute_batch(SCHEMA).map_err(db_error)?;
+    Ok(conn)
+}
+
+// Every occupied cell with the record stored in it
+fn read_stock(conn: &Connection) -> Result<Vec<(Location, Item)>, String> {
+    let mut records: HashMap<u64, Item> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT r.record_id, r.product_id, p.name, r.quantity, r.attributes, r.timestamp, r.expiry_date,
+                r.provenance, r.lot, r.serials, r.unit_cost
+         FROM stock_records r JOIN products p ON p.product_id = r.product_id",
+    ).map_err(db_error)?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?, row.get::<_, u32>(1)?, row.get::<_, String>(2)?, row.get::<_, u32>(3)?,
+            row.get::<_, String>(4)?, row.get::<_, i64>(5)?, row.get::<_, Option<String>>(6)?,
+            row.get::<_, Option<String>>(7)?, row.get::<_, Option<String>>(8)?, row.get::<_, String>(9)?,
+            row.get::<_, f64>(10)?,
+        ))
+    }).map_err(db_error)?;
+    for row in rows {
+        let (record_id, num_id, name, quantity, attributes, timestamp, expiry, provenance, lot, serials, unit_cost) = row.map_err(db_error)?;
+        let expiry = expiry
+            .map(|d| NaiveDate::parse_from_str(&d, DATE_FORMAT).map_err(|_| format!("Data inválida na base de dados: {}", d)))
+            .transpose()?;
+        let mut item = Item::new(num_id, name, quantity, from_text(&attributes)?, expiry);
+        item.record_id = record_id as u64;
+        item.timestamp = timestamp;
+        item.provenance = provenance.as_deref().map(from_text).transpose()?;
+        item.lot = lot;
+        item.serials = from_text(&serials)?;
+        item.unit_cost = unit_cost;
+        records.insert(item.record_id, item);
+    }
+
+    let mut stock = Vec::new();
+    let mut stmt = conn.prepare("SELECT row, shelf, level, zone, record_id FROM locations").map_err(db_error)?;
+    let rows = stmt.query_map([], |row| {
+        Ok((Location { row: row.get(0)?, shelf: row.get(1)?, level: row.get(2)?, zone: row.get(3)? }, row.get::<_, i64>(4)?))
+    }).map_err(db_error)?;
+    for row in rows {
+        let (loc, record_id) = row.map_err(db_error)?;
+        let item = records.get(&(record_id as u64)).ok_or("Localização aponta para um registo inexistente.".to_string())?;
+        stock.push((loc, item.clone()));
+    }
+    Ok(stock)
+}
+
+// The movement history, oldest first
+fn read_movements(conn: &Connection) -> Result<Vec<Movement>, String> {
+    let mut movements = Vec::new();
+    let mut stmt = conn.prepare(
+        "SELECT timestamp, kind, record_id, product_id, name, quantity_change, from_location, to_location,
+                reason, lot, serials, unit_cost, user
+         FROM movements ORDER BY id",
+    ).map_err(db_error)?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, u32>(3)?,
+            row.get::<_, String>(4)?, row.get::<_, i64>(5)?, row.get::<_, Option<String>>(6)?,
+            row.get::<_, Option<String>>(7)?, row.get::<_, Option<String>>(8)?, row.get::<_, Option<String>>(9)?,
+            row.get::<_, String>(10)?, row.get::<_, f64>(11)?, row.get::<_, Option<String>>(12)?,
+        ))
+    }).map_err(db_error)?;
+    for row in rows {
+        let (timestamp, kind, record_id, num_id, name, quantity_change, from, to, reason, lot, serials, unit_cost, user) = row.map_err(db_error)?;
+        movements.push(Movement {
+            timestamp,
+            kind: enum_from_text(&kind)?,
+            record_id: record_id as u64,
+            num_id,
+            name,
+            quantity_change,
+            from: from.as_deref().map(from_text).transpose()?,
+            to: to.as_deref().map(from_text).transpose()?,
+            reason: reason.as_deref().map(enum_from_text).transpose()?,
+            lot,
+            serials: from_text(&serials)?,
+            unit_cost,
+            user,
+        });
+    }
+    Ok(movements)
+}
+
+// The stored configuration: the warehouse without stock or history, or None for a new database
+fn read_settings(conn: &Connection) -> Result<Option<Warehouse>, String> {
+    let config: Option<String> = conn
+        .query_row("SELECT value FROM settings WHERE key = 'warehouse'", [], |row| row.get(0))
+        .optional()
+        .map_err(db_error)?;
+    config.as_deref().map(from_text).transpose()
+}
+
+fn insert_movement(tx: &Transaction, movement: &Movement) -> Result<(), String> {
+    tx.execute(
+        "INSERT INTO movements (timestamp, kind, record_id, product_id, name, quantity_change, from_location, to_location,
+                                reason, lot, serials, unit_cost, user)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        params![
+            movement.timestamp,
+            enum_text(&movement.kind)?,
+            movement.record_id as i64,
+            movement.num_id,
+            movement.name,
+            movement.quantity_change,
+            movement.from.as_ref().map(to_text).transpose()?,
+            movement.to.as_ref().map(to_text).transpose()?,
+            movement.reason.as_ref().map(enum_text).transpose()?,
+            movement.lot,
+            to_text(&movement.serials)?,
+            movement.unit_cost,
+            movement.user,
+        ],
+    ).map_err(db_error)?;
+    Ok(())
+}
+
+fn insert_new_movements<S>(tx: &Transaction, warehouse: &Warehouse<S>, already_stored: usize) -> Result<(), String> {
+    for movement in warehouse.movements.iter().skip(already_stored) {
+        insert_movement(tx, movement)?;
+    }
+    Ok(())
+}
+
+// Everything but the stock and history, which live in their own tables
+fn write_settings<S: Serialize>(tx: &Transaction, warehouse: &Warehouse<S>) -> Result<(), String> {
+    let mut config = serde_json::to_value(warehouse).map_err(|e| e.to_string())?;
+    if let Some(object) = config.as_object_mut() {
+        object.insert("items".to_string(), serde_json::Value::Array(Vec::new()));
+        object.insert("usage_count".to_string(), serde_json::Value::Array(Vec::new()));
+        object.insert("movements".to_string(), serde_json::Value::Array(Vec::new()));
+    }
+    tx.execute(
+        "INSERT INTO settings (key, value) VALUES ('warehouse', ?1)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![config.to_string()],
+    ).map_err(db_error)?;
+    Ok(())
+}
+
+impl Synced {
+    fn of<S: StorageBackend>(warehouse: &Warehouse<S>) -> Synced {
+        Synced {
+            revision: warehouse.revision(),
+            movements: warehouse.movements.len(),
+            last_movement: warehouse.movements.last().cloned(),
+        }
+    }
+
+    // Whether `warehouse` holds the written history followed by newer movements
+    fn continues_in<S: StorageBackend>(&self, warehouse: &Warehouse<S>) -> bool {
+        warehouse.movements.len() >= self.movements
+            && self.movements.checked_sub(1).and_then(|last| warehouse.movements.get(last)) == self.last_movement.as_ref()
+    }
+}
+
+// Keeps a warehouse in a local SQLite file. Stock records, their cells, products and the movement
+// history get their own tables for reporting, and `cell_usage` holds the allocator's counts;
+// everything else (climate, segregation, reservations, layout, packaging, counters) is kept as one
+// JSON document in `settings`. Reads are served from memory; a commit writes the cells the
+// transaction touched, and the records in them, inside one SQLite transaction, and `save_state`
+// writes the rest.
+// The connection sits behind a mutex so that the storage can be shared between threads.
+pub struct SqliteStorage {
+    db: Mutex<Database>,
+    cells: MemoryStorage,
+}
+
+struct Database {
+    conn: Connection,
+    saved: Option<Synced>, // what `save_state` last wrote
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> Result<SqliteStorage, String> {
+        let conn = connect(path)?;
+        let mut cells = MemoryStorage::default();
+        let mut usage: HashMap<Location, u32> = HashMap::new();
+        {
+            let mut stmt = conn.prepare("SELECT row, shelf, level, zone, usage_count FROM cell_usage").map_err(db_error)?;
+            let rows = stmt.query_map([], |row| {
+                Ok((Location { row: row.get(0)?, shelf: row.get(1)?, level: row.get(2)?, zone: row.get(3)? }, row.get::<_, u32>(4)?))
+            }).map_err(db_error)?;
+            for row in rows {
+                let (loc, count) = row.map_err(db_error)?;
+                usage.insert(loc, count);
+            }
+        }
+        for (loc, item) in read_stock(&conn)? {
+            let count = usage.remove(&loc).unwrap_or(0);
+            cells.load_cell(loc, Some(item), count);
+        }
+        for (loc, count) in usage {
+            cells.load_cell(loc, None, count);
+        }
+        Ok(SqliteStorage { db: Mutex::new(Database { conn, saved: None }), cells })
+    }
+
+    // The warehouse kept in the database at `path`. A database that holds none yet takes a copy
+    // of `initial`: its stock fills the cells if they are empty and the rest is written straight away.
+    pub fn open_warehouse(path: &str, initial: &Warehouse) -> Result<Warehouse<SqliteStorage>, String> {
+        let mut storage = SqliteStorage::open(path)?;
+        let db = storage.db.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(mut stored) = read_settings(&db.conn)? {
+            stored.movements = read_movements(&db.conn)?;
+            let warehouse = stored.with_cells(storage);
+            warehouse.storage.database().saved = Some(Synced::of(&warehouse));
+            return Ok(warehouse);
+        }
+
+        if storage.is_empty() {
+            storage.begin();
+            for (loc, itm) in initial.items() {
+                storage.put(loc.clone(), itm.clone());
+            }
+            storage.commit()?;
+        }
+        let warehouse = initial.clone().with_cells(storage);
+        warehouse.storage.save_state(&warehouse)?;
+        Ok(warehouse)
+    }
+
+    // Closes the database, keeping its cells in memory
+    pub fn into_cells(self) -> MemoryStorage {
+        self.cells
+    }
+
+    fn database(&self) -> MutexGuard<'_, Database> {
+        self.db.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Writes the configuration (reservations included) of `warehouse`, the one over this storage,
+    // and the movements recorded since the last call, in one SQLite transaction, unless nothing
+    // changed since then. The history is rewritten when it no longer continues the one in the
+    // database, as after an import.
+    pub fn save_state<S: StorageBackend + Serialize>(&self, warehouse: &Warehouse<S>) -> Result<(), String> {
+        let mut db = self.database();
+        let stored = match &db.saved {
+            Some(saved) if saved.revision == warehouse.revision() => return Ok(()),
+            Some(saved) if saved.continues_in(warehouse) => Some(saved.movements),
+            _ => None,
+        };
+        let tx = db.conn.transaction().map_err(db_error)?;
+        if stored.is_none() {
+            tx.execute("DELETE FROM movements", []).map_err(db_error)?;
+        }
+        insert_new_movements(&tx, warehouse, stored.unwrap_or(0))?;
+        write_settings(&tx, warehouse)?;
+        let products: Vec<u32> = {
+            let mut stmt = tx.prepare("SELECT product_id FROM products").map_err(db_error)?;
+            let rows = stmt.query_map([], |row| row.get(0)).map_err(db_error)?;
+            rows.collect::<Result<_, _>>().map_err(db_error)?
+        };
+        for num_id in products {
+            let packaging = warehouse.packaging.packaging(num_id);
+            tx.execute(
+                "UPDATE products SET base_unit = ?2, units_per_case = ?3, cases_per_pallet = ?4 WHERE product_id = ?1",
+                params![num_id, packaging.base_unit, packaging.units_per_case, packaging.cases_per_pallet],
+            ).map_err(db_error)?;
+        }
+        tx.commit().map_err(db_error)?;
+        db.saved = Some(Synced::of(warehouse));
+        Ok(())
+    }
+
+    fn write(&mut self) -> Result<(), String> {
+        let db = self.db.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let tx = db.conn.transaction().map_err(db_error)?;
+        for loc in self.cells.touched() {
+            tx.execute(
+                "DELETE FROM locations WHERE row = ?1 AND shelf = ?2 AND level = ?3 AND zone = ?4",
+                params![loc.row, loc.shelf, loc.level, loc.zone],
+            ).map_err(db_error)?;
+            if let Some(item) = self.cells.get(&loc) {
+                Self::upsert_record(&tx, item)?;
+                tx.execute(
+                    "INSERT INTO locations (row, shelf, level, zone, record_id) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![loc.row, loc.shelf, loc.level, loc.zone, item.record_id as i64],
+                ).map_err(db_error)?;
+            }
+            tx.execute(
+                "INSERT INTO cell_usage (row, shelf, level, zone, usage_count) VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(row, shelf, level, zone) DO UPDATE SET usage_count = excluded.usage_count",
+                params![loc.row, loc.shelf, loc.level, loc.zone, self.cells.usage(&loc)],
+            ).map_err(db_error)?;
+        }
+        // Records whose last cell was emptied
+        tx.execute("DELETE FROM stock_records WHERE record_id NOT IN (SELECT record_id FROM locations)", []).map_err(db_error)?;
+        tx.commit().map_err(db_error)
+    }
+
+    // Products get the default packaging here; `save_state` writes the catalogue's
+    fn upsert_record(tx: &Transaction, item: &Item) -> Result<(), String> {
+        let packaging = Packaging::default();
+        tx.execute(
+            "INSERT INTO products (product_id, name, base_unit, units_per_case, cases_per_pallet) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(product_id) DO UPDATE SET name = excluded.name",
+            params![item.num_id, item.name, packaging.base_unit, packaging.units_per_case, packaging.cases_per_pallet],
+        ).map_err(db_error)?;
+        tx.execute(
+            "INSERT INTO stock_records (record_id, product_id, quantity, attributes, timestamp, expiry_date, provenance, lot, serials, unit_cost)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT(record_id) DO UPDATE SET product_id = excluded.product_id, quantity = excluded.quantity,
+                 attributes = excluded.attributes, timestamp = excluded.timestamp, expiry_date = excluded.expiry_date,
+                 provenance = excluded.provenance, lot = excluded.lot, serials = excluded.serials, unit_cost = excluded.unit_cost",
+            params![
+                item.record_id as i64,
+                item.num_id,
+                item.item_quantity,
+                to_text(&item.attributes)?,
+                item.timestamp,
+                item.data_validade.map(|d| d.format(DATE_FORMAT).to_string()),
+                item.provenance.as_ref().map(to_text).transpose()?,
+                item.lot,
+                to_text(&item.serials)?,
+                item.unit_cost,
+            ],
+        ).map_err(db_error)?;
+        Ok(())
+    }
+}
+
+// Snapshots of a warehouse on SQLite storage show its cells like the in-memory ones
+impl Serialize for SqliteStorage {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        self.cells.serialize(serializer)
+    }
+}
+
+impl StorageBackend for SqliteStorage {
+    fn get(&self, loc: &Location) -> Option<&Item> {
+        self.cells.get(loc)
+    }
+
+    fn get_mut(&mut self, loc: &Location) -> Option<&mut Item> {
+        self.cells.get_mut(loc)
+    }
+
+    fn put(&mut self, loc: Location, item: Item) -> Option<Item> {
+        self.cells.put(loc, item)
+    }
+
+    fn remove(&mut self, loc: &Location) -> Option<Item> {
+        self.cells.remove(loc)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Location, &Item)> + '_> {
+        self.cells.iter()
+    }
+
+    fn usage(&self, loc: &Location) -> u32 {
+        self.cells.usage(loc)
+    }
+
+    fn record_use(&mut self, loc: &Location) {
+        self.cells.record_use(loc)
+    }
+
+    fn begin(&mut self) {
+        self.cells.begin()
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        if self.cells.touched().is_empty() {
+            return self.cells.commit();
+        }
+        self.write()?;
+        self.cells.commit()
+    }
+
+    fn rollback(&mut self) {
+        self.cells.rollback()
+    }
+
+    fn savepoint(&self) -> usize {
+        self.cells.savepoint()
+    }
+
+    fn rollback_to(&mut self, savepoint: usize) {
+        self.cells.rollback_to(savepoint)
+    }
+
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+}
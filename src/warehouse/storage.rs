@@ -0,0 +1,248 @@
+use super::item::Item;
+use super::warehouse::Location;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fs;
+
+// Where the warehouse keeps its cells: what is stored in each location and how often the
+// allocator has used it. Changes made between `begin` and `commit` form one unit; a backend
+// that fails to persist them returns an error from `commit` and the caller rolls back.
+pub trait StorageBackend {
+    fn get(&self, loc: &Location) -> Option<&Item>;
+    fn get_mut(&mut self, loc: &Location) -> Option<&mut Item>;
+    fn put(&mut self, loc: Location, item: Item) -> Option<Item>;
+    fn remove(&mut self, loc: &Location) -> Option<Item>;
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Location, &Item)> + '_>;
+    fn usage(&self, loc: &Location) -> u32;
+    fn record_use(&mut self, loc: &Location);
+
+    fn begin(&mut self);
+    fn commit(&mut self) -> Result<(), String>;
+    fn rollback(&mut self);
+    // A point in the open transaction; `rollback_to` undoes only what changed after it
+    fn savepoint(&self) -> usize;
+    fn rollback_to(&mut self, savepoint: usize);
+
+    fn contains(&self, loc: &Location) -> bool {
+        self.get(loc).is_some()
+    }
+
+    fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Cells kept in process memory. An open transaction keeps an undo journal with the previous
+// contents of every cell it touches, so rolling back costs only what was changed.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct MemoryStorage {
+    #[serde(with = "super::serde_map")]
+    items: HashMap<Location, Item>,
+    #[serde(with = "super::serde_map")]
+    usage_count: HashMap<Location, u32>,
+    #[serde(skip)]
+    journal: Option<Vec<(Location, Option<Item>, u32)>>,
+}
+
+impl MemoryStorage {
+    fn remember(&mut self, loc: &Location) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push((loc.clone(), self.items.get(loc).cloned(), self.usage_count.get(loc).cloned().unwrap_or(0)));
+        }
+    }
+
+    // Fills a cell as read from a persistent backend, bypassing the journal
+    pub fn load_cell(&mut self, loc: Location, item: Option<Item>, usage: u32) {
+        if usage > 0 {
+            self.usage_count.insert(loc.clone(), usage);
+        }
+        if let Some(item) = item {
+            self.items.insert(loc, item);
+        }
+    }
+
+    // Cells changed by the open transaction, each listed once
+    pub fn touched(&self) -> Vec<Location> {
+        let mut touched: Vec<Location> = self.journal.iter().flatten().map(|(loc, _, _)| loc.clone()).collect();
+        touched.sort();
+        touched.dedup();
+        touched
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn get(&self, loc: &Location) -> Option<&Item> {
+        self.items.get(loc)
+    }
+
+    fn get_mut(&mut self, loc: &Location) -> Option<&mut Item> {
+        if self.items.contains_key(loc) {
+            self.remember(loc);
+        }
+        self.items.get_mut(loc)
+    }
+
+    fn put(&mut self, loc: Location, item: Item) -> Option<Item> {
+        self.remember(&loc);
+        self.items.insert(loc, item)
+    }
+
+    fn remove(&mut self, loc: &Location) -> Option<Item> {
+        if self.items.contains_key(loc) {
+            self.remember(loc);
+        }
+        self.items.remove(loc)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Location, &Item)> + '_> {
+        Box::new(self.items.iter())
+    }
+
+    fn usage(&self, loc: &Location) -> u32 {
+        self.usage_count.get(loc).cloned().unwrap_or(0)
+    }
+
+    fn record_use(&mut self, loc: &Location) {
+        self.remember(loc);
+        *self.usage_count.entry(loc.clone()).or_insert(0) += 1;
+    }
+
+    // Transactions do not nest: a begin inside an open transaction joins it
+    fn begin(&mut self) {
+        if self.journal.is_none() {
+            self.journal = Some(Vec::new());
+        }
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        self.journal = None;
+        Ok(())
+    }
+
+    fn rollback(&mut self) {
+        self.rollback_to(0);
+        self.journal = None;
+    }
+
+    fn savepoint(&self) -> usize {
+        self.journal.as_ref().map_or(0, Vec::len)
+    }
+
+    fn rollback_to(&mut self, savepoint: usize) {
+        let undone = match self.journal.as_mut() {
+            Some(journal) if journal.len() > savepoint => journal.split_off(savepoint),
+            _ => return,
+        };
+        for (loc, item, usage) in undone.into_iter().rev() {
+            match item {
+                Some(item) => self.items.insert(loc.clone(), item),
+                None => self.items.remove(&loc),
+            };
+            if usage == 0 {
+                self.usage_count.remove(&loc);
+            } else {
+                self.usage_count.insert(loc, usage);
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+}
+
+// Cells kept in memory and written to a JSON file (same layout as the `items` and `usage_count`
+// fields of the warehouse snapshot) on every commit. The file is replaced atomically.
+// Changes made outside a transaction stay in memory until the next commit.
+pub struct FileStorage {
+    path: String,
+    cells: MemoryStorage,
+}
+
+impl FileStorage {
+    // Opens the file, starting empty if it does not exist yet
+    pub fn open(path: &str) -> Result<FileStorage, String> {
+        let cells = match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Ficheiro {} inválido: {}", path, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => MemoryStorage::default(),
+            Err(e) => return Err(format!("Não foi possível ler {}: {}", path, e)),
+        };
+        Ok(FileStorage { path: path.to_string(), cells })
+    }
+
+    fn write(&self) -> Result<(), String> {
+        let json = serde_json::to_string(&self.cells).map_err(|e| e.to_string())?;
+        let temp = format!("{}.tmp", self.path);
+        fs::write(&temp, json).map_err(|e| format!("Não foi possível escrever {}: {}", temp, e))?;
+        fs::rename(&temp, &self.path).map_err(|e| format!("Não foi possível substituir {}: {}", self.path, e))
+    }
+}
+
+// Snapshots of a warehouse on file storage show its cells like the in-memory ones
+impl Serialize for FileStorage {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        self.cells.serialize(serializer)
+    }
+}
+
+impl StorageBackend for FileStorage {
+    fn get(&self, loc: &Location) -> Option<&Item> {
+        self.cells.get(loc)
+    }
+
+    fn get_mut(&mut self, loc: &Location) -> Option<&mut Item> {
+        self.cells.get_mut(loc)
+    }
+
+    fn put(&mut self, loc: Location, item: Item) -> Option<Item> {
+        self.cells.put(loc, item)
+    }
+
+    fn remove(&mut self, loc: &Location) -> Option<Item> {
+        self.cells.remove(loc)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&Location, &Item)> + '_> {
+        self.cells.iter()
+    }
+
+    fn usage(&self, loc: &Location) -> u32 {
+        self.cells.usage(loc)
+    }
+
+    fn record_use(&mut self, loc: &Location) {
+        self.cells.record_use(loc)
+    }
+
+    fn begin(&mut self) {
+        self.cells.begin()
+    }
+
+    fn commit(&mut self) -> Result<(), String> {
+        if self.cells.touched().is_empty() {
+            return self.cells.commit();
+        }
+        self.write()?;
+        self.cells.commit()
+    }
+
+    fn rollback(&mut self) {
+        self.cells.rollback()
+    }
+
+    fn savepoint(&self) -> usize {
+        self.cells.savepoint()
+    }
+
+    fn rollback_to(&mut self, savepoint: usize) {
+        self.cells.rollback_to(savepoint)
+    }
+
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackagingLevel {
+    Each,
+    Case,
+    Pallet,
+}
+
+impl PackagingLevel {
+    pub const ALL: [PackagingLevel; 3] = [PackagingLevel::Each, PackagingLevel::Case, PackagingLevel::Pallet];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PackagingLevel::Each => "Unidade",
+            PackagingLevel::Case => "Caixa",
+            PackagingLevel::Pallet => "Palete",
+        }
+    }
+}
+
+// How a product is packed; stored quantities are always in the base unit
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Packaging {
+    pub base_unit: String, // e.g. "un", "kg", "L"
+    pub units_per_case: u32,
+    pub cases_per_pallet: u32,
+}
+
+impl Default for Packaging {
+    fn default() -> Self {
+        Packaging {
+            base_unit: "un".to_string(),
+            units_per_case: 1,
+            cases_per_pallet: 1,
+        }
+    }
+}
+
+impl Packaging {
+    // Base units in one package of the given level
+    pub fn factor(&self, level: PackagingLevel) -> u32 {
+        match level {
+            PackagingLevel::Each => 1,
+            PackagingLevel::Case => self.units_per_case,
+            PackagingLevel::Pallet => self.units_per_case * self.cases_per_pallet,
+        }
+    }
+
+    pub fn to_base(&self, quantity: u32, level: PackagingLevel) -> Result<u32, String> {
+        quantity.checked_mul(self.factor(level))
+            .ok_or("Quantidade demasiado grande.".to_string())
+    }
+
+    // "130 un (1 Palete, 3 Caixas, 10 un)" - the breakdown is only shown when packs are defined
+    pub fn describe(&self, base_quantity: u32) -> String {
+        let total = format!("{} {}", base_quantity, self.base_unit);
+        if self.units_per_case <= 1 && self.cases_per_pallet <= 1 {
+            return total;
+        }
+
+        let pallet = self.factor(PackagingLevel::Pallet);
+        let mut rest = base_quantity;
+        let mut parts = Vec::new();
+        if self.cases_per_pallet > 1 && rest >= pallet {
+            parts.push(format!("{} Palete(s)", rest / pallet));
+            rest %= pallet;
+        }
+        if self.units_per_case > 1 && rest >= self.units_per_case {
+            parts.push(format!("{} Caixa(s)", rest / self.units_per_case));
+            rest %= self.units_per_case;
+        }
+        if rest > 0 || parts.is_empty() {
+            parts.push(format!("{} {}", rest, self.base_unit));
+        }
+        format!("{} ({})", total, parts.join(", "))
+    }
+}
+
+// Packaging definitions by product id; products without one are counted in plain units
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PackagingCatalog {
+    products: HashMap<u32, Packaging>,
+}
+
+impl PackagingCatalog {
+    pub fn define(&mut self, num_id: u32, base_unit: String, units_per_case: u32, cases_per_pallet: u32) -> Result<(), String> {
+        if base_unit.trim().is_empty() {
+            return Err("A unidade base não pode estar vazia.".to_string());
+        }
+        if units_per_case == 0 || cases_per_pallet == 0 {
+            return Err("Os fatores de conversão têm de ser maiores que zero.".to_string());
+        }
+        if units_per_case.checked_mul(cases_per_pallet).is_none() {
+            return Err("Fatores de conversão demasiado grandes.".to_string());
+        }
+        self.products.insert(num_id, Packaging { base_unit: base_unit.trim().to_string(), units_per_case, cases_per_pallet });
+        Ok(())
+    }
+
+    pub fn packaging(&self, num_id: u32) -> Packaging {
+        self.products.get(&num_id).cloned().unwrap_or_default()
+    }
+
+    pub fn to_base(&self, num_id: u32, quantity: u32, level: PackagingLevel) -> Result<u32, String> {
+        if level != PackagingLevel::Each && !self.products.contains_key(&num_id) {
+            return Err(format!("O produto {} não tem embalagens definidas; use a unidade base.", num_id));
+        }
+        self.packaging(num_id).to_base(quantity, level)
+    }
+
+    pub fn describe(&self, num_id: u32, base_quantity: u32) -> String {
+        self.packaging(num_id).describe(base_quantity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boxes() -> Packaging {
+        Packaging { base_unit: "un".to_string(), units_per_case: 12, cases_per_pallet: 10 }
+    }
+
+    #[test]
+    fn packages_convert_to_base_units() {
+        let packaging = boxes();
+        assert_eq!(packaging.to_base(3, PackagingLevel::Each), Ok(3));
+        assert_eq!(packaging.to_base(3, PackagingLevel::Case), Ok(36));
+        assert_eq!(packaging.to_base(2, PackagingLevel::Pallet), Ok(240));
+        assert!(packaging.to_base(u32::MAX, PackagingLevel::Case).is_err());
+    }
+
+    #[test]
+    fn base_units_are_described_in_packages() {
+        let packaging = boxes();
+        assert_eq!(packaging.describe(130), "130 un (1 Palete(s), 10 un)");
+        assert_eq!(packaging.describe(157), "157 un (1 Palete(s), 3 Caixa(s), 1 un)");
+        assert_eq!(packaging.describe(24), "24 un (2 Caixa(s))");
+        assert_eq!(packaging.describe(0), "0 un (0 un)");
+        assert_eq!(Packaging::default().describe(7), "7 un");
+    }
+
+    #[test]
+    fn only_products_with_packaging_take_packed_quantities() {
+        let mut catalog = PackagingCatalog::default();
+        assert!(catalog.define(1, " ".to_string(), 6, 4).is_err());
+        assert!(catalog.define(1, "kg".to_string(), 0, 4).is_err());
+        assert!(catalog.define(1, "kg".to_string(), u32::MAX, 2).is_err());
+        catalog.define(1, " kg ".to_string(), 6, 4).unwrap();
+
+        assert_eq!(catalog.to_base(1, 2, PackagingLevel::Pallet), Ok(48));
+        assert_eq!(catalog.describe(1, 30), "30 kg (1 Palete(s), 1 Caixa(s))");
+        assert_eq!(catalog.to_base(2, 5, PackagingLevel::Each), Ok(5));
+        assert!(catalog.to_base(2, 5, PackagingLevel::Case).is_err());
+    }
+}
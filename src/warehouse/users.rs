@@ -0,0 +1,223 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+const MIN_PASSWORD_LENGTH: usize = 4;
+const HASH_PREFIX: &str = "argon2id";
+const SALT_BYTES: usize = 16;
+
+// Ordered from least to most privileged; each role can do everything the previous one can
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Supervisor,
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Viewer, Role::Operator, Role::Supervisor, Role::Admin];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Role::Viewer => "Consulta",
+            Role::Operator => "Operador",
+            Role::Supervisor => "Supervisor",
+            Role::Admin => "Administrador",
+        }
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        *self >= permission.minimum_role()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Permission {
+    Store,       // add items and receive purchase orders
+    Pick,        // reserve, release and pick stock for orders
+    Move,        // relocate stored records
+    Remove,      // take stock out of the warehouse
+    Adjust,      // change recorded quantities
+    Configure,   // climate, segregation, packaging and replenishment settings
+    ManageUsers,
+}
+
+impl Permission {
+    pub fn minimum_role(&self) -> Role {
+        match self {
+            Permission::Store | Permission::Pick | Permission::Move => Role::Operator,
+            Permission::Remove | Permission::Adjust => Role::Supervisor,
+            Permission::Configure | Permission::ManageUsers => Role::Admin,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Permission::Store => "armazenar itens",
+            Permission::Pick => "reservar e expedir stock",
+            Permission::Move => "mover itens",
+            Permission::Remove => "remover itens",
+            Permission::Adjust => "ajustar quantidades",
+            Permission::Configure => "alterar a configuração do armazém",
+            Permission::ManageUsers => "gerir utilizadores",
+        }
+    }
+}
+
+// Who is operating the warehouse; stamped on every movement it records
+#[derive(Clone)]
+pub struct Session {
+    pub username: String,
+    pub role: Role,
+}
+
+impl Session {
+    pub fn authorize(&self, permission: Permission) -> Result<(), String> {
+        if self.role.allows(permission) {
+            Ok(())
+        } else {
+            Err(format!("Permissão negada: {} ({}) não pode {}.", self.username, self.role.label(), permission.label()))
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct User {
+    pub username: String,
+    pub role: Role,
+    salt: String,
+    password_hash: String,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Stored as "argon2id$<memory KiB>,<passes>,<lanes>$<hex digest>" so that hashes made with other
+// costs still verify if the defaults change
+fn argon2_hash(salt: &str, password: &str, params: Params) -> Result<String, String> {
+    let costs = format!("{},{},{}", params.m_cost(), params.t_cost(), params.p_cost());
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt.as_bytes(), &mut key)
+        .map_err(|e| format!("Não foi possível calcular o hash da palavra-passe: {}", e))?;
+    Ok(format!("{}${}${}", HASH_PREFIX, costs, to_hex(&key)))
+}
+
+fn parse_costs(costs: &str) -> Option<Params> {
+    let costs: Vec<u32> = costs.split(',').map(|c| c.parse().ok()).collect::<Option<_>>()?;
+    match costs[..] {
+        [memory, passes, lanes] => Params::new(memory, passes, lanes, None).ok(),
+        _ => None,
+    }
+}
+
+// Compares every byte, so the time taken does not tell how much of a hash matched
+fn same_hash(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+impl User {
+    fn new(username: &str, password: &str, role: Role) -> Result<User, String> {
+        let mut salt = [0u8; SALT_BYTES];
+        getrandom::getrandom(&mut salt).map_err(|e| format!("Não foi possível gerar o sal da palavra-passe: {}", e))?;
+        let salt = to_hex(&salt);
+        let password_hash = argon2_hash(&salt, password, Params::default())?;
+        Ok(User { username: username.to_string(), role, salt, password_hash })
+    }
+
+    fn verify(&self, password: &str) -> bool {
+        let params = self.password_hash.split('$').nth(1).and_then(parse_costs);
+        params.and_then(|params| argon2_hash(&self.salt, password, params).ok())
+            .is_some_and(|hash| same_hash(&hash, &self.password_hash))
+    }
+
+    pub fn session(&self) -> Session {
+        Session { username: self.username.clone(), role: self.role }
+    }
+}
+
+// Accounts kept in a JSON file; passwords are stored as Argon2id hashes with a random salt
+#[derive(Default, Serialize, Deserialize)]
+pub struct UserDirectory {
+    users: Vec<User>,
+}
+
+impl UserDirectory {
+    // An empty directory when the file does not exist yet
+    pub fn load(path: &str) -> Result<UserDirectory, String> {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Ficheiro de utilizadores inválido: {}", e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(UserDirectory::default()),
+            Err(e) => Err(format!("Não foi possível ler {}: {}", path, e)),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("Não foi possível gravar {}: {}", path, e))
+    }
+
+    pub fn users(&self) -> &[User] {
+        &self.users
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    pub fn add_user(&mut self, username: &str, password: &str, role: Role) -> Result<(), String> {
+        let username = username.trim();
+        if username.is_empty() {
+            return Err("O nome de utilizador não pode estar vazio.".to_string());
+        }
+        if self.users.iter().any(|u| u.username == username) {
+            return Err(format!("O utilizador {} já existe.", username));
+        }
+        if password.chars().count() < MIN_PASSWORD_LENGTH {
+            return Err(format!("A palavra-passe tem de ter pelo menos {} caracteres.", MIN_PASSWORD_LENGTH));
+        }
+        self.users.push(User::new(username, password, role)?);
+        Ok(())
+    }
+
+    pub fn authenticate(&self, username: &str, password: &str) -> Result<User, String> {
+        self.users.iter()
+            .find(|u| u.username == username.trim() && u.verify(password))
+            .cloned()
+            .ok_or("Utilizador ou palavra-passe inválidos.".to_string())
+    }
+
+    // There must always be an administrator left to manage the accounts
+    fn keeps_admin(&self, username: &str) -> Result<(), String> {
+        if !self.users.iter().any(|u| u.username != username && u.role == Role::Admin) {
+            return Err("Tem de existir pelo menos um administrador.".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn set_role(&mut self, username: &str, role: Role) -> Result<(), String> {
+        let current = self.users.iter().find(|u| u.username == username)
+            .map(|u| u.role)
+            .ok_or(format!("Utilizador {} não encontrado.", username))?;
+        if current == Role::Admin && role != Role::Admin {
+            self.keeps_admin(username)?;
+        }
+        if let Some(user) = self.users.iter_mut().find(|u| u.username == username) {
+            user.role = role;
+        }
+        Ok(())
+    }
+
+    pub fn remove_user(&mut self, username: &str) -> Result<(), String> {
+        let index = self.users.iter().position(|u| u.username == username)
+            .ok_or(format!("Utilizador {} não encontrado.", username))?;
+        if self.users[index].role == Role::Admin {
+            self.keeps_admin(username)?;
+        }
+        self.users.remove(index);
+        Ok(())
+    }
+}
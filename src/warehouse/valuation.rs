@@ -0,0 +1,142 @@
+use super::movement::MovementKind;
+use super::storage::StorageBackend;
+use super::warehouse::Warehouse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValuationMethod {
+    Fifo,
+    WeightedAverage,
+}
+
+impl ValuationMethod {
+    pub const ALL: [ValuationMethod; 2] = [ValuationMethod::Fifo, ValuationMethod::WeightedAverage];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ValuationMethod::Fifo => "FIFO",
+            ValuationMethod::WeightedAverage => "Custo Médio Ponderado",
+        }
+    }
+}
+
+// Value of the stock of one product held in one row under one quality class
+#[derive(Serialize, Deserialize)]
+pub struct ValuationLine {
+    #[serde(rename = "product_id")]
+    pub num_id: u32,
+    pub name: String,
+    pub row: u32,
+    pub quality: String,
+    pub quantity: u32,
+    pub unit_cost: f64,
+    pub value: f64,
+}
+
+// Receipts of a product as (timestamp, quantity, unit cost), oldest first. Stored records that no
+// inbound movement explains (stock loaded without its history) count as received at their own
+// cost and creation time.
+fn cost_layers<S: StorageBackend>(warehouse: &Warehouse<S>, num_id: u32) -> Vec<(i64, u32, f64)> {
+    let inbound: Vec<_> = warehouse.movements.iter()
+        .filter(|m| m.kind == MovementKind::Inbound && m.num_id == num_id)
+        .collect();
+    let received: HashSet<u64> = inbound.iter().map(|m| m.record_id).collect();
+    let mut layers: Vec<_> = inbound.iter()
+        .map(|m| (m.timestamp, m.quantity_change.max(0) as u32, m.unit_cost))
+        .collect();
+    for (itm, _) in warehouse.search_locations_by_id(num_id) {
+        if !received.contains(&itm.record_id) {
+            layers.push((itm.timestamp, itm.item_quantity, itm.unit_cost));
+        }
+    }
+    layers.sort_by_key(|(timestamp, _, _)| *timestamp);
+    layers
+}
+
+// Cost per unit of the `on_hand` quantity of a product. Under FIFO the oldest receipts were
+// consumed first, so what is left is valued at the newest layers; stock beyond the known
+// receipts (e.g. found in a count) takes the oldest cost.
+pub fn unit_cost<S: StorageBackend>(warehouse: &Warehouse<S>, num_id: u32, on_hand: u32, method: ValuationMethod) -> f64 {
+    let layers = cost_layers(warehouse, num_id);
+    if layers.is_empty() || on_hand == 0 {
+        return 0.0;
+    }
+    match method {
+        ValuationMethod::WeightedAverage => {
+            let quantity: u64 = layers.iter().map(|(_, q, _)| *q as u64).sum();
+            let cost: f64 = layers.iter().map(|(_, q, c)| *q as f64 * c).sum();
+            if quantity == 0 { 0.0 } else { cost / quantity as f64 }
+        },
+        ValuationMethod::Fifo => {
+            let mut remaining = on_hand;
+            let mut value = 0.0;
+            for (_, quantity, cost) in layers.iter().rev() {
+                let taken = remaining.min(*quantity);
+                value += taken as f64 * cost;
+                remaining -= taken;
+                if remaining == 0 {
+                    break;
+                }
+            }
+            value += remaining as f64 * layers[0].2;
+            value / on_hand as f64
+        },
+    }
+}
+
+// Current stock valued with `method`, split by product, row and quality class
+pub fn valuation_report<S: StorageBackend>(warehouse: &Warehouse<S>, method: ValuationMethod) -> Vec<ValuationLine> {
+    let grouped = warehouse.grouped_items();
+
+    let mut on_hand: HashMap<u32, u32> = HashMap::new();
+    for (itm, _) in &grouped {
+        *on_hand.entry(itm.num_id).or_insert(0) += itm.item_quantity;
+    }
+    let costs: HashMap<u32, f64> = on_hand.iter()
+        .map(|(num_id, quantity)| (*num_id, unit_cost(warehouse, *num_id, *quantity, method)))
+        .collect();
+
+    let mut lines: BTreeMap<(u32, u32, String), (String, u32)> = BTreeMap::new();
+    for (itm, locs) in grouped {
+        let entry = lines.entry((itm.num_id, locs[0].row, itm.quality_label()))
+            .or_insert((itm.name.clone(), 0));
+        entry.1 += itm.item_quantity;
+    }
+
+    lines.into_iter().map(|((num_id, row, quality), (name, quantity))| {
+        let unit_cost = costs[&num_id];
+        ValuationLine { num_id, name, row, quality, quantity, unit_cost, value: quantity as f64 * unit_cost }
+    }).collect()
+}
+
+// Sums line values under a key, e.g. by product or by row
+pub fn subtotals<K: Ord, F: Fn(&ValuationLine) -> K>(lines: &[ValuationLine], key: F) -> BTreeMap<K, (u32, f64)> {
+    let mut totals = BTreeMap::new();
+    for line in lines {
+        let entry = totals.entry(key(line)).or_insert((0, 0.0));
+        entry.0 += line.quantity;
+        entry.1 += line.value;
+    }
+    totals
+}
+
+pub fn export_csv(lines: &[ValuationLine], method: ValuationMethod, path: &str) -> Result<(), String> {
+    let mut writer = csv::Writer::from_path(path).map_err(|e| format!("Não foi possível criar {}: {}", path, e))?;
+    writer.write_record(["method", "product_id", "name", "row", "quality", "quantity", "unit_cost", "value"])
+        .map_err(|e| e.to_string())?;
+    for line in lines {
+        writer.write_record([
+            method.label().to_string(),
+            line.num_id.to_string(),
+            line.name.clone(),
+            line.row.to_string(),
+            line.quality.clone(),
+            line.quantity.to_string(),
+            format!("{:.4}", line.unit_cost),
+            format!("{:.2}", line.value),
+        ]).map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())
+}
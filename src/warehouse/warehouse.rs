@@ -1,8 +1,18 @@
-use super::item::{Item, ItemQuality};
-use chrono::NaiveDate;
-use std::collections::HashMap;
-
-#[derive(Hash, Eq, PartialEq, Debug, Clone, PartialOrd, Ord)]
+use super::climate::{ClimateMap, ClimateScope, ClimateZone};
+use super::hazard::{SegregationMatrix, SegregationViolation};
+use super::item::{HandlingAttribute, Item};
+use super::layout::WarehouseLayout;
+use super::movement::{AdjustmentReason, Movement, MovementKind};
+use super::reservation::{Reservation, StockSummary};
+use super::storage::{MemoryStorage, StorageBackend};
+use super::uom::PackagingCatalog;
+use super::users::{Permission, Session, User};
+use chrono::{NaiveDate, Utc};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde::{Deserialize, Serialize};
+
+#[derive(Hash, Eq, PartialEq, Debug, Clone, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Location {
     pub row: u32,
     pub shelf: u32,
@@ -10,186 +20,466 @@ pub struct Location {
     pub zone: u32,
 }
 
-pub struct Warehouse {
-    pub items: HashMap<Location, Item>,
+// The cells live in a `StorageBackend` (in memory unless another one is chosen); every
+// operation below goes through the trait, so allocation and searches work over any backend
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Warehouse<S = MemoryStorage> {
+    #[serde(flatten)]
+    pub storage: S, // items and usage_count in the JSON snapshot
     pub max_dimensions: (u32, u32, u32, u32), // (rows, shelves, levels, zones)
-    pub usage_count: HashMap<Location, u32>,
+    pub climate: ClimateMap,
+    pub secure_rows: HashSet<u32>,
+    pub segregation: SegregationMatrix,
+    pub reservations: Vec<Reservation>,
+    pub layout: WarehouseLayout,
+    pub movements: Vec<Movement>,
+    pub packaging: PackagingCatalog,
+    next_record_id: u64,
+    next_reservation_id: u32,
+    #[serde(skip)]
+    transaction: Option<Checkpoint>,
+    #[serde(skip)]
+    session: Option<Session>,
+    #[serde(skip, default = "next_revision")]
+    revision: u64,
+}
+
+// Revisions are drawn from one counter for every warehouse, so a warehouse that replaces another
+// (a loaded snapshot, a switched site) never shows the revision a store last wrote
+static REVISIONS: AtomicU64 = AtomicU64::new(1);
+
+fn next_revision() -> u64 {
+    REVISIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+// State outside the storage backend as it was when a transaction began, and the backend's
+// savepoint at that moment
+#[derive(Clone)]
+struct Checkpoint {
+    cells: usize,
+    movements: usize,
+    reservations: Vec<Reservation>,
+    next_record_id: u64,
+    next_reservation_id: u32,
 }
 
 impl Warehouse {
     pub fn new(max_dimensions: (u32, u32, u32, u32)) -> Self {
+        Self::with_storage(MemoryStorage::default(), max_dimensions)
+    }
+
+    pub fn from_json(json: &str) -> Result<Warehouse, String> {
+        serde_json::from_str(json).map_err(|e| format!("JSON inválido: {}", e))
+    }
+}
+
+impl<S: StorageBackend + Serialize> Warehouse<S> {
+    // Full snapshot in the JSON format described in docs/json-schema.md
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+}
+
+impl<S: StorageBackend> Warehouse<S> {
+    // A backend opened on existing cells keeps its records' ids; new records continue after them
+    pub fn with_storage(storage: S, max_dimensions: (u32, u32, u32, u32)) -> Self {
+        let next_record_id = Self::first_free_record_id(&storage);
         Warehouse {
-            items: HashMap::new(),
+            storage,
             max_dimensions,
-            usage_count: HashMap::new(),
+            climate: ClimateMap::default(),
+            secure_rows: HashSet::new(),
+            segregation: SegregationMatrix::standard(),
+            reservations: Vec::new(),
+            layout: WarehouseLayout::default(),
+            movements: Vec::new(),
+            packaging: PackagingCatalog::default(),
+            next_record_id,
+            next_reservation_id: 1,
+            transaction: None,
+            session: None,
+            revision: next_revision(),
         }
     }
 
-    pub fn add_item(&mut self, item: Item) -> Result<Vec<Location>, String> {
-        let allocated_locations = self.find_allocation_spot(&item)?;
+    // The same warehouse (configuration, reservations, history) over the cells of `storage`,
+    // which become the stock. Used to put a snapshot on a persistent backend.
+    pub fn with_cells<T: StorageBackend>(self, storage: T) -> Warehouse<T> {
+        let mut warehouse = self.map_storage(|_| storage);
+        warehouse.next_record_id = warehouse.next_record_id.max(Warehouse::<T>::first_free_record_id(&warehouse.storage));
+        warehouse.mark_changed();
+        warehouse
+    }
 
-        // Check constraints
-        match item.item_quality {
-            ItemQuality::Fragile => {
-                if let Some(nivel_maximo) = item.nivel_maximo {
-                    for loc in &allocated_locations {
-                        if loc.level > nivel_maximo {
-                            return Err(format!("Não é possível armazenar item frágil acima do nível máximo {}", nivel_maximo));
-                        }
-                    }
-                }
+    // The same warehouse over the same cells, with the backend converted by `convert` (wrapped
+    // in another type, or a database closed into memory). Not for use inside a transaction.
+    pub fn map_storage<T: StorageBackend>(self, convert: impl FnOnce(S) -> T) -> Warehouse<T> {
+        Warehouse {
+            storage: convert(self.storage),
+            max_dimensions: self.max_dimensions,
+            climate: self.climate,
+            secure_rows: self.secure_rows,
+            segregation: self.segregation,
+            reservations: self.reservations,
+            layout: self.layout,
+            movements: self.movements,
+            packaging: self.packaging,
+            next_record_id: self.next_record_id,
+            next_reservation_id: self.next_reservation_id,
+            transaction: None,
+            session: self.session,
+            revision: self.revision,
+        }
+    }
+
+    fn first_free_record_id(storage: &S) -> u64 {
+        storage.iter().map(|(_, itm)| itm.record_id).max().map_or(1, |id| id + 1)
+    }
+
+    // Mutations are checked against the signed-in user's role. Without a session (server,
+    // background jobs) the warehouse is not restricted.
+    pub fn sign_in(&mut self, user: &User) {
+        self.session = Some(user.session());
+    }
+
+    pub fn sign_out(&mut self) {
+        self.session = None;
+    }
+
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_ref()
+    }
+
+    pub fn authorize(&self, permission: Permission) -> Result<(), String> {
+        match &self.session {
+            Some(session) => session.authorize(permission),
+            None => Ok(()),
+        }
+    }
+
+    // Changes whenever stock, history, reservations or the climate change. Fields edited directly
+    // (layout, packaging, segregation, secure rows) are followed by `mark_changed`.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn mark_changed(&mut self) {
+        self.revision = next_revision();
+    }
+
+    // Record id the next stored item will get
+    pub fn next_record_id(&self) -> u64 {
+        self.next_record_id
+    }
+
+    pub fn item_at(&self, location: &Location) -> Option<&Item> {
+        self.storage.get(location)
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = (&Location, &Item)> + '_ {
+        self.storage.iter()
+    }
+
+    // Opens a transaction: the operations that follow apply at once and are visible to later
+    // ones, but stock, reservations and movement history can still be restored with `rollback`.
+    // `commit` makes them durable in the storage backend as a single unit.
+    pub fn begin(&mut self) -> Result<(), String> {
+        if self.transaction.is_some() {
+            return Err("Já existe uma transação em curso.".to_string());
+        }
+        self.storage.begin();
+        self.transaction = Some(self.checkpoint());
+        Ok(())
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            cells: self.storage.savepoint(),
+            movements: self.movements.len(),
+            reservations: self.reservations.clone(),
+            next_record_id: self.next_record_id,
+            next_reservation_id: self.next_reservation_id,
+        }
+    }
+
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        self.storage.rollback_to(checkpoint.cells);
+        self.movements.truncate(checkpoint.movements);
+        self.reservations = checkpoint.reservations;
+        self.next_record_id = checkpoint.next_record_id;
+        self.next_reservation_id = checkpoint.next_reservation_id;
+        self.mark_changed();
+    }
+
+    // If the backend cannot persist the changes they are all rolled back and its error returned
+    pub fn commit(&mut self) -> Result<(), String> {
+        if self.transaction.is_none() {
+            return Err("Não há nenhuma transação em curso.".to_string());
+        }
+        if let Err(e) = self.storage.commit() {
+            self.rollback();
+            return Err(e);
+        }
+        self.transaction = None;
+        Ok(())
+    }
+
+    pub fn rollback(&mut self) {
+        if let Some(checkpoint) = self.transaction.take() {
+            self.restore(checkpoint);
+            self.storage.rollback();
+        }
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.is_some()
+    }
+
+    // Runs `operations` as one transaction: all of them take effect, or none if any fails.
+    // Inside an open transaction a failure undoes only these operations, and they are made
+    // durable by the enclosing commit.
+    pub fn transaction<T>(&mut self, operations: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        if self.transaction.is_some() {
+            let checkpoint = self.checkpoint();
+            return operations(self).inspect_err(|_| self.restore(checkpoint));
+        }
+        self.begin()?;
+        match operations(self) {
+            Ok(result) => self.commit().map(|_| result),
+            Err(e) => {
+                self.rollback();
+                Err(e)
             },
-            ItemQuality::Oversized => {
-                if let Some(required_zones) = item.required_zones {
-                    if allocated_locations.len() as u32 != required_zones {
-                        return Err(format!("Item oversized requer {} zonas contíguas, mas foram alocadas {}", required_zones, allocated_locations.len()));
-                    }
-                }
+        }
+    }
+
+    // Applies a change to the cells as one unit: it is committed to the backend, or rolled back
+    // and the backend's error returned. Inside a transaction it waits for the transaction's commit.
+    fn write_storage<T>(&mut self, change: impl FnOnce(&mut S) -> T) -> Result<T, String> {
+        self.mark_changed();
+        if self.transaction.is_some() {
+            return Ok(change(&mut self.storage));
+        }
+        self.storage.begin();
+        let result = change(&mut self.storage);
+        match self.storage.commit() {
+            Ok(()) => Ok(result),
+            Err(e) => {
+                self.storage.rollback();
+                Err(e)
             },
-            ItemQuality::Normal => {}
         }
+    }
 
-        for loc in &allocated_locations {
-            self.items.insert(loc.clone(), item.clone());
-            let count = self.usage_count.get(loc).cloned().unwrap_or(0);
-            self.usage_count.insert(loc.clone(), count + 1);
+    pub fn add_item(&mut self, mut item: Item) -> Result<Vec<Location>, String> {
+        self.authorize(Permission::Store)?;
+        item.validate_levels(self.max_dimensions.2)?;
+        item.validate_tracking()?;
+        if !item.unit_cost.is_finite() || item.unit_cost < 0.0 {
+            return Err("Custo unitário inválido.".to_string());
         }
+        if let Some(serial) = item.serials.iter().find(|s| self.search_by_serial(s).is_some()) {
+            return Err(format!("Número de série {} já existe no armazém.", serial));
+        }
+        let allocated_locations = self.find_allocation_spot(&item)?;
+
+        item.record_id = self.next_record_id;
+        self.write_storage(|storage| {
+            for loc in &allocated_locations {
+                storage.put(loc.clone(), item.clone());
+                storage.record_use(loc);
+            }
+        })?;
+        self.next_record_id += 1;
+        self.record_movement(MovementKind::Inbound, &item, item.item_quantity as i64, None, allocated_locations.first().cloned(), None);
 
         Ok(allocated_locations)
     }
 
-    fn find_allocation_spot(&self, item: &Item) -> Result<Vec<Location>, String> {
-        let (rows, shelves, levels, zones) = self.max_dimensions;
-
-        match item.item_quality {
-            ItemQuality::Normal => {
-                let mut candidates = Vec::new();
-                for row in 0..rows {
-                    for shelf in 0..shelves {
-                        for level in 0..levels {
-                            for zone in 0..zones {
-                                let loc = Location { row, shelf, level, zone };
-                                if !self.items.contains_key(&loc) {
-                                    let usage = self.usage_count.get(&loc).cloned().unwrap_or(0);
-                                    candidates.push((usage, loc));
-                                }
-                            }
-                        }
-                    }
-                }
+    fn record_movement(&mut self, kind: MovementKind, item: &Item, quantity_change: i64, from: Option<Location>, to: Option<Location>, reason: Option<AdjustmentReason>) {
+        self.mark_changed();
+        self.movements.push(Movement {
+            timestamp: Utc::now().timestamp(),
+            kind,
+            record_id: item.record_id,
+            num_id: item.num_id,
+            name: item.name.clone(),
+            quantity_change,
+            from,
+            to,
+            reason,
+            lot: item.lot.clone(),
+            serials: item.serials.clone(),
+            unit_cost: item.unit_cost,
+            user: self.session.as_ref().map(|s| s.username.clone()),
+        });
+    }
 
-                if candidates.is_empty() {
-                    return Err("Nenhuma localização disponível encontrada.".to_string());
-                }
+    // Changes the climate of part of the grid, refusing if stock already stored there needs another climate
+    pub fn assign_climate(&mut self, scope: ClimateScope, zone: ClimateZone) -> Result<(), String> {
+        self.authorize(Permission::Configure)?;
+        let mut climate = self.climate.clone();
+        climate.assign(scope, zone);
+        let mut conflicts: Vec<String> = self.grouped_items()
+            .into_iter()
+            .filter(|(itm, locs)| locs.iter().any(|loc| climate.zone_at(loc) != itm.climate()))
+            .map(|(itm, locs)| format!("ID {} {} (F{}, P{}, N{}, Z{}) exige {}", itm.num_id, itm.name, locs[0].row, locs[0].shelf, locs[0].level, locs[0].zone, itm.climate().label()))
+            .collect();
+        if !conflicts.is_empty() {
+            conflicts.sort();
+            return Err(format!("Não é possível atribuir {} a ({}): {}.", zone.label(), scope.label(), conflicts.join("; ")));
+        }
+        self.climate = climate;
+        self.mark_changed();
+        Ok(())
+    }
 
-                candidates.sort_by(|(u1, l1), (u2, l2)| u1.cmp(u2).then(l1.cmp(l2)));
+    // Whether `item` could occupy `loc` as far as climate, handling attributes and segregation go
+    pub fn location_fits(&self, item: &Item, loc: &Location) -> bool {
+        self.fits(item, loc, &self.segregated_cells(item))
+    }
 
-                Ok(vec![candidates[0].1.clone()])
-            },
-            ItemQuality::Fragile => {
-                let nivel_maximo = item.nivel_maximo.unwrap_or(levels - 1);
-                let mut candidates = Vec::new();
-                for row in 0..rows {
-                    for shelf in 0..shelves {
-                        for level in 0..levels {
-                            if level > nivel_maximo {
-                                continue;
-                            }
-                            for zone in 0..zones {
-                                let loc = Location { row, shelf, level, zone };
-                                if !self.items.contains_key(&loc) {
-                                    let usage = self.usage_count.get(&loc).cloned().unwrap_or(0);
-                                    candidates.push((usage, loc));
-                                }
-                            }
-                        }
-                    }
-                }
+    // `location_fits` against an index of the segregated cells built once for many locations
+    fn fits(&self, item: &Item, loc: &Location, segregated: &[(Location, Item)]) -> bool {
+        self.climate.zone_at(loc) == item.climate()
+            && item.attributes.iter().all(|a| self.attribute_allows(a, loc))
+            && !segregated.iter().any(|(other_loc, other)| self.segregation.conflict(item, loc, other, other_loc).is_some())
+    }
 
-                if candidates.is_empty() {
-                    return Err("Nenhuma localização disponível encontrada para item frágil.".to_string());
-                }
+    // Cells of the other records `item` must keep its distance from; none if it has no segregation group
+    fn segregated_cells(&self, item: &Item) -> Vec<(Location, Item)> {
+        if item.segregation_groups().is_empty() {
+            return Vec::new();
+        }
+        self.storage.iter()
+            .filter(|(_, other)| other.record_id != item.record_id && !other.segregation_groups().is_empty())
+            .map(|(loc, other)| (loc.clone(), other.clone()))
+            .collect()
+    }
 
-                candidates.sort_by(|(u1, l1), (u2, l2)| u1.cmp(u2).then(l1.cmp(l2)));
+    // Each handling attribute contributes its own restriction on where the item may be stored
+    fn attribute_allows(&self, attribute: &HandlingAttribute, loc: &Location) -> bool {
+        match attribute {
+            HandlingAttribute::LevelRange { nivel_minimo, nivel_maximo } => loc.level >= *nivel_minimo && loc.level <= *nivel_maximo,
+            HandlingAttribute::Fragile => true,
+            // The footprint itself is handled by the contiguous run search
+            HandlingAttribute::Oversized { .. } => true,
+            // Hazard classes are kept apart by the segregation matrix, not by level
+            HandlingAttribute::Hazardous(_) => true,
+            HandlingAttribute::Food => true,
+            HandlingAttribute::Climate(zone) => self.climate.zone_at(loc) == *zone,
+            HandlingAttribute::HighValue => self.secure_rows.contains(&loc.row),
+            HandlingAttribute::StackLimit(limit) => loc.level < *limit,
+        }
+    }
 
-                Ok(vec![candidates[0].1.clone()])
-            },
-            ItemQuality::Oversized => {
-                let required_zones = item.required_zones.ok_or("Item oversized sem zonas contíguas necessárias especificadas.".to_string())?;
+    fn find_allocation_spot(&self, item: &Item) -> Result<Vec<Location>, String> {
+        let (rows, shelves, levels, zones) = self.max_dimensions;
 
-                if required_zones == 0 {
-                    return Err("Número de zonas contíguas inválido para item oversized.".to_string());
-                }
+        let (required_zones, required_levels, required_shelves) = item.footprint();
+        if required_zones == 0 || required_levels == 0 || required_shelves == 0 {
+            return Err("Dimensões inválidas para item oversized.".to_string());
+        }
 
-                let (rows, shelves, levels, zones) = self.max_dimensions;
-                let mut candidates = Vec::new();
-                for row in 0..rows {
-                    for shelf in 0..shelves {
-                        for level in 0..levels {
-                            let mut start_zone = 0;
-                            while start_zone + required_zones <= zones {
-                                let mut run = Vec::new();
-                                let mut sum_usage = 0;
-                                let mut all_free = true;
-                                for z in start_zone..(start_zone + required_zones) {
-                                    let loc = Location { row, shelf, level, zone: z };
-                                    if self.items.contains_key(&loc) {
+        // Every free block of required_shelves x required_levels x required_zones cells is a candidate
+        let segregated = self.segregated_cells(item);
+        let mut candidates = Vec::new();
+        for row in 0..rows {
+            let mut start_shelf = 0;
+            while start_shelf + required_shelves <= shelves {
+                let mut start_level = 0;
+                while start_level + required_levels <= levels {
+                    let mut start_zone = 0;
+                    while start_zone + required_zones <= zones {
+                        let mut block = Vec::new();
+                        let mut sum_usage = 0;
+                        let mut all_free = true;
+                        'block: for shelf in start_shelf..(start_shelf + required_shelves) {
+                            for level in start_level..(start_level + required_levels) {
+                                for zone in start_zone..(start_zone + required_zones) {
+                                    let loc = Location { row, shelf, level, zone };
+                                    if self.storage.contains(&loc) || !self.fits(item, &loc, &segregated) {
                                         all_free = false;
-                                        break;
+                                        break 'block;
                                     } else {
-                                        let usage = self.usage_count.get(&loc).cloned().unwrap_or(0);
+                                        let usage = self.storage.usage(&loc);
                                         sum_usage += usage;
-                                        run.push(loc);
+                                        block.push(loc);
                                     }
                                 }
-
-                                if all_free {
-                                    candidates.push((sum_usage, run[0].clone(), run));
-                                }
-
-                                start_zone += 1;
                             }
                         }
+
+                        if all_free {
+                            candidates.push((sum_usage, block[0].clone(), block));
+                        }
+
+                        start_zone += 1;
                     }
+                    start_level += 1;
                 }
+                start_shelf += 1;
+            }
+        }
 
-                if candidates.is_empty() {
-                    return Err("Não há zonas contíguas suficientes para o item oversized.".to_string());
-                }
+        if candidates.is_empty() {
+            return if item.is_oversized() {
+                Err(format!("Não há um bloco livre de {} zonas x {} níveis x {} prateleiras para o item oversized.", required_zones, required_levels, required_shelves))
+            } else {
+                Err(format!("Nenhuma localização disponível encontrada para item {}.", item.quality_label()))
+            };
+        }
 
-                candidates.sort_by(|(sum1, first1, _), (sum2, first2, _)| sum1.cmp(&sum2).then(first1.cmp(&first2)));
+        candidates.sort_by(|(sum1, first1, _), (sum2, first2, _)| sum1.cmp(sum2).then(first1.cmp(first2)));
 
-                let (_, _, best_run) = candidates[0].clone();
-                Ok(best_run)
-            },
+        let (_, _, best_block) = candidates[0].clone();
+        Ok(best_block)
+    }
+
+    // Scans the stored items for pairs that break the current segregation matrix
+    pub fn validate_segregation(&self) -> Vec<SegregationViolation> {
+        let mut grouped = self.grouped_items();
+        grouped.retain(|(itm, _)| !itm.segregation_groups().is_empty());
+        grouped.sort_by(|(_, l1), (_, l2)| l1.cmp(l2));
+
+        let mut violations = Vec::new();
+        for (i, (first, first_locs)) in grouped.iter().enumerate() {
+            for (second, second_locs) in &grouped[i + 1..] {
+                'pair: for loc_a in first_locs {
+                    for loc_b in second_locs {
+                        if let Some((group_a, group_b, separation)) = self.segregation.conflict(first, loc_a, second, loc_b) {
+                            violations.push(SegregationViolation {
+                                first: first.clone(),
+                                first_location: loc_a.clone(),
+                                second: second.clone(),
+                                second_location: loc_b.clone(),
+                                groups: (group_a, group_b),
+                                separation,
+                            });
+                            break 'pair;
+                        }
+                    }
+                }
+            }
         }
+        violations
     }
 
     pub fn remove_item(&mut self, location: &Location) -> Result<Vec<Item>, String> {
-        if let Some(item) = self.items.get(location) {
-            let num_id = item.num_id;
-            let name = item.name.clone();
-            let timestamp = item.timestamp;
-
-            let mut locations_to_remove = Vec::new();
-            match item.item_quality {
-                ItemQuality::Oversized => {
-                    locations_to_remove = self.items
-                        .iter()
-                        .filter(|(_, itm)| itm.num_id == num_id && itm.name == name && itm.timestamp == timestamp)
-                        .map(|(loc, _)| loc.clone())
-                        .collect();
-                },
-                _ => {
-                    locations_to_remove.push(location.clone());
-                }
+        self.authorize(Permission::Remove)?;
+        if let Some(item) = self.storage.get(location) {
+            let reserved = self.reserved_quantity(item.record_id);
+            if reserved > 0 {
+                return Err(format!("Item tem {} unidade(s) reservada(s); liberte as reservas antes de o remover.", reserved));
             }
 
-            let mut removed_items = Vec::new();
-            for loc in &locations_to_remove {
-                if let Some(itm) = self.items.remove(loc) {
-                    removed_items.push(itm);
-                }
+            let locations_to_remove = self.record_locations(item.record_id);
+
+            let removed_items: Vec<Item> = self.write_storage(|storage| {
+                locations_to_remove.iter().filter_map(|loc| storage.remove(loc)).collect()
+            })?;
+            if let Some(itm) = removed_items.first().cloned() {
+                self.record_movement(MovementKind::Removal, &itm, -(itm.item_quantity as i64), locations_to_remove.first().cloned(), None, None);
             }
 
             Ok(removed_items)
@@ -199,10 +489,9 @@ impl Warehouse {
     }
 
     pub fn grouped_items(&self) -> Vec<(Item, Vec<Location>)> {
-        let mut map: HashMap<(u32, String, i64), Vec<(Location, Item)>> = HashMap::new();
-        for (loc, itm) in &self.items {
-            let key = (itm.num_id, itm.name.clone(), itm.timestamp);
-            map.entry(key).or_default().push((loc.clone(), itm.clone()));
+        let mut map: HashMap<u64, Vec<(Location, Item)>> = HashMap::new();
+        for (loc, itm) in self.storage.iter() {
+            map.entry(itm.record_id).or_default().push((loc.clone(), itm.clone()));
         }
 
         let mut result = Vec::new();
@@ -216,26 +505,267 @@ impl Warehouse {
         result
     }
 
-    pub fn search_by_name(&self, name: &str) -> (bool, u32) {
+    pub fn search_by_name(&self, name: &str) -> StockSummary {
         let grouped = self.grouped_items();
-        let mut total = 0;
+        let mut summary = StockSummary::default();
         for (itm, _) in grouped {
             if itm.name == name {
-                total += itm.item_quantity;
+                summary.add(itm.item_quantity, self.reserved_quantity(itm.record_id));
             }
         }
-        (total > 0, total)
+        summary
     }
 
-    pub fn search_by_id(&self, num_id: u32) -> (bool, u32) {
+    pub fn search_by_id(&self, num_id: u32) -> StockSummary {
         let grouped = self.grouped_items();
-        let mut total = 0;
+        let mut summary = StockSummary::default();
         for (itm, _) in grouped {
             if itm.num_id == num_id {
-                total += itm.item_quantity;
+                summary.add(itm.item_quantity, self.reserved_quantity(itm.record_id));
+            }
+        }
+        summary
+    }
+
+    // All cells occupied by one stored record (more than one for oversized items)
+    pub fn record_locations(&self, record_id: u64) -> Vec<Location> {
+        let mut locations: Vec<Location> = self.storage
+            .iter()
+            .filter(|(_, itm)| itm.record_id == record_id)
+            .map(|(loc, _)| loc.clone())
+            .collect();
+        locations.sort();
+        locations
+    }
+
+    pub fn reserved_quantity(&self, record_id: u64) -> u32 {
+        self.reservations.iter().filter(|r| r.record_id == record_id).map(|r| r.quantity).sum()
+    }
+
+    pub fn reserve(&mut self, location: &Location, quantity: u32, order_ref: String, expires: NaiveDate) -> Result<Reservation, String> {
+        self.authorize(Permission::Pick)?;
+        let item = self.storage.get(location).ok_or("Nenhum item encontrado nesta localização.".to_string())?;
+        if quantity == 0 {
+            return Err("Quantidade a reservar tem de ser maior que zero.".to_string());
+        }
+        let available = item.item_quantity.saturating_sub(self.reserved_quantity(item.record_id));
+        if quantity > available {
+            return Err(format!("Só há {} unidade(s) disponível(is) para reservar.", available));
+        }
+
+        let reservation = Reservation {
+            id: self.next_reservation_id,
+            record_id: item.record_id,
+            quantity,
+            order_ref,
+            expires,
+        };
+        self.next_reservation_id += 1;
+        self.reservations.push(reservation.clone());
+        self.mark_changed();
+        Ok(reservation)
+    }
+
+    pub fn release_reservation(&mut self, reservation_id: u32) -> Result<Reservation, String> {
+        self.authorize(Permission::Pick)?;
+        let index = self.reservations
+            .iter()
+            .position(|r| r.id == reservation_id)
+            .ok_or(format!("Reserva {} não encontrada.", reservation_id))?;
+        self.mark_changed();
+        Ok(self.reservations.remove(index))
+    }
+
+    pub fn purge_expired_reservations(&mut self, today: NaiveDate) -> Vec<Reservation> {
+        let (expired, active) = self.reservations.drain(..).partition(|r| r.is_expired(today));
+        self.reservations = active;
+        if !expired.is_empty() {
+            self.mark_changed();
+        }
+        expired
+    }
+
+    // Takes a reservation's quantity out of its record, removing the record once it is empty.
+    // A reservation past its expiry cannot be picked even before it is purged.
+    pub fn pick_reservation(&mut self, reservation_id: u32, today: NaiveDate) -> Result<Item, String> {
+        self.authorize(Permission::Pick)?;
+        let index = self.reservations
+            .iter()
+            .position(|r| r.id == reservation_id)
+            .ok_or(format!("Reserva {} não encontrada.", reservation_id))?;
+        let reservation = &self.reservations[index];
+        if reservation.is_expired(today) {
+            return Err(format!("Reserva {} expirou em {}.", reservation_id, reservation.expires.format("%d-%m-%Y")));
+        }
+
+        let locations = self.record_locations(reservation.record_id);
+        let item = locations.first().and_then(|loc| self.storage.get(loc)).cloned()
+            .ok_or("O registo reservado já não existe no armazém.".to_string())?;
+        if reservation.quantity > item.item_quantity {
+            return Err(format!("Reserva {} excede a quantidade em stock ({}).", reservation_id, item.item_quantity));
+        }
+
+        let picked = reservation.quantity;
+        let picked_item = self.write_storage(|storage| Self::take_units(storage, &item, &locations, picked))?;
+        self.reservations.remove(index);
+        self.record_movement(MovementKind::Outbound, &picked_item, -(picked as i64), locations.first().cloned(), None, None);
+        Ok(picked_item)
+    }
+
+    // Takes part of a record's available stock out of the warehouse, removing the record once it is empty
+    pub fn remove_quantity(&mut self, location: &Location, quantity: u32) -> Result<Item, String> {
+        self.authorize(Permission::Remove)?;
+        let item = self.storage.get(location).cloned().ok_or("Nenhum item encontrado nesta localização.".to_string())?;
+        if quantity == 0 {
+            return Err("Quantidade a remover tem de ser maior que zero.".to_string());
+        }
+        let available = item.item_quantity.saturating_sub(self.reserved_quantity(item.record_id));
+        if quantity > available {
+            return Err(format!("Só há {} unidade(s) disponível(is) para remover.", available));
+        }
+
+        let locations = self.record_locations(item.record_id);
+        let removed = self.write_storage(|storage| Self::take_units(storage, &item, &locations, quantity))?;
+        self.record_movement(MovementKind::Removal, &removed, -(quantity as i64), locations.first().cloned(), None, None);
+        Ok(removed)
+    }
+
+    // Splits `quantity` units off a stored record and returns them; serialised units leave in the order they were received
+    fn take_units(storage: &mut S, item: &Item, locations: &[Location], quantity: u32) -> Item {
+        let remaining = item.item_quantity - quantity;
+        let mut taken = item.clone();
+        taken.item_quantity = quantity;
+        let remaining_serials = if item.is_serialised() {
+            taken.serials = item.serials[..quantity as usize].to_vec();
+            item.serials[quantity as usize..].to_vec()
+        } else {
+            Vec::new()
+        };
+
+        for loc in locations {
+            if remaining == 0 {
+                storage.remove(loc);
+            } else if let Some(itm) = storage.get_mut(loc) {
+                itm.item_quantity = remaining;
+                itm.serials = remaining_serials.clone();
+            }
+        }
+        taken
+    }
+
+    // Sets the quantity of the record stored at `location`, never below what is reserved. A record
+    // adjusted to zero leaves the warehouse, freeing every cell it occupied.
+    pub fn adjust_quantity(&mut self, location: &Location, new_quantity: u32, reason: AdjustmentReason) -> Result<u32, String> {
+        self.authorize(Permission::Adjust)?;
+        let item = self.storage.get(location).cloned().ok_or("Nenhum item encontrado nesta localização.".to_string())?;
+        let record_id = item.record_id;
+        let old_quantity = item.item_quantity;
+        if item.is_serialised() && new_quantity != old_quantity {
+            return Err("Item com números de série: remova ou receba as unidades em vez de ajustar a quantidade.".to_string());
+        }
+
+        let reserved = self.reserved_quantity(record_id);
+        if new_quantity < reserved {
+            return Err(format!("Não é possível ajustar para {}: há {} unidade(s) reservada(s).", new_quantity, reserved));
+        }
+
+        let locations = self.record_locations(record_id);
+        self.write_storage(|storage| {
+            for loc in &locations {
+                if new_quantity == 0 {
+                    storage.remove(loc);
+                } else if let Some(itm) = storage.get_mut(loc) {
+                    itm.item_quantity = new_quantity;
+                }
+            }
+        })?;
+        let mut adjusted = item;
+        adjusted.item_quantity = new_quantity;
+        let to = (new_quantity > 0).then(|| location.clone());
+        self.record_movement(MovementKind::Adjustment, &adjusted, new_quantity as i64 - old_quantity as i64, Some(location.clone()), to, Some(reason));
+        Ok(old_quantity)
+    }
+
+    // Moves a whole stored record to new cells, which must be free (or already its own), suitable,
+    // and form the same block shape as the record's footprint
+    pub fn move_record(&mut self, record_id: u64, mut to: Vec<Location>) -> Result<(), String> {
+        self.authorize(Permission::Move)?;
+        let from = self.record_locations(record_id);
+        let item = from.first().and_then(|loc| self.storage.get(loc)).cloned()
+            .ok_or("Registo não encontrado no armazém.".to_string())?;
+        let requested = to.len();
+        to.sort();
+        to.dedup();
+        if to.len() != requested {
+            return Err("Foram indicadas localizações de destino repetidas.".to_string());
+        }
+        if to.len() != from.len() {
+            return Err(format!("O item ocupa {} localização(ões), mas foram indicadas {}.", from.len(), to.len()));
+        }
+        if !Self::is_block(&to, item.footprint()) {
+            let (required_zones, required_levels, required_shelves) = item.footprint();
+            return Err(format!(
+                "As localizações de destino têm de formar um bloco contíguo de {} zonas x {} níveis x {} prateleiras numa só fileira.",
+                required_zones, required_levels, required_shelves
+            ));
+        }
+
+        let (rows, shelves, levels, zones) = self.max_dimensions;
+        for loc in &to {
+            if loc.row >= rows || loc.shelf >= shelves || loc.level >= levels || loc.zone >= zones {
+                return Err("Localização excede as dimensões do armazém.".to_string());
+            }
+            if self.storage.get(loc).is_some_and(|other| other.record_id != record_id) {
+                return Err(format!("Localização (F{}, P{}, N{}, Z{}) já está ocupada.", loc.row, loc.shelf, loc.level, loc.zone));
+            }
+            if !self.location_fits(&item, loc) {
+                return Err(format!("Localização (F{}, P{}, N{}, Z{}) incompatível com o item.", loc.row, loc.shelf, loc.level, loc.zone));
             }
         }
-        (total > 0, total)
+
+        self.write_storage(|storage| {
+            for loc in &from {
+                storage.remove(loc);
+            }
+            for loc in &to {
+                storage.put(loc.clone(), item.clone());
+                storage.record_use(loc);
+            }
+        })?;
+        self.record_movement(MovementKind::Relocation, &item, 0, from.first().cloned(), to.first().cloned(), None);
+        Ok(())
+    }
+
+    // Whether sorted, distinct `cells` are exactly one block of (zones, levels, shelves) in a single row
+    fn is_block(cells: &[Location], (zones, levels, shelves): (u32, u32, u32)) -> bool {
+        let Some(first) = cells.first() else {
+            return false;
+        };
+        let mut block = Vec::new();
+        for shelf in first.shelf..first.shelf + shelves {
+            for level in first.level..first.level + levels {
+                for zone in first.zone..first.zone + zones {
+                    block.push(Location { row: first.row, shelf, level, zone });
+                }
+            }
+        }
+        block.sort();
+        block == cells
+    }
+
+    pub fn search_by_lot(&self, lot: &str) -> Vec<(Item, Vec<Location>)> {
+        let mut result: Vec<_> = self.grouped_items()
+            .into_iter()
+            .filter(|(itm, _)| itm.lot.as_deref() == Some(lot))
+            .collect();
+        result.sort_by(|(_, l1), (_, l2)| l1.cmp(l2));
+        result
+    }
+
+    pub fn search_by_serial(&self, serial: &str) -> Option<(Item, Vec<Location>)> {
+        self.grouped_items()
+            .into_iter()
+            .find(|(itm, _)| itm.serials.iter().any(|s| s == serial))
     }
 
     pub fn search_locations_by_id(&self, num_id: u32) -> Vec<(Item, Vec<Location>)> {
@@ -249,19 +779,27 @@ impl Warehouse {
         result
     }
 
+    // Shortest walking order we can find for visiting the given locations from the dock and back
+    pub fn optimise_route(&self, locations: &[Location]) -> Vec<Location> {
+        self.layout.optimise_route(locations, self.layout.aisle_length(self.max_dimensions))
+    }
+
+    pub fn route_length(&self, route: &[Location]) -> f32 {
+        self.layout.route_length(route, self.layout.aisle_length(self.max_dimensions))
+    }
+
+    // Fragile records that have expired or expire within three days of `reference_date`
     pub fn find_expiring_items_by_date(&self, reference_date: NaiveDate) -> Vec<(Item, String, Vec<Location>)> {
         let grouped = self.grouped_items();
         let mut result = Vec::new();
-        for (itm, locs) in grouped {
-            if let ItemQuality::Fragile = itm.item_quality {
-                if let Some(data_validade) = itm.data_validade {
-                    if data_validade < reference_date {
-                        result.push((itm.clone(), "Expirado".to_string(), locs.clone()));
-                    } else {
-                        let days_to_expire = (data_validade - reference_date).num_days();
-                        if days_to_expire <= 3 && days_to_expire >= 0 {
-                            result.push((itm.clone(), format!("Expira em {} dias", days_to_expire), locs.clone()));
-                        }
+        for (itm, locs) in grouped.into_iter().filter(|(itm, _)| itm.is_fragile()) {
+            if let Some(data_validade) = itm.data_validade {
+                if data_validade < reference_date {
+                    result.push((itm.clone(), "Expirado".to_string(), locs.clone()));
+                } else {
+                    let days_to_expire = (data_validade - reference_date).num_days();
+                    if (0..=3).contains(&days_to_expire) {
+                        result.push((itm.clone(), format!("Expira em {} dias", days_to_expire), locs.clone()));
                     }
                 }
             }
@@ -269,3 +807,76 @@ impl Warehouse {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oversized(zones: u32, levels: u32, shelves: u32) -> Item {
+        let footprint = HandlingAttribute::Oversized { required_zones: zones, required_levels: levels, required_shelves: shelves };
+        Item::new(1, "Palete".to_string(), 1, vec![footprint], None)
+    }
+
+    #[test]
+    fn oversized_items_take_a_block_across_levels_and_shelves() {
+        let mut warehouse = Warehouse::new((2, 3, 3, 3));
+        let locations = warehouse.add_item(oversized(2, 2, 2)).unwrap();
+        assert_eq!(locations.len(), 8);
+        assert!(Warehouse::<MemoryStorage>::is_block(&locations, (2, 2, 2)));
+        let record_id = warehouse.item_at(&locations[0]).unwrap().record_id;
+        assert_eq!(warehouse.record_locations(record_id), locations);
+
+        // Removing it frees the whole block
+        warehouse.remove_item(&locations[7]).unwrap();
+        assert!(locations.iter().all(|loc| warehouse.item_at(loc).is_none()));
+    }
+
+    #[test]
+    fn oversized_items_need_a_free_block_of_their_shape() {
+        let mut warehouse = Warehouse::new((1, 2, 2, 2));
+        warehouse.add_item(Item::new(2, "Caixa".to_string(), 1, vec![], None)).unwrap();
+        assert!(warehouse.add_item(oversized(2, 2, 2)).is_err());
+        assert!(warehouse.add_item(oversized(1, 2, 2)).is_ok());
+        assert!(warehouse.add_item(oversized(1, 3, 1)).is_err());
+    }
+
+    fn tracked(num_id: u32, lot: &str, serials: &[&str]) -> Item {
+        let mut item = Item::new(num_id, format!("Produto {}", num_id), serials.len().max(1) as u32, vec![], None);
+        item.lot = Some(lot.to_string());
+        item.serials = serials.iter().map(|s| s.to_string()).collect();
+        item
+    }
+
+    #[test]
+    fn records_are_found_by_lot_and_serial() {
+        let mut warehouse = Warehouse::new((2, 2, 2, 2));
+        let first = warehouse.add_item(tracked(1, "L1", &["S1", "S2"])).unwrap();
+        let second = warehouse.add_item(tracked(2, "L1", &[])).unwrap();
+        warehouse.add_item(tracked(1, "L2", &["S3"])).unwrap();
+
+        let in_lot: Vec<Vec<Location>> = warehouse.search_by_lot("L1").into_iter().map(|(_, locs)| locs).collect();
+        assert_eq!(in_lot, vec![first.clone(), second]);
+        assert!(warehouse.search_by_lot("L9").is_empty());
+
+        let (itm, locs) = warehouse.search_by_serial("S2").unwrap();
+        assert_eq!((itm.lot.as_deref(), locs), (Some("L1"), first));
+        assert!(warehouse.search_by_serial("S9").is_none());
+    }
+
+    #[test]
+    fn serials_are_unique_and_leave_in_order() {
+        let mut warehouse = Warehouse::new((2, 2, 2, 2));
+        let locations = warehouse.add_item(tracked(1, "L1", &["S1", "S2", "S3"])).unwrap();
+        assert!(warehouse.add_item(tracked(2, "L2", &["S3"])).is_err());
+        assert!(warehouse.add_item(tracked(2, "L2", &["S4", "S4"])).is_err());
+        let mut miscounted = tracked(2, "L2", &["S5"]);
+        miscounted.item_quantity = 2;
+        assert!(warehouse.add_item(miscounted).is_err());
+
+        let taken = warehouse.remove_quantity(&locations[0], 2).unwrap();
+        assert_eq!(taken.serials, vec!["S1", "S2"]);
+        assert!(warehouse.search_by_serial("S1").is_none());
+        assert_eq!(warehouse.search_by_serial("S3").unwrap().0.item_quantity, 1);
+        assert!(warehouse.adjust_quantity(&locations[0], 5, AdjustmentReason::Found).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use rust_projeto::api::{authenticate, handle, read, Response};
+use rust_projeto::warehouse::item::Item;
+use rust_projeto::warehouse::users::{Role, User, UserDirectory};
+use rust_projeto::warehouse::warehouse::{Location, Warehouse};
+use serde_json::{json, Value};
+use std::sync::OnceLock;
+
+fn user(username: &str, role: Role) -> User {
+    let mut users = UserDirectory::default();
+    users.add_user(username, "segredo", role).unwrap();
+    users.authenticate(username, "segredo").unwrap()
+}
+
+fn admin() -> &'static User {
+    static ADMIN: OnceLock<User> = OnceLock::new();
+    ADMIN.get_or_init(|| user("ana", Role::Admin))
+}
+
+fn call_as(warehouse: &mut Warehouse, user: &User, method: &str, url: &str, body: &str) -> (u16, Value) {
+    let Response { status, body } = handle(warehouse, user, method, url, body);
+    (status, serde_json::from_str(&body).unwrap())
+}
+
+fn call(warehouse: &mut Warehouse, method: &str, url: &str, body: &str) -> (u16, Value) {
+    call_as(warehouse, admin(), method, url, body)
+}
+
+fn stocked() -> (Warehouse, Vec<Location>) {
+    let mut warehouse = Warehouse::new((3, 3, 3, 3));
+    let locations = warehouse.add_item(Item::new(7, "Caixa A".to_string(), 12, vec![], None)).unwrap();
+    (warehouse, locations)
+}
+
+#[test]
+fn get_routes_return_the_stored_records() {
+    let (mut warehouse, locations) = stocked();
+    let loc = &locations[0];
+
+    let (status, items) = call(&mut warehouse, "GET", "/items", "");
+    assert_eq!(status, 200);
+    assert_eq!(items.as_array().unwrap().len(), 1);
+    assert_eq!(items[0]["item"]["product_id"], 7);
+    assert_eq!(items[0]["locations"], json!(locations));
+
+    let url = format!("/locations/{}/{}/{}/{}", loc.row, loc.shelf, loc.level, loc.zone);
+    let (status, item) = call(&mut warehouse, "GET", &url, "");
+    assert_eq!(status, 200);
+    assert_eq!(item["quantity"], 12);
+
+    let (status, summary) = call(&mut warehouse, "GET", "/stock/name/Caixa%20A", "");
+    assert_eq!(status, 200);
+    assert_eq!(summary["on_hand"], 12);
+}
+
+#[test]
+fn post_items_stores_the_item() {
+    let mut warehouse = Warehouse::new((3, 3, 3, 3));
+    let (status, body) = call(&mut warehouse, "POST", "/items", r#"{"product_id": 3, "name": "Saco", "quantity": 4}"#);
+    assert_eq!(status, 201);
+    let loc: Location = serde_json::from_value(body["locations"][0].clone()).unwrap();
+    assert_eq!(warehouse.item_at(&loc).unwrap().name, "Saco");
+}
+
+#[test]
+fn post_moves_moves_the_record() {
+    let (mut warehouse, locations) = stocked();
+    let record_id = warehouse.item_at(&locations[0]).unwrap().record_id;
+    let to = Location { row: 2, shelf: 2, level: 0, zone: 2 };
+    let body = json!({ "record_id": record_id, "to": [to] }).to_string();
+
+    let (status, _) = call(&mut warehouse, "POST", "/moves", &body);
+    assert_eq!(status, 200);
+    assert!(warehouse.item_at(&locations[0]).is_none());
+    assert_eq!(warehouse.item_at(&to).unwrap().record_id, record_id);
+
+    let missing = json!({ "record_id": record_id + 1, "to": [to] }).to_string();
+    assert_eq!(call(&mut warehouse, "POST", "/moves", &missing).0, 404);
+}
+
+#[test]
+fn errors_are_reported_with_their_status() {
+    let (mut warehouse, locations) = stocked();
+    let occupied = json!({ "record_id": 99, "to": [locations[0]] });
+
+    assert_eq!(call(&mut warehouse, "POST", "/items", "{ not json").0, 400);
+    assert_eq!(call(&mut warehouse, "GET", "/locations/0/0/x/0", "").0, 400);
+    assert_eq!(call(&mut warehouse, "GET", "/expiring?date=31-12-2030", "").0, 400);
+    assert_eq!(call(&mut warehouse, "GET", "/locations/2/2/2/2", "").0, 404);
+    assert_eq!(call(&mut warehouse, "GET", "/nowhere", "").0, 404);
+    assert_eq!(call(&mut warehouse, "POST", "/moves", &occupied.to_string()).0, 404);
+    assert_eq!(call(&mut warehouse, "PUT", "/items", "").0, 405);
+    assert_eq!(call(&mut warehouse, "DELETE", "/moves", "").0, 405);
+
+    let (status, body) = call(&mut warehouse, "POST", "/items", r#"{"product_id": 1, "name": "Grande", "quantity": 1,
+        "attributes": [{"oversized": {"required_zones": 9, "required_levels": 1, "required_shelves": 1}}]}"#);
+    assert_eq!(status, 422);
+    assert!(body["error"].is_string());
+}
+
+#[test]
+fn requests_are_authenticated_with_basic_credentials() {
+    let mut users = UserDirectory::default();
+    users.add_user("ana", "segredo", Role::Operator).unwrap();
+
+    // "ana:segredo" and "ana:errada"
+    let user = authenticate(&users, Some("Basic YW5hOnNlZ3JlZG8=")).unwrap();
+    assert_eq!((user.username.as_str(), user.role), ("ana", Role::Operator));
+    assert_eq!(authenticate(&users, Some("Basic YW5hOmVycmFkYQ==")).err().unwrap().status, 401);
+    assert_eq!(authenticate(&users, Some("Basic !!!")).err().unwrap().status, 401);
+    assert_eq!(authenticate(&users, Some("Bearer abc")).err().unwrap().status, 401);
+    assert_eq!(authenticate(&users, None).err().unwrap().status, 401);
+}
+
+#[test]
+fn mutations_need_the_role_that_allows_them() {
+    let viewer = user("rui", Role::Viewer);
+    let (mut warehouse, locations) = stocked();
+    let loc = &locations[0];
+
+    assert_eq!(call_as(&mut warehouse, &viewer, "GET", "/items", "").0, 200);
+    assert_eq!(call_as(&mut warehouse, &viewer, "POST", "/items", r#"{"product_id": 3, "name": "Saco", "quantity": 4}"#).0, 403);
+    let url = format!("/locations/{}/{}/{}/{}", loc.row, loc.shelf, loc.level, loc.zone);
+    assert_eq!(call_as(&mut warehouse, &viewer, "DELETE", &url, "").0, 403);
+    let record_id = warehouse.item_at(loc).unwrap().record_id;
+    let body = json!({ "record_id": record_id, "to": [Location { row: 2, shelf: 2, level: 0, zone: 2 }] }).to_string();
+    assert_eq!(call_as(&mut warehouse, &viewer, "POST", "/moves", &body).0, 403);
+    assert_eq!(warehouse.item_at(loc).unwrap().item_quantity, 12);
+    assert!(warehouse.session().is_none());
+}
+
+#[test]
+fn changes_are_recorded_under_the_user_and_reads_need_no_user() {
+    let (mut warehouse, _) = stocked();
+    call(&mut warehouse, "POST", "/items", r#"{"product_id": 3, "name": "Saco", "quantity": 4}"#);
+    assert_eq!(warehouse.movements.last().unwrap().user.as_deref(), Some("ana"));
+    assert!(warehouse.session().is_none());
+
+    let Response { status, body } = read(&warehouse, "/stock/id/3");
+    assert_eq!(status, 200);
+    assert_eq!(serde_json::from_str::<Value>(&body).unwrap()["on_hand"], 4);
+    assert_eq!(read(&warehouse, "/moves").status, 405);
+}
@@ -0,0 +1,26 @@
+use rust_projeto::warehouse::inbound::InboundManager;
+use rust_projeto::warehouse::item::Item;
+use rust_projeto::warehouse::warehouse::Warehouse;
+
+#[test]
+fn putaway_keeps_lot_serials_and_cost_of_the_line() {
+    let mut warehouse = Warehouse::new((5, 5, 5, 5));
+    let mut inbound = InboundManager::default();
+    let po_id = inbound.create_purchase_order("PO-1".to_string(), "Fornecedor".to_string(), None).unwrap();
+
+    let mut template = Item::new(7, "Sensor".to_string(), 3, vec![], None);
+    template.lot = Some("L-42".to_string());
+    template.serials = vec!["S1".to_string(), "S2".to_string(), "S3".to_string()];
+    template.unit_cost = 12.5;
+    inbound.add_line(po_id, template, 3).unwrap();
+    inbound.receive(po_id, 1, 3, 1).unwrap();
+    let locations = inbound.putaway(po_id, 1, &mut warehouse).unwrap();
+
+    let stored = warehouse.item_at(&locations[0]).unwrap();
+    assert_eq!(stored.item_quantity, 2);
+    assert_eq!(stored.lot.as_deref(), Some("L-42"));
+    assert_eq!(stored.serials, vec!["S1".to_string(), "S2".to_string()]);
+    assert_eq!(stored.unit_cost, 12.5);
+    assert_eq!(warehouse.search_by_lot("L-42").len(), 1);
+    assert!(warehouse.search_by_serial("S2").is_some());
+}
@@ -0,0 +1,51 @@
+use rust_projeto::warehouse::inventory_csv::{export_inventory, import_inventory};
+use rust_projeto::warehouse::item::Item;
+use rust_projeto::warehouse::warehouse::Warehouse;
+use std::fs;
+
+fn import(name: &str, csv: &str, warehouse: &mut Warehouse) -> (usize, Vec<usize>) {
+    let path = std::env::temp_dir().join(format!("rust_projeto_{}_{}.csv", std::process::id(), name));
+    fs::write(&path, csv).unwrap();
+    let report = import_inventory(warehouse, path.to_str().unwrap(), |_, id| Ok(id.unwrap_or(1))).unwrap();
+    let _ = fs::remove_file(&path);
+    (report.imported.len(), report.errors.iter().map(|(line, _)| *line).collect())
+}
+
+#[test]
+fn a_bad_row_stops_the_whole_import_and_every_bad_row_is_reported() {
+    let mut warehouse = Warehouse::new((3, 3, 3, 3));
+    let (imported, errors) = import("bad_rows", "id,name,quantity\n1,Caixa,10\n2,Saco,muitos\n3,,4\n", &mut warehouse);
+    assert_eq!(imported, 0);
+    assert_eq!(errors, vec![3, 4]);
+    assert_eq!(warehouse.items().count(), 0);
+    assert!(warehouse.movements.is_empty());
+}
+
+#[test]
+fn a_row_that_does_not_fit_rolls_back_the_rows_before_it() {
+    let mut warehouse = Warehouse::new((1, 1, 1, 2));
+    let (imported, errors) = import("no_room", "id,name,quantity\n1,Caixa,10\n2,Saco,5\n3,Rolo,1\n", &mut warehouse);
+    assert_eq!(imported, 0);
+    assert_eq!(errors, vec![4]);
+    assert_eq!(warehouse.items().count(), 0);
+    assert_eq!(warehouse.next_record_id(), 1);
+}
+
+#[test]
+fn serial_numbers_survive_an_export_and_import() {
+    let mut warehouse = Warehouse::new((3, 3, 3, 3));
+    let mut item = Item::new(4, "Portátil".to_string(), 2, vec![], None);
+    item.serials = vec!["SN-1".to_string(), "SN-2".to_string()];
+    warehouse.add_item(item).unwrap();
+    let path = std::env::temp_dir().join(format!("rust_projeto_{}_serials.csv", std::process::id()));
+    export_inventory(&warehouse, path.to_str().unwrap()).unwrap();
+    let csv = fs::read_to_string(&path).unwrap();
+    let _ = fs::remove_file(&path);
+
+    let mut copy = Warehouse::new((3, 3, 3, 3));
+    assert_eq!(import("serials", &csv, &mut copy), (1, vec![]));
+    assert_eq!(copy.search_by_serial("SN-2").unwrap().0.serials, vec!["SN-1", "SN-2"]);
+
+    let (imported, errors) = import("too_few_serials", "id,name,quantity,serials\n4,Portátil,3,SN-1;SN-2\n", &mut Warehouse::new((3, 3, 3, 3)));
+    assert_eq!((imported, errors), (0, vec![2]));
+}
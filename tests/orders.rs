@@ -0,0 +1,98 @@
+use chrono::NaiveDate;
+use rust_projeto::warehouse::item::Item;
+use rust_projeto::warehouse::order::{OrderLine, OrderManager, OrderStatus};
+use rust_projeto::warehouse::warehouse::Warehouse;
+
+fn today() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2030, 1, 1).unwrap()
+}
+
+fn stocked_warehouse() -> Warehouse {
+    let mut warehouse = Warehouse::new((5, 5, 5, 5));
+    warehouse.add_item(Item::new(1, "Parafuso".to_string(), 5, vec![], None)).unwrap();
+    warehouse.add_item(Item::new(2, "Porca".to_string(), 5, vec![], None)).unwrap();
+    warehouse
+}
+
+fn two_line_order(orders: &mut OrderManager, warehouse: &mut Warehouse) -> u32 {
+    let lines = vec![OrderLine { num_id: 1, quantity: 2 }, OrderLine { num_id: 2, quantity: 3 }];
+    let order_id = orders.create_order("Cliente".to_string(), lines).unwrap();
+    orders.allocate(order_id, warehouse, NaiveDate::from_ymd_opt(2099, 1, 1).unwrap()).unwrap();
+    order_id
+}
+
+#[test]
+fn orders_with_a_lapsed_reservation_are_reopened_instead_of_picked_short() {
+    let mut warehouse = stocked_warehouse();
+    let mut orders = OrderManager::default();
+    let order_id = two_line_order(&mut orders, &mut warehouse);
+    let released = orders.orders[0].reservation_ids[1];
+    warehouse.release_reservation(released).unwrap();
+
+    assert!(orders.create_wave(10, &mut warehouse).is_err());
+    assert_eq!(orders.orders[0].status, OrderStatus::Open);
+    assert!(warehouse.reservations.is_empty());
+
+    orders.allocate(order_id, &mut warehouse, NaiveDate::from_ymd_opt(2099, 1, 1).unwrap()).unwrap();
+    let wave_id = orders.create_wave(10, &mut warehouse).unwrap();
+    orders.confirm_wave(wave_id, &mut warehouse, today()).unwrap();
+    assert_eq!(orders.orders[0].status, OrderStatus::Shipped);
+    assert_eq!(warehouse.search_by_id(1).on_hand, 3);
+    assert_eq!(warehouse.search_by_id(2).on_hand, 2);
+}
+
+#[test]
+fn an_order_ships_only_when_every_line_is_picked() {
+    let mut warehouse = stocked_warehouse();
+    let mut orders = OrderManager::default();
+    two_line_order(&mut orders, &mut warehouse);
+    let wave_id = orders.create_wave(10, &mut warehouse).unwrap();
+
+    orders.confirm_pick(wave_id, 0, &mut warehouse, today()).unwrap();
+    assert_eq!(orders.orders[0].status, OrderStatus::Picking);
+    orders.confirm_pick(wave_id, 1, &mut warehouse, today()).unwrap();
+    assert_eq!(orders.orders[0].status, OrderStatus::Shipped);
+}
+
+#[test]
+fn a_picking_order_whose_reservations_expire_is_reopened_for_what_is_left() {
+    let mut warehouse = stocked_warehouse();
+    let mut orders = OrderManager::default();
+    let order_id = two_line_order(&mut orders, &mut warehouse);
+    let wave_id = orders.create_wave(10, &mut warehouse).unwrap();
+    let first = orders.confirm_pick(wave_id, 0, &mut warehouse, today()).unwrap();
+
+    // Ages the remaining reservation past its expiry
+    assert_eq!(warehouse.purge_expired_reservations(NaiveDate::from_ymd_opt(2099, 1, 2).unwrap()).len(), 1);
+    assert!(orders.confirm_wave(wave_id, &mut warehouse, today()).is_err());
+    assert_eq!(orders.reopen_lapsed(&mut warehouse).unwrap(), vec![order_id]);
+    assert_eq!(orders.orders[0].status, OrderStatus::Open);
+    assert_eq!(orders.waves[0].tasks.len(), 1);
+
+    // Only the line not picked yet is reserved again
+    orders.allocate(order_id, &mut warehouse, NaiveDate::from_ymd_opt(2099, 2, 1).unwrap()).unwrap();
+    assert_eq!(warehouse.reservations.len(), 1);
+    assert_eq!(warehouse.reservations[0].quantity, 5 - first.quantity);
+    let wave_id = orders.create_wave(10, &mut warehouse).unwrap();
+    orders.confirm_wave(wave_id, &mut warehouse, today()).unwrap();
+    assert_eq!(orders.orders[0].status, OrderStatus::Shipped);
+    assert_eq!(warehouse.search_by_id(1).on_hand, 3);
+    assert_eq!(warehouse.search_by_id(2).on_hand, 2);
+}
+
+#[test]
+fn an_expired_reservation_cannot_be_picked_before_it_is_purged() {
+    let mut warehouse = stocked_warehouse();
+    let mut orders = OrderManager::default();
+    two_line_order(&mut orders, &mut warehouse);
+    let wave_id = orders.create_wave(10, &mut warehouse).unwrap();
+
+    let expiry = NaiveDate::from_ymd_opt(2099, 1, 1).unwrap();
+    assert!(orders.confirm_wave(wave_id, &mut warehouse, expiry.succ_opt().unwrap()).is_err());
+    assert_eq!(warehouse.reservations.len(), 2);
+    assert_eq!(warehouse.search_by_id(1).on_hand, 5);
+
+    // The day it expires is still good
+    orders.confirm_wave(wave_id, &mut warehouse, expiry).unwrap();
+    assert_eq!(orders.orders[0].status, OrderStatus::Shipped);
+}
@@ -0,0 +1,37 @@
+use rust_projeto::warehouse::cyclecount::CycleCountManager;
+use rust_projeto::warehouse::item::Item;
+use rust_projeto::warehouse::order::{pick_list, OrderLine};
+use rust_projeto::warehouse::warehouse::Warehouse;
+
+fn warehouse_with_products(count: u32) -> Warehouse {
+    let mut warehouse = Warehouse::new((5, 5, 5, 5));
+    for num_id in 1..=count {
+        warehouse.add_item(Item::new(num_id, format!("Produto {}", num_id), 4, vec![], None)).unwrap();
+    }
+    warehouse
+}
+
+#[test]
+fn pick_lists_follow_the_optimised_route() {
+    let warehouse = warehouse_with_products(4);
+    let lines = vec![OrderLine { num_id: 4, quantity: 2 }, OrderLine { num_id: 1, quantity: 4 }, OrderLine { num_id: 3, quantity: 1 }];
+    let stops = pick_list(&warehouse, &lines).unwrap();
+
+    let locations: Vec<_> = stops.iter().map(|s| s.location.clone()).collect();
+    assert_eq!(locations, warehouse.optimise_route(&locations));
+    assert_eq!(stops.iter().map(|s| s.quantity).sum::<u32>(), 7);
+    assert!(pick_list(&warehouse, &[OrderLine { num_id: 2, quantity: 5 }]).is_err());
+}
+
+#[test]
+fn pending_counts_follow_the_optimised_route() {
+    let warehouse = warehouse_with_products(4);
+    let mut counts = CycleCountManager::default();
+    for num_id in [3, 1, 4] {
+        counts.create_for_product(&warehouse, num_id);
+    }
+
+    let route: Vec<_> = counts.route(&warehouse).into_iter().map(|(_, loc)| loc).collect();
+    assert_eq!(route.len(), 3);
+    assert_eq!(route, warehouse.optimise_route(&route));
+}
@@ -0,0 +1,162 @@
+#![cfg(feature = "server")]
+
+use rust_projeto::warehouse::users::{Role, UserDirectory};
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::{Message, WebSocket};
+
+// "ana:segredo"
+const CREDENTIALS: &str = "Basic YW5hOnNlZ3JlZG8=";
+
+struct Servidor(Child, PathBuf);
+
+impl Drop for Servidor {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+        let _ = std::fs::remove_dir_all(&self.1);
+    }
+}
+
+// A port whose successor is free too, for the events
+fn free_port() -> u16 {
+    loop {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        if port < u16::MAX && TcpListener::bind(("127.0.0.1", port + 1)).is_ok() {
+            return port;
+        }
+    }
+}
+
+// Runs in its own directory, with one operator account in a user file named by the environment
+fn start() -> (Servidor, u16) {
+    let port = free_port();
+    let dir = std::env::temp_dir().join(format!("rust_projeto_servidor_{}_{}", std::process::id(), port));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut users = UserDirectory::default();
+    users.add_user("ana", "segredo", Role::Operator).unwrap();
+    users.save(dir.join("contas.json").to_str().unwrap()).unwrap();
+    let child = Command::new(env!("CARGO_BIN_EXE_servidor"))
+        .arg(format!("127.0.0.1:{}", port))
+        .env("ARMAZEM_UTILIZADORES", "contas.json")
+        .current_dir(&dir)
+        .spawn()
+        .unwrap();
+    let servidor = Servidor(child, dir);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        assert!(Instant::now() < deadline, "o servidor não arrancou");
+        thread::sleep(Duration::from_millis(20));
+    }
+    (servidor, port)
+}
+
+fn post(port: u16, path: &str, body: &str) -> String {
+    post_as(port, Some(CREDENTIALS), path, body)
+}
+
+fn post_as(port: u16, authorization: Option<&str>, path: &str, body: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    let authorization = authorization.map(|value| format!("Authorization: {}\r\n", value)).unwrap_or_default();
+    write!(stream, "POST {} HTTP/1.1\r\nHost: localhost\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path, authorization, body.len(), body).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+fn subscribe(port: u16) -> WebSocket<tungstenite::stream::MaybeTlsStream<TcpStream>> {
+    // The events listener opens just after the REST API
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let (socket, _) = loop {
+        let mut request = format!("ws://127.0.0.1:{}/events", port + 1).into_client_request().unwrap();
+        request.headers_mut().insert("Authorization", CREDENTIALS.parse().unwrap());
+        match tungstenite::connect(request) {
+            Ok(connected) => break connected,
+            Err(e) => assert!(Instant::now() < deadline, "{}", e),
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    if let tungstenite::stream::MaybeTlsStream::Plain(stream) = socket.get_ref() {
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    }
+    socket
+}
+
+fn next_event(socket: &mut WebSocket<tungstenite::stream::MaybeTlsStream<TcpStream>>) -> Value {
+    loop {
+        match socket.read().unwrap() {
+            Message::Text(json) => return serde_json::from_str(&json).unwrap(),
+            Message::Pong(_) | Message::Ping(_) => continue,
+            other => panic!("mensagem inesperada: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn clients_get_a_snapshot_then_changes_and_pings_are_answered() {
+    let (_servidor, port) = start();
+    let mut socket = subscribe(port);
+    assert_eq!(next_event(&mut socket)["type"], "snapshot");
+
+    socket.send(Message::Ping(b"ola".to_vec())).unwrap();
+    loop {
+        match socket.read().unwrap() {
+            Message::Pong(payload) => {
+                assert_eq!(payload, b"ola".to_vec());
+                break;
+            },
+            Message::Ping(_) => continue,
+            other => panic!("mensagem inesperada: {:?}", other),
+        }
+    }
+
+    let response = post(port, "/items", r#"{"product_id": 1, "name": "Caixa", "quantity": 3}"#);
+    assert!(response.starts_with("HTTP/1.1 201"), "{}", response);
+    let event = next_event(&mut socket);
+    assert_eq!(event["type"], "added");
+    assert_eq!(event["item"]["name"], "Caixa");
+
+    socket.close(None).unwrap();
+    while socket.read().is_ok() {}
+}
+
+#[test]
+fn a_client_that_never_reads_does_not_hold_up_requests_or_other_clients() {
+    let (_servidor, port) = start();
+    let _silent = subscribe(port);
+    let mut listening = subscribe(port);
+    assert_eq!(next_event(&mut listening)["type"], "snapshot");
+
+    let name = "x".repeat(64 * 1024);
+    for id in 1..=20 {
+        let body = format!(r#"{{"product_id": {}, "name": "{}", "quantity": 1}}"#, id, name);
+        let started = Instant::now();
+        assert!(post(port, "/items", &body).starts_with("HTTP/1.1 201"));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(next_event(&mut listening)["type"], "added");
+    }
+}
+
+#[test]
+fn requests_without_valid_credentials_are_refused() {
+    let (_servidor, port) = start();
+    let body = r#"{"product_id": 1, "name": "Caixa", "quantity": 3}"#;
+    let response = post_as(port, None, "/items", body);
+    assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+    assert!(response.contains("WWW-Authenticate: Basic"), "{}", response);
+    // "ana:errada"
+    assert!(post_as(port, Some("Basic YW5hOmVycmFkYQ=="), "/items", body).starts_with("HTTP/1.1 401"));
+
+    let _listening = subscribe(port);
+    match tungstenite::connect(format!("ws://127.0.0.1:{}/events", port + 1)) {
+        Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 401),
+        other => panic!("ligação inesperada: {:?}", other.map(|(_, response)| response.status())),
+    }
+}
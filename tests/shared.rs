@@ -0,0 +1,68 @@
+use rust_projeto::warehouse::item::Item;
+use rust_projeto::warehouse::shared::SharedWarehouse;
+use rust_projeto::warehouse::warehouse::{Location, Warehouse};
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+#[test]
+fn concurrent_writers_never_share_a_location() {
+    let shared = SharedWarehouse::new(Warehouse::new((4, 4, 4, 4)));
+    let threads: Vec<_> = (0..8u32).map(|t| {
+        let shared = shared.clone();
+        thread::spawn(move || {
+            (0..30u32).map(|i| {
+                let item = Item::new(t * 100 + i, format!("Item {}-{}", t, i), 1, vec![], None);
+                shared.write(|wh| wh.add_item(item)).unwrap().unwrap()
+            }).collect::<Vec<Vec<Location>>>()
+        })
+    }).collect();
+
+    let allocated: Vec<Location> = threads.into_iter().flat_map(|t| t.join().unwrap()).flatten().collect();
+    assert_eq!(allocated.len(), 240);
+    assert_eq!(allocated.iter().collect::<HashSet<_>>().len(), allocated.len());
+    assert_eq!(shared.read(|wh| wh.items().count()), 240);
+}
+
+#[test]
+fn a_panicking_writer_leaves_nothing_behind() {
+    let shared = SharedWarehouse::new(Warehouse::new((3, 3, 3, 3)));
+    shared.write(|wh| wh.add_item(Item::new(1, "Caixa".to_string(), 5, vec![], None))).unwrap().unwrap();
+
+    let writer = shared.clone();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        writer.write(|wh| {
+            wh.add_item(Item::new(2, "Saco".to_string(), 3, vec![], None)).unwrap();
+            panic!("falha a meio da escrita");
+        })
+    }));
+    assert!(result.is_err());
+
+    shared.read(|wh| {
+        assert_eq!(wh.items().count(), 1);
+        assert_eq!(wh.movements.len(), 1);
+        assert_eq!(wh.next_record_id(), 2);
+        assert!(!wh.in_transaction());
+    });
+    let locations = shared.write(|wh| wh.add_item(Item::new(2, "Saco".to_string(), 3, vec![], None))).unwrap().unwrap();
+    assert_eq!(shared.read(|wh| wh.item_at(&locations[0]).unwrap().record_id), 2);
+}
+
+#[test]
+fn a_failed_transaction_inside_a_write_undoes_only_its_own_part() {
+    let shared = SharedWarehouse::new(Warehouse::new((3, 3, 3, 3)));
+    let result = shared.write(|wh| {
+        wh.add_item(Item::new(1, "Caixa".to_string(), 5, vec![], None)).unwrap();
+        wh.transaction(|wh| {
+            wh.add_item(Item::new(2, "Saco".to_string(), 3, vec![], None))?;
+            Err::<(), String>("desistir".to_string())
+        })
+    }).unwrap();
+    assert!(result.is_err());
+
+    shared.read(|wh| {
+        assert_eq!(wh.items().map(|(_, itm)| itm.num_id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(wh.movements.len(), 1);
+        assert_eq!(wh.next_record_id(), 2);
+    });
+}
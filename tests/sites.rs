@@ -0,0 +1,87 @@
+use rust_projeto::warehouse::climate::{ClimateScope, ClimateZone};
+use rust_projeto::warehouse::item::Item;
+use rust_projeto::warehouse::order::OrderLine;
+use rust_projeto::warehouse::replenishment::ReplenishmentScope;
+use rust_projeto::warehouse::sites::{Site, SiteRegistry, TransferStatus};
+use rust_projeto::warehouse::warehouse::{Location, Warehouse};
+
+#[test]
+fn new_sites_take_the_active_sites_climate_and_secure_rows() {
+    let mut current = Warehouse::new((4, 4, 4, 4));
+    current.climate.assign(ClimateScope::Row(1), ClimateZone::Chilled);
+    current.secure_rows.insert(2);
+    let mut sites = SiteRegistry::new("Porto");
+    sites.add_site("Lisboa", (2, 2, 2, 2), &current).unwrap();
+
+    let mut site = Site::new(current);
+    sites.switch_to("Lisboa", &mut site).unwrap();
+    let loc = Location { row: 1, shelf: 0, level: 0, zone: 0 };
+    assert_eq!(site.warehouse.max_dimensions, (2, 2, 2, 2));
+    assert_eq!(site.warehouse.climate.zone_at(&loc), ClimateZone::Chilled);
+    assert!(site.warehouse.secure_rows.contains(&2));
+    assert!(site.warehouse.grouped_items().is_empty());
+}
+
+#[test]
+fn received_records_are_numbered_by_the_receiving_site() {
+    let mut porto = Warehouse::new((3, 3, 3, 3));
+    for id in 1..=3 {
+        porto.add_item(Item::new(id, format!("Produto {}", id), 5, vec![], None)).unwrap();
+    }
+    let mut sites = SiteRegistry::new("Porto");
+    sites.add_site("Lisboa", (3, 3, 3, 3), &porto).unwrap();
+    let transfer = sites.ship("Lisboa", 3, 2, &mut porto).unwrap();
+
+    let mut site = Site::new(porto);
+    sites.switch_to("Lisboa", &mut site).unwrap();
+    site.warehouse.add_item(Item::new(9, "Local".to_string(), 1, vec![], None)).unwrap();
+    let locations = sites.receive(transfer, &mut site.warehouse).unwrap();
+
+    let received = site.warehouse.item_at(&locations[0]).unwrap();
+    assert_eq!((received.num_id, received.item_quantity), (3, 2));
+    let ids: Vec<u64> = site.warehouse.grouped_items().iter().map(|(itm, _)| itm.record_id).collect();
+    assert_eq!(ids.len(), 2);
+    assert_ne!(ids[0], ids[1]);
+    assert_eq!(sites.transfers[0].status, TransferStatus::Received);
+}
+
+#[test]
+fn the_registry_and_transfers_in_transit_survive_a_save() {
+    let mut porto = Warehouse::new((3, 3, 3, 3));
+    porto.add_item(Item::new(1, "Caixa".to_string(), 10, vec![], None)).unwrap();
+    let mut sites = SiteRegistry::new("Porto");
+    sites.add_site("Lisboa", (2, 2, 2, 2), &porto).unwrap();
+    let transfer = sites.ship("Lisboa", 1, 4, &mut porto).unwrap();
+
+    let path = std::env::temp_dir().join(format!("rust_projeto_sites_{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+    sites.save(path).unwrap();
+    let mut loaded = SiteRegistry::load(path).unwrap().unwrap();
+    let _ = std::fs::remove_file(path);
+    assert!(SiteRegistry::load(path).unwrap().is_none());
+
+    assert_eq!(loaded.active(), "Porto");
+    assert_eq!(loaded.names(), vec!["Lisboa".to_string(), "Porto".to_string()]);
+    let stock = loaded.stock_by_product(&porto);
+    assert_eq!((stock[0].in_transit, stock[0].total()), (4, 10));
+
+    let mut site = Site::new(porto);
+    loaded.switch_to("Lisboa", &mut site).unwrap();
+    loaded.receive(transfer, &mut site.warehouse).unwrap();
+    assert_eq!(site.warehouse.search_by_id(1).on_hand, 4);
+}
+
+#[test]
+fn an_exported_site_keeps_its_orders_receipts_and_rules() {
+    let mut site = Site::new(Warehouse::new((3, 3, 3, 3)));
+    site.warehouse.add_item(Item::new(1, "Caixa".to_string(), 10, vec![], None)).unwrap();
+    site.orders.create_order("Cliente".to_string(), vec![OrderLine { num_id: 1, quantity: 2 }]).unwrap();
+    site.inbound.create_purchase_order("PO-1".to_string(), "Fornecedor".to_string(), None).unwrap();
+    site.replenishment.set_rule(1, ReplenishmentScope::Total, 5, 20).unwrap();
+
+    let loaded = Site::from_json(&site.to_json().unwrap()).unwrap();
+    assert_eq!(loaded.warehouse.search_by_id(1).on_hand, 10);
+    assert_eq!(loaded.orders.orders.len(), 1);
+    assert_eq!(loaded.inbound.purchase_orders[0].reference, "PO-1");
+    assert_eq!(loaded.replenishment.rules[0].max, 20);
+}
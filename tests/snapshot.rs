@@ -0,0 +1,83 @@
+use chrono::NaiveDate;
+use rust_projeto::warehouse::item::{HandlingAttribute, Item};
+use rust_projeto::warehouse::movement::AdjustmentReason;
+use rust_projeto::warehouse::warehouse::{Location, Warehouse};
+
+fn cells(warehouse: &Warehouse) -> Vec<(Location, Item)> {
+    let mut cells: Vec<_> = warehouse.items().map(|(loc, itm)| (loc.clone(), itm.clone())).collect();
+    cells.sort_by(|(l1, _), (l2, _)| l1.cmp(l2));
+    cells
+}
+
+fn round_trip(warehouse: &Warehouse) -> Warehouse {
+    Warehouse::from_json(&warehouse.to_json().unwrap()).unwrap()
+}
+
+fn assert_same_stock(original: &Warehouse, loaded: &Warehouse) {
+    assert_eq!(cells(loaded), cells(original));
+    assert_eq!(loaded.next_record_id(), original.next_record_id());
+    let record_ids = |w: &Warehouse| {
+        let mut ids: Vec<u64> = w.grouped_items().iter().map(|(itm, _)| itm.record_id).collect();
+        ids.sort();
+        ids
+    };
+    assert_eq!(record_ids(loaded), record_ids(original));
+}
+
+fn stocked_warehouse() -> Warehouse {
+    let mut warehouse = Warehouse::new((5, 5, 5, 5));
+    let oversized = HandlingAttribute::Oversized { required_zones: 2, required_levels: 2, required_shelves: 1 };
+    warehouse.add_item(Item::new(1, "Palete".to_string(), 1, vec![oversized], None)).unwrap();
+
+    let mut tracked = Item::new(2, "Sensor".to_string(), 2, vec![HandlingAttribute::Fragile], NaiveDate::from_ymd_opt(2030, 5, 1));
+    tracked.lot = Some("L-1".to_string());
+    tracked.serials = vec!["S1".to_string(), "S2".to_string()];
+    tracked.unit_cost = 9.75;
+    warehouse.add_item(tracked).unwrap();
+
+    let boxes = warehouse.add_item(Item::new(3, "Caixa".to_string(), 40, vec![], None)).unwrap();
+    warehouse.adjust_quantity(&boxes[0], 36, AdjustmentReason::Damaged).unwrap();
+    warehouse.reserve(&boxes[0], 12, "ENC-1".to_string(), NaiveDate::from_ymd_opt(2030, 1, 1).unwrap()).unwrap();
+    warehouse.packaging.define(3, "un".to_string(), 12, 10).unwrap();
+    warehouse
+}
+
+#[test]
+fn oversized_records_survive_a_round_trip() {
+    let warehouse = stocked_warehouse();
+    let loaded = round_trip(&warehouse);
+    assert_same_stock(&warehouse, &loaded);
+
+    let palete = loaded.search_locations_by_id(1);
+    assert_eq!(palete.len(), 1);
+    assert_eq!(palete[0].1.len(), 4);
+}
+
+#[test]
+fn reservations_packaging_and_movements_survive_a_round_trip() {
+    let warehouse = stocked_warehouse();
+    let loaded = round_trip(&warehouse);
+    assert_same_stock(&warehouse, &loaded);
+
+    assert_eq!(loaded.reservations.len(), 1);
+    assert_eq!(loaded.reservations[0].quantity, 12);
+    assert_eq!(loaded.search_by_id(3).available, 24);
+
+    let packaging = loaded.packaging.packaging(3);
+    assert_eq!((packaging.units_per_case, packaging.cases_per_pallet), (12, 10));
+
+    assert_eq!(loaded.movements.len(), warehouse.movements.len());
+    for (a, b) in loaded.movements.iter().zip(&warehouse.movements) {
+        assert_eq!((a.kind, a.record_id, a.quantity_change, &a.from, &a.to, a.reason), (b.kind, b.record_id, b.quantity_change, &b.from, &b.to, b.reason));
+    }
+}
+
+#[test]
+fn records_stored_after_loading_get_new_ids() {
+    let warehouse = stocked_warehouse();
+    let mut loaded = round_trip(&warehouse);
+    let locations = loaded.add_item(Item::new(4, "Novo".to_string(), 1, vec![], None)).unwrap();
+    let record_id = loaded.item_at(&locations[0]).unwrap().record_id;
+    assert_eq!(record_id, warehouse.next_record_id());
+    assert_eq!(loaded.grouped_items().len(), warehouse.grouped_items().len() + 1);
+}
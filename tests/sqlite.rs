@@ -0,0 +1,67 @@
+#![cfg(feature = "sqlite")]
+
+use chrono::NaiveDate;
+use rust_projeto::warehouse::item::Item;
+use rust_projeto::warehouse::sqlite::SqliteStorage;
+use rust_projeto::warehouse::warehouse::Warehouse;
+use std::fs;
+
+fn database(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("rust_projeto_{}_{}.db", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path.to_string_lossy().into_owned()
+}
+
+fn movement_rows(path: &str) -> i64 {
+    rusqlite::Connection::open(path).unwrap().query_row("SELECT COUNT(*) FROM movements", [], |row| row.get(0)).unwrap()
+}
+
+#[test]
+fn configuration_and_reservations_are_written_when_they_change() {
+    let path = database("settings");
+    let mut warehouse = SqliteStorage::open_warehouse(&path, &Warehouse::new((4, 4, 4, 4))).unwrap();
+    let locations = warehouse.add_item(Item::new(1, "Caixa".to_string(), 10, vec![], None)).unwrap();
+    warehouse.reserve(&locations[0], 4, "ENC-1".to_string(), NaiveDate::from_ymd_opt(2030, 1, 1).unwrap()).unwrap();
+    warehouse.storage.save_state(&warehouse).unwrap();
+
+    warehouse.layout.row_spacing = 7.5;
+    warehouse.packaging.define(1, "un".to_string(), 3, 2).unwrap();
+    warehouse.mark_changed();
+    warehouse.storage.save_state(&warehouse).unwrap();
+    drop(warehouse);
+
+    // The snapshot given to a database that already holds a warehouse is ignored
+    let loaded = SqliteStorage::open_warehouse(&path, &Warehouse::new((1, 1, 1, 1))).unwrap();
+    assert_eq!(loaded.max_dimensions, (4, 4, 4, 4));
+    assert_eq!(loaded.reservations.len(), 1);
+    assert_eq!(loaded.layout.row_spacing, 7.5);
+    assert_eq!(loaded.packaging.packaging(1).units_per_case, 3);
+    assert_eq!(loaded.grouped_items().len(), 1);
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn saving_appends_only_new_movements() {
+    let path = database("history");
+    let mut initial = Warehouse::new((4, 4, 4, 4));
+    initial.add_item(Item::new(1, "Caixa".to_string(), 10, vec![], None)).unwrap();
+    let mut warehouse = SqliteStorage::open_warehouse(&path, &initial).unwrap();
+    assert_eq!(movement_rows(&path), 1);
+    let (_, locations) = warehouse.grouped_items().remove(0);
+    warehouse.remove_quantity(&locations[0], 3).unwrap();
+    warehouse.storage.save_state(&warehouse).unwrap();
+    warehouse.storage.save_state(&warehouse).unwrap();
+    assert_eq!(movement_rows(&path), 2);
+
+    // A history that does not continue the stored one replaces it
+    warehouse.movements.remove(0);
+    warehouse.mark_changed();
+    warehouse.storage.save_state(&warehouse).unwrap();
+    assert_eq!(movement_rows(&path), 1);
+    drop(warehouse);
+
+    let loaded = SqliteStorage::open_warehouse(&path, &initial).unwrap();
+    assert_eq!(loaded.movements.len(), 1);
+    assert_eq!(loaded.movements[0].quantity_change, -3);
+    let _ = fs::remove_file(&path);
+}
@@ -0,0 +1,88 @@
+use rust_projeto::warehouse::item::{HandlingAttribute, Item};
+use rust_projeto::warehouse::storage::{FileStorage, StorageBackend};
+use rust_projeto::warehouse::warehouse::{Location, Warehouse};
+use std::fs;
+
+fn temp_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("rust_projeto_{}_{}", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path.to_string_lossy().into_owned()
+}
+
+fn cells<S: StorageBackend>(warehouse: &Warehouse<S>) -> Vec<(Location, Item)> {
+    let mut cells: Vec<_> = warehouse.items().map(|(loc, itm)| (loc.clone(), itm.clone())).collect();
+    cells.sort_by(|(l1, _), (l2, _)| l1.cmp(l2));
+    cells
+}
+
+// Stores a few records (one of them on several cells), takes one out, and returns the cells left
+fn fill<S: StorageBackend>(warehouse: &mut Warehouse<S>) -> Vec<(Location, Item)> {
+    let oversized = HandlingAttribute::Oversized { required_zones: 2, required_levels: 1, required_shelves: 1 };
+    warehouse.add_item(Item::new(1, "Palete".to_string(), 1, vec![oversized], None)).unwrap();
+    let boxes = warehouse.add_item(Item::new(2, "Caixa".to_string(), 40, vec![], None)).unwrap();
+    warehouse.add_item(Item::new(3, "Saco".to_string(), 5, vec![], None)).unwrap();
+    warehouse.remove_quantity(&boxes[0], 15).unwrap();
+    warehouse.remove_item(&warehouse.search_locations_by_id(3)[0].1[0].clone()).unwrap();
+    cells(warehouse)
+}
+
+fn assert_reopened<S: StorageBackend>(mut reopened: Warehouse<S>, stored: &[(Location, Item)]) {
+    assert_eq!(cells(&reopened), stored);
+    let max_record_id = stored.iter().map(|(_, itm)| itm.record_id).max().unwrap();
+    assert_eq!(reopened.next_record_id(), max_record_id + 1);
+
+    let locations = reopened.add_item(Item::new(4, "Rolo".to_string(), 2, vec![], None)).unwrap();
+    let record_id = reopened.item_at(&locations[0]).unwrap().record_id;
+    assert!(stored.iter().all(|(_, itm)| itm.record_id != record_id));
+}
+
+#[test]
+fn file_storage_keeps_its_cells_and_ids_across_reopening() {
+    let path = temp_path("cells.json");
+    let mut warehouse = Warehouse::with_storage(FileStorage::open(&path).unwrap(), (4, 4, 4, 4));
+    let stored = fill(&mut warehouse);
+    let usage = warehouse.storage.usage(&stored[0].0);
+    drop(warehouse);
+
+    let reopened = Warehouse::with_storage(FileStorage::open(&path).unwrap(), (4, 4, 4, 4));
+    assert_eq!(reopened.storage.usage(&stored[0].0), usage);
+    assert_reopened(reopened, &stored);
+    let _ = fs::remove_file(&path);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_storage_keeps_its_cells_and_ids_across_reopening() {
+    use rust_projeto::warehouse::sqlite::SqliteStorage;
+
+    let path = temp_path("cells.db");
+    let mut warehouse = Warehouse::with_storage(SqliteStorage::open(&path).unwrap(), (4, 4, 4, 4));
+    let stored = fill(&mut warehouse);
+    let usage = warehouse.storage.usage(&stored[0].0);
+    drop(warehouse);
+
+    // The emptied record is gone from the tables, not just from its cells
+    let records: i64 = rusqlite::Connection::open(&path).unwrap()
+        .query_row("SELECT COUNT(*) FROM stock_records", [], |row| row.get(0)).unwrap();
+    assert_eq!(records, 2);
+
+    let reopened = Warehouse::with_storage(SqliteStorage::open(&path).unwrap(), (4, 4, 4, 4));
+    assert_eq!(reopened.storage.usage(&stored[0].0), usage);
+    assert_reopened(reopened, &stored);
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn a_snapshot_moved_onto_a_backend_keeps_its_history_and_counters() {
+    let path = temp_path("snapshot_cells.json");
+    let mut warehouse = Warehouse::new((4, 4, 4, 4));
+    warehouse.add_item(Item::new(1, "Caixa".to_string(), 10, vec![], None)).unwrap();
+    let next_record_id = warehouse.next_record_id();
+    let movements = warehouse.movements.len();
+
+    let moved = warehouse.with_cells(FileStorage::open(&path).unwrap());
+    assert!(moved.storage.is_empty());
+    assert_eq!(moved.next_record_id(), next_record_id);
+    assert_eq!(moved.movements.len(), movements);
+    let _ = fs::remove_file(&path);
+}
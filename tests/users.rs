@@ -0,0 +1,21 @@
+use rust_projeto::warehouse::users::{Role, UserDirectory};
+use serde_json::Value;
+
+fn stored(users: &UserDirectory) -> Vec<Value> {
+    serde_json::from_value(serde_json::to_value(users.users()).unwrap()).unwrap()
+}
+
+#[test]
+fn new_passwords_are_hashed_with_argon2id_and_a_random_salt() {
+    let mut users = UserDirectory::default();
+    users.add_user("ana", "segredo", Role::Admin).unwrap();
+    users.add_user("rui", "segredo", Role::Viewer).unwrap();
+
+    let stored = stored(&users);
+    assert!(stored[0]["password_hash"].as_str().unwrap().starts_with("argon2id$"));
+    assert_ne!(stored[0]["salt"], stored[1]["salt"]);
+    assert_ne!(stored[0]["password_hash"], stored[1]["password_hash"]);
+
+    assert_eq!(users.authenticate("ana", "segredo").unwrap().role, Role::Admin);
+    assert!(users.authenticate("ana", "errada").is_err());
+}
@@ -0,0 +1,31 @@
+use rust_projeto::warehouse::item::Item;
+use rust_projeto::warehouse::valuation::{unit_cost, valuation_report, ValuationMethod};
+use rust_projeto::warehouse::warehouse::Warehouse;
+
+fn costed(quantity: u32, cost: f64) -> Item {
+    let mut item = Item::new(1, "Cabo".to_string(), quantity, vec![], None);
+    item.unit_cost = cost;
+    item
+}
+
+#[test]
+fn stock_without_history_is_valued_at_its_own_cost() {
+    let mut warehouse = Warehouse::new((5, 5, 5, 5));
+    warehouse.add_item(costed(4, 2.5)).unwrap();
+    warehouse.add_item(costed(6, 5.0)).unwrap();
+    warehouse.movements.clear();
+
+    for method in ValuationMethod::ALL {
+        let value: f64 = valuation_report(&warehouse, method).iter().map(|l| l.value).sum();
+        assert!((value - 40.0).abs() < 1e-9, "{}: {}", method.label(), value);
+    }
+}
+
+#[test]
+fn stock_with_and_without_history_is_valued_together() {
+    let mut warehouse = Warehouse::new((5, 5, 5, 5));
+    warehouse.add_item(costed(4, 2.0)).unwrap();
+    warehouse.movements.clear();
+    warehouse.add_item(costed(4, 4.0)).unwrap();
+    assert!((unit_cost(&warehouse, 1, 8, ValuationMethod::WeightedAverage) - 3.0).abs() < 1e-9);
+}
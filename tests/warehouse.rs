@@ -0,0 +1,75 @@
+use chrono::NaiveDate;
+use rust_projeto::warehouse::hazard::HazardClass;
+use rust_projeto::warehouse::item::{HandlingAttribute, Item};
+use rust_projeto::warehouse::movement::AdjustmentReason;
+use rust_projeto::warehouse::warehouse::{Location, Warehouse};
+
+fn item(num_id: u32, quantity: u32, attributes: Vec<HandlingAttribute>) -> Item {
+    Item::new(num_id, format!("Produto {}", num_id), quantity, attributes, None)
+}
+
+#[test]
+fn hazardous_items_may_span_several_levels() {
+    let mut warehouse = Warehouse::new((5, 5, 5, 5));
+    let oversized = HandlingAttribute::Oversized { required_zones: 1, required_levels: 2, required_shelves: 1 };
+    let locations = warehouse.add_item(item(1, 1, vec![HandlingAttribute::Hazardous(HazardClass::Flammable), oversized])).unwrap();
+    assert_eq!(locations.len(), 2);
+    assert_ne!(locations[0].level, locations[1].level);
+}
+
+#[test]
+fn adjusting_to_zero_frees_every_cell_of_the_record() {
+    let mut warehouse = Warehouse::new((5, 5, 5, 5));
+    let oversized = HandlingAttribute::Oversized { required_zones: 2, required_levels: 1, required_shelves: 1 };
+    let locations = warehouse.add_item(item(1, 4, vec![oversized])).unwrap();
+    warehouse.adjust_quantity(&locations[0], 0, AdjustmentReason::Lost).unwrap();
+    assert!(locations.iter().all(|loc| warehouse.item_at(loc).is_none()));
+    assert_eq!(warehouse.search_by_id(1).on_hand, 0);
+    assert_eq!(warehouse.movements.last().unwrap().quantity_change, -4);
+}
+
+#[test]
+fn moves_must_keep_the_footprint_as_one_block() {
+    let mut warehouse = Warehouse::new((5, 5, 5, 5));
+    let oversized = HandlingAttribute::Oversized { required_zones: 2, required_levels: 1, required_shelves: 1 };
+    let from = warehouse.add_item(item(1, 1, vec![oversized])).unwrap();
+    let record_id = warehouse.item_at(&from[0]).unwrap().record_id;
+    let cell = |row, shelf, level, zone| Location { row, shelf, level, zone };
+
+    assert!(warehouse.move_record(record_id, vec![cell(2, 0, 0, 0), cell(2, 0, 0, 0)]).is_err());
+    assert!(warehouse.move_record(record_id, vec![cell(2, 0, 0, 0), cell(2, 0, 0, 2)]).is_err());
+    assert!(warehouse.move_record(record_id, vec![cell(2, 0, 0, 0), cell(3, 0, 0, 1)]).is_err());
+    assert!(warehouse.move_record(record_id, vec![cell(2, 0, 0, 0), cell(2, 0, 1, 0)]).is_err());
+    assert_eq!(warehouse.record_locations(record_id), from);
+
+    warehouse.move_record(record_id, vec![cell(2, 1, 0, 3), cell(2, 1, 0, 2)]).unwrap();
+    assert_eq!(warehouse.record_locations(record_id), vec![cell(2, 1, 0, 2), cell(2, 1, 0, 3)]);
+}
+
+#[test]
+fn moves_respect_segregation() {
+    let mut warehouse = Warehouse::new((5, 5, 5, 5));
+    let food = warehouse.add_item(item(1, 1, vec![HandlingAttribute::Food])).unwrap();
+    let toxic = warehouse.add_item(item(2, 1, vec![HandlingAttribute::Hazardous(HazardClass::Toxic)])).unwrap();
+    let record_id = warehouse.item_at(&toxic[0]).unwrap().record_id;
+    let next_to_food = Location { zone: food[0].zone + 1, ..food[0].clone() };
+    assert_ne!(toxic[0], next_to_food);
+
+    assert!(warehouse.move_record(record_id, vec![next_to_food]).is_err());
+    assert_eq!(warehouse.record_locations(record_id), toxic);
+    assert!(warehouse.validate_segregation().is_empty());
+}
+
+#[test]
+fn only_fragile_records_are_reported_as_expiring() {
+    let mut warehouse = Warehouse::new((5, 5, 5, 5));
+    let date = |day| NaiveDate::from_ymd_opt(2030, 1, day);
+    warehouse.add_item(Item::new(1, "Vidro".to_string(), 1, vec![HandlingAttribute::Fragile], date(2))).unwrap();
+    warehouse.add_item(Item::new(2, "Caixa".to_string(), 1, vec![], date(2))).unwrap();
+    warehouse.add_item(Item::new(3, "Copo".to_string(), 1, vec![HandlingAttribute::Fragile], date(20))).unwrap();
+
+    let expiring = warehouse.find_expiring_items_by_date(date(1).unwrap());
+    assert_eq!(expiring.len(), 1);
+    assert_eq!((expiring[0].0.num_id, expiring[0].1.as_str()), (1, "Expira em 1 dias"));
+    assert_eq!(warehouse.find_expiring_items_by_date(date(3).unwrap())[0].1, "Expirado");
+}